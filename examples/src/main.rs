@@ -1,20 +1,16 @@
 extern crate mux;
-
-extern crate byteorder;
 extern crate rand;
 
 use mux::Rmsg;
+use mux::client::Session;
 
-use std::net::TcpStream;
 use std::sync::Arc;
 
 use std::thread;
 use std::time::Duration;
 
-fn test_session(socket: TcpStream) {
-    /*
-
-    let session = Arc::new(MuxSession::new(socket).unwrap());
+fn test_session(session: Session) {
+    let session = Arc::new(session);
 
     let res = session.ping().unwrap();
     println!("Ping time: {:?}", res);
@@ -28,9 +24,9 @@ fn test_session(socket: TcpStream) {
             for _ in 0..iters {
                 if rand::random::<u8>() > 64 {
                     let b = format!("Hello, world: {}", id).into_bytes();
-                    let frame = mux::Tdispatch::basic_("/foo".to_string(), b);
+                    let frame = mux::Tdispatch::new("/foo".to_string(), b);
 
-                    let msg = session.dispatch(&frame).unwrap();
+                    let msg = session.dispatch(frame).unwrap();
                     if let Rmsg::Ok(body) = msg.msg {
                         let _ = String::from_utf8(body).unwrap();
                     } else {
@@ -51,15 +47,13 @@ fn test_session(socket: TcpStream) {
     }
 
     println!("Finished. Average ping: {:?}", total_ping/threadc);
-    */
 }
 
 fn main() {
-  let socket = TcpStream::connect(("localhost", 9000)).unwrap();
+  let session = Session::connect(("localhost", 9000)).unwrap();
+  println!("Negotiated mux version {}.", session.version());
 
-  println!("Testing TRequest frame.");
-  //test_trequest(&mut socket);
-  test_session(socket);
+  test_session(session);
 }
 
 #[cfg(test)]
@@ -0,0 +1,407 @@
+//! Print the mux messages of a raw TCP stream capture.
+//!
+//! The capture, read from a file or stdin, is the byte stream of one
+//! direction of a mux session, such as a TCP stream exported by a packet
+//! analyser. Each message is printed with its type, tag, size and fields,
+//! previewing the payloads in hex and UTF8. Frames of unknown type are
+//! printed with their raw body. The fragments of a message are printed as
+//! they come, the final one as the reassembled message.
+//!
+//! A message that fails to decode ends the dump unless `--resync` is given,
+//! in which case the bytes up to the next length prefix of a message that
+//! decodes are skipped. The exit status is 1 if any of the capture failed to
+//! decode.
+
+extern crate byteorder;
+extern crate mux;
+
+use std::env;
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process;
+
+use byteorder::{BigEndian, ByteOrder};
+
+use mux::{types, Contexts, Message, MessageFrame, Rmsg};
+use mux::codec::{self, size, DecoderConfig, Fragment, Reassembler};
+
+const USAGE: &str = "\
+usage: mux-dump [--json] [--resync] [FILE]
+
+Print the mux messages of a raw TCP stream capture read from FILE, or stdin.
+
+options:
+    --json      print the messages as JSON objects, one per line
+    --resync    skip to the next length prefix on decode errors
+    -h, --help  print this help";
+
+// bytes of a payload previewed
+const PREVIEW_SIZE: usize = 32;
+
+struct Options {
+    json: bool,
+    resync: bool,
+    path: Option<String>,
+}
+
+// a frame of the capture
+enum Frame {
+    Message(Message),
+    // a fragment of a message completed by a later frame
+    Fragment(Fragment),
+}
+
+// value of a field of a dumped message
+enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+    Bytes(Vec<u8>),
+    Pairs(Vec<(String, Value)>),
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("mux-dump: {}\n\n{}", msg, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let mut input = Vec::new();
+    let read = match options.path {
+        Some(ref path) => File::open(path).and_then(|mut file| file.read_to_end(&mut input)),
+        None => io::stdin().read_to_end(&mut input),
+    };
+    if let Err(e) = read {
+        eprintln!("mux-dump: {}", e);
+        process::exit(2);
+    }
+
+    let stdout = io::stdout();
+    match dump(&input, &options, &mut stdout.lock()) {
+        Ok(true) => (),
+        Ok(false) => process::exit(1),
+        // most likely a closed pipe
+        Err(_) => process::exit(1),
+    }
+}
+
+fn parse_args<I: Iterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut options = Options { json: false, resync: false, path: None };
+    for arg in args {
+        match arg.as_str() {
+            "--json" => options.json = true,
+            "--resync" => options.resync = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "-" if options.path.is_none() => (),
+            flag if flag.starts_with('-') => return Err(format!("unknown option: {}", flag)),
+            _ if options.path.is_some() => return Err("more than one FILE".to_string()),
+            _ => options.path = Some(arg),
+        }
+    }
+    Ok(options)
+}
+
+// dump the messages of `input`, returning whether all of it decoded
+fn dump<W: Write>(input: &[u8], options: &Options, out: &mut W) -> io::Result<bool> {
+    let config = DecoderConfig { allow_unknown_frames: true, ..DecoderConfig::default() };
+    let mut reassembler = Reassembler::with_config(config);
+    let mut clean = true;
+    let mut offset = 0;
+    while offset < input.len() {
+        match decode_at(input, offset, &config, &mut reassembler) {
+            Ok((frame, len)) => {
+                let fields = match frame {
+                    Frame::Message(ref msg) => fields(offset, msg),
+                    Frame::Fragment(ref fragment) => fragment_fields(offset, fragment),
+                };
+                let line = if options.json { json(&fields) } else { text(&fields) };
+                writeln!(out, "{}", line)?;
+                offset += len;
+            }
+            Err(e) => {
+                clean = false;
+                eprintln!("mux-dump: decode error at offset {}: {}", offset, e);
+                if !options.resync {
+                    break;
+                }
+
+                let next = (offset + 1..input.len())
+                    .find(|&at| starts_frame(input, at, &config))
+                    .unwrap_or(input.len());
+                eprintln!("mux-dump: skipped {} bytes to offset {}", next - offset, next);
+                offset = next;
+            }
+        }
+    }
+    Ok(clean)
+}
+
+// decode the frame starting at `offset`, and its length
+fn decode_at(input: &[u8], offset: usize, config: &DecoderConfig, reassembler: &mut Reassembler)
+    -> codec::Result<(Frame, usize)>
+{
+    let mut reader = &input[offset..];
+    let fragment = codec::read_fragment_with(&mut reader, config)?;
+    let len = input.len() - offset - reader.len();
+
+    let frame = match reassembler.push(fragment.clone())? {
+        Some(msg) => Frame::Message(msg),
+        None => Frame::Fragment(fragment),
+    };
+    Ok((frame, len))
+}
+
+// whether a frame that decodes starts at `offset`. Only offsets with a length
+// prefix that fits the input and the frame size limit are decoded.
+fn starts_frame(input: &[u8], offset: usize, config: &DecoderConfig) -> bool {
+    let rest = &input[offset..];
+    if rest.len() < 4 {
+        return false;
+    }
+    let size = BigEndian::read_i32(rest);
+    if size < 4 || size as usize > rest.len() - 4 || size as usize > config.max_frame_size {
+        return false;
+    }
+    decode_at(input, offset, config, &mut Reassembler::with_config(*config)).is_ok()
+}
+
+fn fields(offset: usize, msg: &Message) -> Vec<(&'static str, Value)> {
+    let mut fields = vec![
+        ("offset", Value::Int(offset as i64)),
+        ("type", Value::Str(type_name(msg.frame.frame_id()).to_string())),
+        ("tag", Value::Int(msg.tag.id as i64)),
+        ("end", Value::Bool(msg.tag.end)),
+        ("size", Value::Int(size::frame_size(&msg.frame) as i64)),
+    ];
+
+    match msg.frame {
+        MessageFrame::Treq(ref treq) => {
+            let headers = treq.headers.iter()
+                .map(|(key, value)| (key.to_string(), Value::Bytes(value.to_vec())))
+                .collect();
+            fields.push(("headers", Value::Pairs(headers)));
+            fields.push(("body", Value::Bytes(treq.body.to_vec())));
+        }
+        MessageFrame::Rreq(ref rmsg) => rmsg_fields(rmsg, &mut fields),
+        MessageFrame::Tdispatch(ref tdispatch) => {
+            fields.push(("contexts", contexts(&tdispatch.contexts)));
+            fields.push(("dest", Value::Str(tdispatch.dest.clone())));
+            fields.push(("dtab", Value::Str(tdispatch.dtab.to_string())));
+            fields.push(("body", Value::Bytes(tdispatch.body.to_vec())));
+        }
+        MessageFrame::Rdispatch(ref rdispatch) => {
+            fields.push(("contexts", contexts(&rdispatch.contexts)));
+            rmsg_fields(&rdispatch.msg, &mut fields);
+        }
+        MessageFrame::Tinit(ref init) | MessageFrame::Rinit(ref init) => {
+            fields.push(("version", Value::Int(init.version as i64)));
+            fields.push(("headers", contexts(&init.headers)));
+        }
+        MessageFrame::Tdiscarded(ref tdiscarded) => {
+            fields.push(("id", Value::Int(tdiscarded.id as i64)));
+            fields.push(("reason", Value::Str(tdiscarded.msg.clone())));
+        }
+        MessageFrame::Tlease(ref tlease) => match tlease.expires_in() {
+            Some(duration) => fields.push(("lease_ms", Value::Int(duration.as_millis() as i64))),
+            None => fields.push(("lease", Value::Str("infinite".to_string()))),
+        },
+        MessageFrame::Rerr(ref rerr) => fields.push(("error", Value::Str(rerr.msg.clone()))),
+        MessageFrame::Unknown { tpe, ref body } => {
+            fields.push(("type_id", Value::Int(tpe as i64)));
+            fields.push(("body", Value::Bytes(body.to_vec())));
+        }
+        MessageFrame::Tdrain |
+        MessageFrame::Rdrain |
+        MessageFrame::Tping |
+        MessageFrame::Rping |
+        MessageFrame::Rdiscarded => (),
+    }
+    fields
+}
+
+fn fragment_fields(offset: usize, fragment: &Fragment) -> Vec<(&'static str, Value)> {
+    vec![
+        ("offset", Value::Int(offset as i64)),
+        ("type", Value::Str(type_name(fragment.tpe).to_string())),
+        ("tag", Value::Int(fragment.tag.id as i64)),
+        ("end", Value::Bool(fragment.tag.end)),
+        ("size", Value::Int(fragment.body.len() as i64)),
+        ("fragment", Value::Bytes(fragment.body.clone())),
+    ]
+}
+
+fn rmsg_fields(rmsg: &Rmsg, fields: &mut Vec<(&'static str, Value)>) {
+    match *rmsg {
+        Rmsg::Ok(ref body) => fields.push(("body", Value::Bytes(body.to_vec()))),
+        Rmsg::Error(ref msg) => fields.push(("error", Value::Str(msg.clone()))),
+        Rmsg::Nack(ref msg) => fields.push(("nack", Value::Str(msg.clone()))),
+    }
+}
+
+// context keys are names, so they're printed as text
+fn contexts(contexts: &Contexts) -> Value {
+    Value::Pairs(contexts.iter()
+        .map(|(key, value)| (String::from_utf8_lossy(key).into_owned(), Value::Bytes(value.to_vec())))
+        .collect())
+}
+
+fn type_name(tpe: i8) -> &'static str {
+    match tpe {
+        types::TREQ => "Treq",
+        types::RREQ => "Rreq",
+        types::TDISPATCH => "Tdispatch",
+        types::RDISPATCH => "Rdispatch",
+        types::TINIT => "Tinit",
+        types::RINIT => "Rinit",
+        types::TDRAIN => "Tdrain",
+        types::RDRAIN => "Rdrain",
+        types::TPING => "Tping",
+        types::RPING => "Rping",
+        types::TDISCARDED | types::BAD_TDISCARDED => "Tdiscarded",
+        types::RDISCARDED => "Rdiscarded",
+        types::TLEASE => "Tlease",
+        types::RERR => "Rerr",
+        _ => "Unknown",
+    }
+}
+
+// hex and printable text of the first `PREVIEW_SIZE` bytes
+fn preview(bytes: &[u8]) -> (String, String) {
+    let bytes = &bytes[..bytes.len().min(PREVIEW_SIZE)];
+    let mut hex = String::with_capacity(2 * bytes.len());
+    for byte in bytes {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    let utf8 = String::from_utf8_lossy(bytes).chars()
+        .map(|c| if c.is_control() { '.' } else { c })
+        .collect();
+    (hex, utf8)
+}
+
+// the header fields on the first line, then a line per other field
+fn text(fields: &[(&'static str, Value)]) -> String {
+    let mut line = String::new();
+    for (i, (name, value)) in fields.iter().enumerate() {
+        match i {
+            0 => line.push('@'),
+            1 => line.push(' '),
+            2..=4 => {
+                line.push(' ');
+                line.push_str(name);
+                line.push('=');
+            }
+            _ => {
+                line.push_str("\n  ");
+                line.push_str(name);
+                line.push(':');
+                // the pairs are listed on the following lines
+                match *value {
+                    Value::Pairs(ref pairs) if !pairs.is_empty() => (),
+                    _ => line.push(' '),
+                }
+            }
+        }
+        text_value(&mut line, value);
+    }
+    line
+}
+
+fn text_value(line: &mut String, value: &Value) {
+    match *value {
+        Value::Str(ref s) => line.push_str(s),
+        Value::Int(i) => {
+            let _ = write!(line, "{}", i);
+        }
+        Value::Bool(b) => {
+            let _ = write!(line, "{}", b);
+        }
+        Value::Bytes(ref bytes) => {
+            let (hex, utf8) = preview(bytes);
+            let more = if bytes.len() > PREVIEW_SIZE { ".." } else { "" };
+            let _ = write!(line, "{} bytes {}{} |{}{}|", bytes.len(), hex, more, utf8, more);
+        }
+        Value::Pairs(ref pairs) if pairs.is_empty() => line.push_str("none"),
+        Value::Pairs(ref pairs) => {
+            for (key, value) in pairs {
+                line.push_str("\n    ");
+                line.push_str(key);
+                line.push_str(" => ");
+                text_value(line, value);
+            }
+        }
+    }
+}
+
+// an object of the fields, payloads being objects of their length and preview
+fn json(fields: &[(&'static str, Value)]) -> String {
+    let mut line = String::from("{");
+    for (i, (name, value)) in fields.iter().enumerate() {
+        if i > 0 {
+            line.push(',');
+        }
+        json_str(&mut line, name);
+        line.push(':');
+        json_value(&mut line, value);
+    }
+    line.push('}');
+    line
+}
+
+fn json_value(line: &mut String, value: &Value) {
+    match *value {
+        Value::Str(ref s) => json_str(line, s),
+        Value::Int(i) => {
+            let _ = write!(line, "{}", i);
+        }
+        Value::Bool(b) => {
+            let _ = write!(line, "{}", b);
+        }
+        Value::Bytes(ref bytes) => {
+            let (hex, utf8) = preview(bytes);
+            let _ = write!(line, "{{\"len\":{},\"hex\":", bytes.len());
+            json_str(line, &hex);
+            line.push_str(",\"utf8\":");
+            json_str(line, &utf8);
+            line.push('}');
+        }
+        Value::Pairs(ref pairs) => {
+            line.push('[');
+            for (i, (key, value)) in pairs.iter().enumerate() {
+                if i > 0 {
+                    line.push(',');
+                }
+                line.push('[');
+                json_str(line, key);
+                line.push(',');
+                json_value(line, value);
+                line.push(']');
+            }
+            line.push(']');
+        }
+    }
+}
+
+fn json_str(line: &mut String, s: &str) {
+    line.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            '\n' => line.push_str("\\n"),
+            '\r' => line.push_str("\\r"),
+            '\t' => line.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(line, "\\u{:04x}", c as u32);
+            }
+            c => line.push(c),
+        }
+    }
+    line.push('"');
+}
@@ -0,0 +1,252 @@
+//! Borrowed views of the mux message types.
+//!
+//! These mirror the owned types but reference the bytes of the frame they
+//! were decoded from rather than copying them, see `codec::decode_message_ref`.
+
+use super::*;
+
+/// Borrowed `Contexts`.
+pub type ContextsRef<'a> = Vec<(&'a [u8], &'a [u8])>;
+
+/// Borrowed `Headers`.
+pub type HeadersRef<'a> = Vec<(u8, &'a [u8])>;
+
+/// Borrowed representation of an entire mux packet.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MessageRef<'a> {
+    /// Identification and termination information about the associated stream.
+    pub tag: Tag,
+    /// Payload of the message.
+    pub frame: MessageFrameRef<'a>,
+}
+
+/// Borrowed `MessageFrame`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum MessageFrameRef<'a> {
+    Treq(TreqRef<'a>),
+    Rreq(RmsgRef<'a>),
+    Tdispatch(TdispatchRef<'a>),
+    Rdispatch(RdispatchRef<'a>),
+    Tinit(InitRef<'a>),
+    Rinit(InitRef<'a>),
+    Tdrain,
+    Rdrain,
+    Tping,
+    Rping,
+    Tdiscarded(TdiscardedRef<'a>),
+    Rdiscarded,
+    Tlease(Tlease),
+    Rerr(RerrRef<'a>),
+    /// Borrowed `MessageFrame::Unknown`.
+    Unknown {
+        /// Type of the frame as read from the wire.
+        tpe: i8,
+        /// Raw body of the frame.
+        body: &'a [u8],
+    },
+}
+
+/// Borrowed `Treq`.
+#[derive(PartialEq, Eq, Debug)]
+pub struct TreqRef<'a> {
+    /// Request headers.
+    pub headers: HeadersRef<'a>,
+    /// Body of the request.
+    pub body: &'a [u8],
+}
+
+/// Borrowed `Rmsg`.
+#[derive(PartialEq, Eq, Debug)]
+pub enum RmsgRef<'a> {
+    /// Successful response containing a body.
+    Ok(&'a [u8]),
+    /// Response failed. The `str` describes the error.
+    Error(&'a str),
+    /// Negative acknowledgment. The `str` describes the reason.
+    Nack(&'a str),
+}
+
+/// Borrowed `Tdispatch`.
+#[derive(PartialEq, Eq, Debug)]
+pub struct TdispatchRef<'a> {
+    /// Context information associated with this request.
+    pub contexts: ContextsRef<'a>,
+    /// Destination of this request.
+    pub dest: &'a str,
+    /// Table of delegation rules for 'rewriting' the destination, parsed
+    /// as it is decoded.
+    pub dtab: Dtab,
+    /// Message payload.
+    pub body: &'a [u8],
+}
+
+/// Borrowed `Rdispatch`.
+#[derive(PartialEq, Eq, Debug)]
+pub struct RdispatchRef<'a> {
+    /// Context information associated with this request.
+    pub contexts: ContextsRef<'a>,
+    /// Response of the dispatch request.
+    pub msg: RmsgRef<'a>,
+}
+
+/// Borrowed `Init`.
+#[derive(PartialEq, Eq, Debug)]
+pub struct InitRef<'a> {
+    /// Mux protocol version.
+    pub version: u16,
+    /// Additional negotiation related information.
+    pub headers: ContextsRef<'a>,
+}
+
+/// Borrowed `Tdiscarded`.
+#[derive(PartialEq, Eq, Debug)]
+pub struct TdiscardedRef<'a> {
+    /// Stream id of the discarded `Tdispatch` request.
+    pub id: u32,
+    /// Reason for discarding the request.
+    pub msg: &'a str,
+}
+
+/// Borrowed `Rerr`.
+#[derive(PartialEq, Eq, Debug)]
+pub struct RerrRef<'a> {
+    /// Description of the error.
+    pub msg: &'a str,
+}
+
+// conversions to the owned representations
+
+#[inline]
+fn contexts_owned(contexts: ContextsRef) -> Contexts {
+    contexts.into_iter().map(|(k, v)| (k.to_vec(), v.to_vec())).collect()
+}
+
+impl<'a> MessageRef<'a> {
+    /// Copy the borrowed data into an owned `Message`.
+    pub fn into_owned(self) -> Message {
+        Message {
+            tag: self.tag,
+            frame: self.frame.into_owned(),
+        }
+    }
+}
+
+impl<'a> MessageFrameRef<'a> {
+    /// Copy the borrowed data into an owned `MessageFrame`.
+    pub fn into_owned(self) -> MessageFrame {
+        match self {
+            MessageFrameRef::Treq(f) => MessageFrame::Treq(f.into_owned()),
+            MessageFrameRef::Rreq(f) => MessageFrame::Rreq(f.into_owned()),
+            MessageFrameRef::Tdispatch(f) => MessageFrame::Tdispatch(f.into_owned()),
+            MessageFrameRef::Rdispatch(f) => MessageFrame::Rdispatch(f.into_owned()),
+            MessageFrameRef::Tinit(f) => MessageFrame::Tinit(f.into_owned()),
+            MessageFrameRef::Rinit(f) => MessageFrame::Rinit(f.into_owned()),
+            MessageFrameRef::Tdrain => MessageFrame::Tdrain,
+            MessageFrameRef::Rdrain => MessageFrame::Rdrain,
+            MessageFrameRef::Tping => MessageFrame::Tping,
+            MessageFrameRef::Rping => MessageFrame::Rping,
+            MessageFrameRef::Tdiscarded(f) => MessageFrame::Tdiscarded(f.into_owned()),
+            MessageFrameRef::Rdiscarded => MessageFrame::Rdiscarded,
+            MessageFrameRef::Tlease(f) => MessageFrame::Tlease(f),
+            MessageFrameRef::Rerr(f) => MessageFrame::Rerr(f.into_owned()),
+            MessageFrameRef::Unknown { tpe, body } => MessageFrame::Unknown { tpe, body: body.to_vec() },
+        }
+    }
+
+    /// Get the `i8` value coresponding the a `MessageFrameRef`.
+    pub fn frame_id(&self) -> i8 {
+        match *self {
+            MessageFrameRef::Treq(_) => types::TREQ,
+            MessageFrameRef::Rreq(_) => types::RREQ,
+            MessageFrameRef::Tdispatch(_) => types::TDISPATCH,
+            MessageFrameRef::Rdispatch(_) => types::RDISPATCH,
+            MessageFrameRef::Tinit(_) => types::TINIT,
+            MessageFrameRef::Rinit(_) => types::RINIT,
+            MessageFrameRef::Tdrain => types::TDRAIN,
+            MessageFrameRef::Rdrain => types::RDRAIN,
+            MessageFrameRef::Tping => types::TPING,
+            MessageFrameRef::Rping => types::RPING,
+            MessageFrameRef::Tdiscarded(_) => types::TDISCARDED,
+            MessageFrameRef::Rdiscarded => types::RDISCARDED,
+            MessageFrameRef::Tlease(_) => types::TLEASE,
+            MessageFrameRef::Rerr(_) => types::RERR,
+            MessageFrameRef::Unknown { tpe, .. } => tpe,
+        }
+    }
+}
+
+impl<'a> TreqRef<'a> {
+    /// Copy the borrowed data into an owned `Treq`.
+    pub fn into_owned(self) -> Treq {
+        Treq {
+            headers: self.headers.into_iter().map(|(k, v)| (k, v.to_vec())).collect(),
+            body: self.body.to_vec(),
+        }
+    }
+}
+
+impl<'a> RmsgRef<'a> {
+    /// Copy the borrowed data into an owned `Rmsg`.
+    pub fn into_owned(self) -> Rmsg {
+        match self {
+            RmsgRef::Ok(body) => Rmsg::Ok(body.to_vec()),
+            RmsgRef::Error(msg) => Rmsg::Error(msg.to_owned()),
+            RmsgRef::Nack(msg) => Rmsg::Nack(msg.to_owned()),
+        }
+    }
+}
+
+impl<'a> TdispatchRef<'a> {
+    /// Copy the borrowed data into an owned `Tdispatch`.
+    pub fn into_owned(self) -> Tdispatch {
+        Tdispatch {
+            contexts: contexts_owned(self.contexts),
+            dest: self.dest.to_owned(),
+            dtab: self.dtab,
+            body: self.body.to_vec(),
+        }
+    }
+}
+
+impl<'a> RdispatchRef<'a> {
+    /// Copy the borrowed data into an owned `Rdispatch`.
+    pub fn into_owned(self) -> Rdispatch {
+        Rdispatch {
+            contexts: contexts_owned(self.contexts),
+            msg: self.msg.into_owned(),
+        }
+    }
+}
+
+impl<'a> InitRef<'a> {
+    /// Copy the borrowed data into an owned `Init`.
+    pub fn into_owned(self) -> Init {
+        Init {
+            version: self.version,
+            headers: contexts_owned(self.headers),
+        }
+    }
+}
+
+impl<'a> TdiscardedRef<'a> {
+    /// Copy the borrowed data into an owned `Tdiscarded`.
+    pub fn into_owned(self) -> Tdiscarded {
+        Tdiscarded {
+            id: self.id,
+            msg: self.msg.to_owned(),
+        }
+    }
+}
+
+impl<'a> RerrRef<'a> {
+    /// Copy the borrowed data into an owned `Rerr`.
+    pub fn into_owned(self) -> Rerr {
+        Rerr { msg: self.msg.to_owned() }
+    }
+}
+
+impl<'a> From<MessageRef<'a>> for Message {
+    fn from(msg: MessageRef<'a>) -> Message {
+        msg.into_owned()
+    }
+}
@@ -0,0 +1,327 @@
+// Async client session running on tokio.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio_util::codec::Framed;
+
+use super::super::*;
+use super::super::codec::MuxCodec;
+use super::super::contexts::Deadline;
+use super::super::init::{self, InitOptions, NegotiatedParams, Negotiator};
+use super::super::lease::LeaseTracker;
+use super::super::trace::{Span, Tracer};
+use super::{client_recv, discard, rdispatch, response, stamp_deadline, Error, Result, State, INIT_TAG};
+
+type ResponseSender = oneshot::Sender<Result<MessageFrame>>;
+
+/// An async mux client session
+///
+/// Requests are written by a background task and their responses are routed
+/// back by `Tag` id by a second task reading the connection, so any number
+/// of requests may be in flight concurrently. The `AsyncClient` is cheap to
+/// clone, all clones share the same session. The tasks are spawned on the
+/// current tokio runtime. Fragmented responses are reassembled, like those
+/// of a `Session`.
+///
+/// ```rust,no_run
+/// use mux::Tdispatch;
+/// use mux::client::AsyncClient;
+///
+/// # async fn run() -> mux::client::Result<()> {
+/// let stream = tokio::net::TcpStream::connect("localhost:9000").await?;
+/// let client = AsyncClient::new(stream).await?;
+/// let tdispatch = Tdispatch::new("/foo".to_string(), b"Hello".to_vec());
+/// let rdispatch = client.dispatch(tdispatch).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct AsyncClient {
+    inner: Arc<AsyncInner>,
+    version: u16,
+    // the Tinit sent and the Rinit received
+    init: Arc<(Init, Init)>,
+    tracer: Option<Arc<dyn Tracer>>,
+}
+
+struct AsyncInner {
+    // dropped to close the session after a drain
+    writer: Mutex<Option<mpsc::UnboundedSender<Message>>>,
+    state: Mutex<State<ResponseSender>>,
+    drained: watch::Sender<bool>,
+}
+
+impl AsyncClient {
+    /// Start an `AsyncClient` over `io`
+    ///
+    /// The `Tinit` is sent and its response awaited before the background
+    /// tasks are spawned. Peers that don't support negotiation answer the
+    /// `Tinit` with an `Rerr` in which case the session continues at
+    /// version 0.
+    pub async fn new<T>(io: T) -> Result<AsyncClient>
+        where T: AsyncRead + AsyncWrite + Send + Unpin + 'static
+    {
+        AsyncClient::with_init_options(io, &InitOptions::new()).await
+    }
+
+    /// Start an `AsyncClient` advertising `options` in the headers of its
+    /// `Tinit`, like `Session::with_init_options`.
+    pub async fn with_init_options<T>(io: T, options: &InitOptions) -> Result<AsyncClient>
+        where T: AsyncRead + AsyncWrite + Send + Unpin + 'static
+    {
+        let mut framed = Framed::new(io, MuxCodec::new().reassembling());
+
+        let tinit = options.to_init(VERSION);
+        framed.send(Message {
+            tag: Tag::new(true, INIT_TAG),
+            frame: MessageFrame::Tinit(tinit.clone()),
+        }).await?;
+
+        let mut lease = LeaseTracker::new();
+        let mut draining = false;
+        let rinit = loop {
+            let msg = match framed.next().await {
+                Some(msg) => msg?,
+                None => return Err(Error::Closed),
+            };
+            match msg.frame {
+                MessageFrame::Rinit(rinit) if msg.tag.id == INIT_TAG => break rinit,
+                // the peer doesn't negotiate, it has nothing to advertise
+                MessageFrame::Rerr(_) if msg.tag.id == INIT_TAG => {
+                    break Init { version: 0, headers: Vec::new() };
+                }
+                MessageFrame::Tlease(ref tlease) => lease.observe(tlease),
+                // leases in units without known semantics are ignored
+                MessageFrame::Unknown { tpe: types::TLEASE, .. } => (),
+                MessageFrame::Tping => framed.send(Message {
+                    tag: msg.tag,
+                    frame: MessageFrame::Rping,
+                }).await?,
+                MessageFrame::Tdrain => {
+                    draining = true;
+                    framed.send(Message { tag: msg.tag, frame: MessageFrame::Rdrain }).await?;
+                }
+                ref other => return Err(Error::UnexpectedFrame(other.frame_id())),
+            }
+        };
+
+        let (mut sink, mut stream) = framed.split();
+        let (writer, mut queue) = mpsc::unbounded_channel();
+        let mut state = State::new();
+        state.lease = lease;
+        state.draining = draining;
+        let inner = Arc::new(AsyncInner {
+            writer: Mutex::new(Some(writer)),
+            state: Mutex::new(state),
+            drained: watch::channel(false).0,
+        });
+        inner.check_drained();
+
+        tokio::spawn(async move {
+            while let Some(msg) = queue.recv().await {
+                if sink.send(msg).await.is_err() {
+                    break;
+                }
+            }
+            let _ = sink.close().await;
+        });
+
+        let reader = inner.clone();
+        tokio::spawn(async move {
+            while let Some(Ok(msg)) = stream.next().await {
+                reader.handle(msg);
+            }
+
+            reader.state().close();
+            reader.check_drained();
+        });
+
+        let version = rinit.version.min(VERSION);
+        Ok(AsyncClient { inner, version, init: Arc::new((tinit, rinit)), tracer: None })
+    }
+
+    /// Trace the dispatches of the session with `tracer`, like a `Session`.
+    pub fn with_tracer<T: Tracer + 'static>(mut self, tracer: T) -> AsyncClient {
+        self.tracer = Some(Arc::new(tracer));
+        self
+    }
+
+    /// Negotiated mux protocol version.
+    #[inline]
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Negotiate the parameters of the session, like `Session::negotiate`.
+    pub fn negotiate(&self, negotiator: &Negotiator) -> init::Result<NegotiatedParams> {
+        negotiator.negotiate(&self.init.0, &self.init.1)
+    }
+
+    /// Issue a `Tdispatch` and wait for its `Rdispatch`.
+    pub async fn dispatch(&self, tdispatch: Tdispatch) -> Result<Rdispatch> {
+        self.dispatch_until(tdispatch, None).await
+    }
+
+    /// Issue a `Tdispatch` due in `timeout` and wait for its `Rdispatch`
+    /// until then. The deadline is propagated like by
+    /// `Session::dispatch_timeout`.
+    pub async fn dispatch_timeout(&self, mut tdispatch: Tdispatch, timeout: Duration) -> Result<Rdispatch> {
+        let deadline = stamp_deadline(&mut tdispatch, timeout);
+        self.dispatch_until(tdispatch, Some(deadline)).await
+    }
+
+    /// Issue a `Treq` and wait for its `Rreq`.
+    pub async fn request(&self, treq: Treq) -> Result<Rmsg> {
+        match self.call(MessageFrame::Treq(treq), None).await? {
+            MessageFrame::Rreq(rmsg) => Ok(rmsg),
+            other => Err(Error::UnexpectedFrame(other.frame_id())),
+        }
+    }
+
+    /// Measure the round trip time of a `Tping`.
+    pub async fn ping(&self) -> Result<Duration> {
+        let start = Instant::now();
+        match self.call(MessageFrame::Tping, None).await? {
+            MessageFrame::Rping => Ok(start.elapsed()),
+            other => Err(Error::UnexpectedFrame(other.frame_id())),
+        }
+    }
+
+    /// Stop accepting new requests, wait for the outstanding ones to
+    /// complete and close the writing half of the connection.
+    pub async fn drain(&self) {
+        self.inner.state().draining = true;
+        self.inner.check_drained();
+        self.drained().await;
+        self.inner.state().closed = true;
+    }
+
+    /// Wait until the session is drained: it refuses new requests, because
+    /// of a drain or because it is closed, and none are outstanding.
+    pub async fn drained(&self) {
+        let mut drained = self.inner.drained.subscribe();
+        // the sender lives as long as `self`
+        let _ = drained.wait_for(|drained| *drained).await;
+    }
+
+    /// The lease granted by the server
+    ///
+    /// Requests are still issued under an expired lease, it's up to the
+    /// caller to hold off until a new lease is granted.
+    pub fn lease(&self) -> LeaseTracker {
+        self.inner.state().lease
+    }
+
+    /// Whether the session refuses new requests because of a drain.
+    pub fn is_draining(&self) -> bool {
+        self.inner.state().draining
+    }
+
+    /// Whether the session is closed.
+    pub fn is_closed(&self) -> bool {
+        self.inner.state().closed
+    }
+
+    // issue a dispatch in its span, if traced
+    async fn dispatch_until(&self, mut tdispatch: Tdispatch, deadline: Option<Deadline>) -> Result<Rdispatch> {
+        let span = self.tracer.as_ref().map(|tracer| Span::client(tracer, &mut tdispatch));
+        let result = self.call(MessageFrame::Tdispatch(tdispatch), deadline).await.and_then(rdispatch);
+        if let Some(span) = span {
+            span.record(client_recv(&result));
+        }
+        result
+    }
+
+    // issue a request and wait for the reader task to route its response,
+    // or the deadline to pass
+    async fn call(&self, frame: MessageFrame, deadline: Option<Deadline>) -> Result<MessageFrame> {
+        let (tx, rx) = oneshot::channel();
+        let id = self.inner.state().register(tx)?;
+
+        let msg = Message { tag: Tag::new(true, id), frame };
+        if let Err(msg) = self.inner.send(msg) {
+            self.inner.state().complete(&msg.tag);
+            self.inner.check_drained();
+            return Err(Error::Closed);
+        }
+
+        let deadline = match deadline {
+            Some(deadline) => deadline,
+            None => return rx.await.unwrap_or(Err(Error::Closed)),
+        };
+        match tokio::time::timeout(deadline.remaining(), rx).await {
+            Ok(result) => result.unwrap_or(Err(Error::Closed)),
+            // the tag stays in use until the server answers or acknowledges
+            // the discard
+            Err(_) => {
+                let _ = self.inner.send(discard(id));
+                Err(Error::Timeout)
+            }
+        }
+    }
+}
+
+impl AsyncInner {
+    #[inline]
+    fn state(&self) -> MutexGuard<'_, State<ResponseSender>> {
+        self.state.lock().unwrap()
+    }
+
+    // queue `msg` for the writer task, handing it back if the session is closed
+    fn send(&self, msg: Message) -> ::std::result::Result<(), Message> {
+        match *self.writer.lock().unwrap() {
+            Some(ref writer) => writer.send(msg).map_err(|e| e.0),
+            None => Err(msg),
+        }
+    }
+
+    // signal a completed drain, closing the writing half if it was a drain
+    fn check_drained(&self) {
+        let (drained, draining) = {
+            let state = self.state();
+            (state.is_drained(), state.draining)
+        };
+
+        if drained {
+            if draining {
+                self.writer.lock().unwrap().take();
+            }
+            self.drained.send_replace(true);
+        }
+    }
+
+    fn handle(&self, msg: Message) {
+        let Message { tag, frame } = msg;
+        let result = match frame {
+            MessageFrame::Tping => {
+                let _ = self.send(Message { tag, frame: MessageFrame::Rping });
+                return;
+            }
+            MessageFrame::Tdrain => {
+                self.state().draining = true;
+                let _ = self.send(Message { tag, frame: MessageFrame::Rdrain });
+                self.check_drained();
+                return;
+            }
+            MessageFrame::Tlease(ref tlease) => {
+                self.state().lease.observe(tlease);
+                return;
+            }
+            // unsolicited messages are of no interest
+            frame => match response(frame) {
+                Some(result) => result,
+                None => return,
+            },
+        };
+
+        let tx = self.state().complete(&tag);
+        if let Some(tx) = tx {
+            let _ = tx.send(result);
+        }
+        self.check_drained();
+    }
+}
@@ -0,0 +1,67 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+use super::super::codec;
+
+/// Result of the client session operations.
+pub type Result<T> = result::Result<T, Error>;
+
+/// Errors produced by a client `Session`.
+#[derive(Debug)]
+pub enum Error {
+    /// Failure to encode or decode a frame, including failures of the
+    /// underlying stream.
+    Codec(codec::Error),
+    /// The session is closed and cannot issue requests.
+    Closed,
+    /// The session is draining and doesn't accept new requests.
+    Draining,
+    /// All tags up to `MAX_TAG` are in use by outstanding requests.
+    TagsExhausted,
+    /// The deadline of the request passed before its response arrived.
+    Timeout,
+    /// The peer discarded the request, acknowledging a `Tdiscarded`.
+    Discarded,
+    /// The peer failed the request with an `Rerr`.
+    Rerr(String),
+    /// The peer answered with a frame of an unexpected type.
+    UnexpectedFrame(i8),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Codec(ref e) => e.fmt(f),
+            Error::Closed => write!(f, "Mux session closed"),
+            Error::Draining => write!(f, "Mux session draining"),
+            Error::TagsExhausted => write!(f, "No mux tags available"),
+            Error::Timeout => write!(f, "Mux request timed out"),
+            Error::Discarded => write!(f, "Mux request discarded"),
+            Error::Rerr(ref msg) => write!(f, "Rerr: {}", msg),
+            Error::UnexpectedFrame(tpe) => write!(f, "Unexpected frame type: {}", tpe),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Codec(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<codec::Error> for Error {
+    fn from(err: codec::Error) -> Error {
+        Error::Codec(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Codec(codec::Error::Io(err))
+    }
+}
@@ -0,0 +1,508 @@
+//! Blocking mux client sessions.
+//!
+//! A `Session` multiplexes requests from any number of threads over a single
+//! connection. Requests are written with a freshly allocated `Tag` and the
+//! calling thread blocks until a background reader thread routes the
+//! response carrying the same tag id back to it. With the `tokio` feature,
+//! the `AsyncClient` does the same for async tasks.
+//!
+//! A session acknowledges the `Tdrain` of a server with an `Rdrain` and
+//! refuses new requests from then on. The connection is closed once the
+//! outstanding requests complete.
+//!
+//! Requests that time out are discarded with a `Tdiscarded` and their tag is
+//! released once the server acknowledges the discard with an `Rdiscarded`,
+//! or answers the request anyway.
+//!
+//! Dispatches issued with a timeout carry a `contexts::Deadline` so the
+//! servers down the line know how long the caller waits for them. Sessions
+//! given a `trace::Tracer` issue each dispatch in a new span.
+
+use std::collections::HashMap;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::*;
+use super::codec::Reassembler;
+use super::contexts::{self, Deadline};
+use super::init::{self, InitOptions, NegotiatedParams, Negotiator};
+use super::lease::LeaseTracker;
+use super::trace::{Annotation, Span, Tracer};
+
+#[cfg(feature = "tokio")]
+mod async_client;
+mod error;
+
+#[cfg(feature = "tokio")]
+pub use self::async_client::AsyncClient;
+pub use self::error::{Error, Result};
+
+// tag of the Tinit issued before any other request
+const INIT_TAG: u32 = 1;
+
+// tag of the marker messages that aren't requests
+const MARKER_TAG: u32 = 0;
+
+/// A blocking mux client session
+///
+/// The `Session` is thread safe: share it through an `Arc` to issue
+/// concurrent requests over the same connection.
+///
+/// ```rust,no_run
+/// use mux::Tdispatch;
+/// use mux::client::Session;
+///
+/// let session = Session::connect("localhost:9000").unwrap();
+/// println!("Ping time: {:?}", session.ping().unwrap());
+///
+/// let tdispatch = Tdispatch::new("/foo".to_string(), b"Hello".to_vec());
+/// let rdispatch = session.dispatch(tdispatch).unwrap();
+/// ```
+pub struct Session {
+    inner: Arc<Inner>,
+    version: u16,
+    // the Tinit sent and the Rinit received
+    init: Arc<(Init, Init)>,
+    tracer: Option<Arc<dyn Tracer>>,
+}
+
+struct Inner {
+    writer: Mutex<Option<Box<dyn Write + Send>>>,
+    state: Mutex<State<Sender<Result<MessageFrame>>>>,
+    // signaled when the last outstanding request completes
+    idle: Condvar,
+}
+
+// the writing half of a TCP connection, shut down when dropped so the peer
+// sees the end of the session while the reading half is still open
+struct TcpWriter(TcpStream);
+
+// outstanding requests by tag id, `T` being the channel of the response
+struct State<T> {
+    tags: TagAllocator,
+    pending: HashMap<u32, T>,
+    lease: LeaseTracker,
+    draining: bool,
+    closed: bool,
+}
+
+impl Session {
+    /// Connect to a mux server over TCP and start a `Session`.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Session> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        let reader = stream.try_clone()?;
+        Session::new(reader, TcpWriter(stream))
+    }
+
+    /// Start a `Session` over the two halves of a connection
+    ///
+    /// The `Tinit` is sent and its response awaited before the reader thread
+    /// is spawned. Peers that don't support negotiation answer the `Tinit`
+    /// with an `Rerr` in which case the session continues at version 0.
+    /// The `writer` is dropped to close the session after a drain.
+    pub fn new<R, W>(reader: R, writer: W) -> Result<Session>
+        where R: Read + Send + 'static,
+              W: Write + Send + 'static
+    {
+        Session::with_init_options(reader, writer, &InitOptions::new())
+    }
+
+    /// Start a `Session` advertising `options` in the headers of its `Tinit`
+    ///
+    /// The options agreed on with the server are then available from
+    /// `negotiate`.
+    pub fn with_init_options<R, W>(reader: R, writer: W, options: &InitOptions) -> Result<Session>
+        where R: Read + Send + 'static,
+              W: Write + Send + 'static
+    {
+        let mut reader = BufReader::new(reader);
+        let inner = Arc::new(Inner {
+            writer: Mutex::new(Some(Box::new(writer))),
+            state: Mutex::new(State::new()),
+            idle: Condvar::new(),
+        });
+
+        let tinit = options.to_init(VERSION);
+        inner.send(&Message {
+            tag: Tag::new(true, INIT_TAG),
+            frame: MessageFrame::Tinit(tinit.clone()),
+        })?;
+
+        let rinit = loop {
+            let msg = codec::read_message(&mut reader)?;
+            match msg.frame {
+                MessageFrame::Rinit(rinit) if msg.tag.id == INIT_TAG => break rinit,
+                // the peer doesn't negotiate, it has nothing to advertise
+                MessageFrame::Rerr(_) if msg.tag.id == INIT_TAG => {
+                    break Init { version: 0, headers: Vec::new() };
+                }
+                MessageFrame::Tlease(ref tlease) => inner.state().lease.observe(tlease),
+                // leases in units without known semantics are ignored
+                MessageFrame::Unknown { tpe: types::TLEASE, .. } => (),
+                MessageFrame::Tping => inner.send(&Message {
+                    tag: msg.tag,
+                    frame: MessageFrame::Rping,
+                })?,
+                MessageFrame::Tdrain => {
+                    inner.state().draining = true;
+                    inner.send(&Message { tag: msg.tag, frame: MessageFrame::Rdrain })?;
+                }
+                ref other => return Err(Error::UnexpectedFrame(other.frame_id())),
+            }
+        };
+        inner.check_drained();
+
+        let thread_inner = inner.clone();
+        thread::Builder::new()
+            .name("mux-client-reader".to_string())
+            .spawn(move || thread_inner.read_loop(reader))?;
+
+        let version = rinit.version.min(VERSION);
+        Ok(Session { inner, version, init: Arc::new((tinit, rinit)), tracer: None })
+    }
+
+    /// Trace the dispatches of the session with `tracer`.
+    pub fn with_tracer<T: Tracer + 'static>(mut self, tracer: T) -> Session {
+        self.tracer = Some(Arc::new(tracer));
+        self
+    }
+
+    /// Negotiated mux protocol version.
+    #[inline]
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Negotiate the parameters of the session from the init headers
+    /// exchanged with the server.
+    pub fn negotiate(&self, negotiator: &Negotiator) -> init::Result<NegotiatedParams> {
+        negotiator.negotiate(&self.init.0, &self.init.1)
+    }
+
+    /// Issue a `Tdispatch` and wait for its `Rdispatch`.
+    pub fn dispatch(&self, tdispatch: Tdispatch) -> Result<Rdispatch> {
+        self.dispatch_until(tdispatch, None)
+    }
+
+    /// Issue a `Tdispatch` due in `timeout` and wait for its `Rdispatch`
+    /// until then
+    ///
+    /// The request carries a `Deadline` context, or the sooner of the two if
+    /// it already carries one so deadlines propagate across hops. When the
+    /// deadline passes the request is discarded with a `Tdiscarded` and fails
+    /// with `Error::Timeout`.
+    pub fn dispatch_timeout(&self, mut tdispatch: Tdispatch, timeout: Duration) -> Result<Rdispatch> {
+        let deadline = stamp_deadline(&mut tdispatch, timeout);
+        self.dispatch_until(tdispatch, Some(deadline))
+    }
+
+    /// Issue a `Treq` and wait for its `Rreq`.
+    pub fn request(&self, treq: Treq) -> Result<Rmsg> {
+        match self.call(MessageFrame::Treq(treq), None)? {
+            MessageFrame::Rreq(rmsg) => Ok(rmsg),
+            other => Err(Error::UnexpectedFrame(other.frame_id())),
+        }
+    }
+
+    /// Measure the round trip time of a `Tping`.
+    pub fn ping(&self) -> Result<Duration> {
+        let start = Instant::now();
+        match self.call(MessageFrame::Tping, None)? {
+            MessageFrame::Rping => Ok(start.elapsed()),
+            other => Err(Error::UnexpectedFrame(other.frame_id())),
+        }
+    }
+
+    /// Stop accepting new requests, wait for the outstanding ones to
+    /// complete and close the writing half of the connection.
+    pub fn drain(&self) -> Result<()> {
+        self.inner.state().draining = true;
+        self.wait_drained();
+        self.inner.state().closed = true;
+
+        if let Some(mut writer) = self.inner.writer.lock().unwrap().take() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Block until the session is drained: it refuses new requests, because
+    /// of a drain or because it is closed, and none are outstanding.
+    pub fn wait_drained(&self) {
+        let mut state = self.inner.state();
+        while !state.is_drained() {
+            state = self.inner.idle.wait(state).unwrap();
+        }
+    }
+
+    /// The lease granted by the server
+    ///
+    /// Requests are still issued under an expired lease, it's up to the
+    /// caller to hold off until a new lease is granted.
+    pub fn lease(&self) -> LeaseTracker {
+        self.inner.state().lease
+    }
+
+    /// Whether the session refuses new requests because of a drain.
+    pub fn is_draining(&self) -> bool {
+        self.inner.state().draining
+    }
+
+    /// Whether the session is closed.
+    pub fn is_closed(&self) -> bool {
+        self.inner.state().closed
+    }
+
+    // issue a dispatch in its span, if traced
+    fn dispatch_until(&self, mut tdispatch: Tdispatch, deadline: Option<Deadline>) -> Result<Rdispatch> {
+        let span = self.tracer.as_ref().map(|tracer| Span::client(tracer, &mut tdispatch));
+        let result = self.call(MessageFrame::Tdispatch(tdispatch), deadline).and_then(rdispatch);
+        if let Some(span) = span {
+            span.record(client_recv(&result));
+        }
+        result
+    }
+
+    // issue a request and block until the reader thread routes its
+    // response, or the deadline passes
+    fn call(&self, frame: MessageFrame, deadline: Option<Deadline>) -> Result<MessageFrame> {
+        let (tx, rx) = mpsc::channel();
+        let id = self.inner.register(tx)?;
+
+        let msg = Message { tag: Tag::new(true, id), frame };
+        if let Err(e) = self.inner.send(&msg) {
+            self.inner.complete(&msg.tag);
+            return Err(e);
+        }
+
+        let deadline = match deadline {
+            Some(deadline) => deadline,
+            None => return rx.recv().unwrap_or(Err(Error::Closed)),
+        };
+        match rx.recv_timeout(deadline.remaining()) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Disconnected) => Err(Error::Closed),
+            // the tag stays in use until the server answers or acknowledges
+            // the discard
+            Err(RecvTimeoutError::Timeout) => {
+                let _ = self.inner.send(&discard(id));
+                Err(Error::Timeout)
+            }
+        }
+    }
+}
+
+impl<T> State<T> {
+    fn new() -> State<T> {
+        State {
+            tags: TagAllocator::new(),
+            pending: HashMap::new(),
+            lease: LeaseTracker::new(),
+            draining: false,
+            closed: false,
+        }
+    }
+
+    // allocate a free tag id for the response channel `tx`
+    fn register(&mut self, tx: T) -> Result<u32> {
+        if self.closed {
+            return Err(Error::Closed);
+        } else if self.draining {
+            return Err(Error::Draining);
+        }
+
+        let id = self.tags.allocate().map_err(|_| Error::TagsExhausted)?;
+        self.pending.insert(id, tx);
+        Ok(id)
+    }
+
+    // the channel of the request answered by `tag`, releasing its id if the
+    // response is complete. Unexpected responses are `None`.
+    fn complete(&mut self, tag: &Tag) -> Option<T> {
+        match self.tags.complete(tag) {
+            Ok(true) => self.pending.remove(&tag.id),
+            _ => None,
+        }
+    }
+
+    // whether no request is outstanding nor will be
+    fn is_drained(&self) -> bool {
+        (self.draining || self.closed) && self.pending.is_empty()
+    }
+
+    // fail everything still outstanding by dropping the channels
+    fn close(&mut self) {
+        self.closed = true;
+        self.pending.clear();
+        self.tags.clear();
+    }
+}
+
+// stamp `tdispatch` with a deadline due in `timeout`, combined with the
+// deadline it already carries
+fn stamp_deadline(tdispatch: &mut Tdispatch, timeout: Duration) -> Deadline {
+    let deadline = Deadline::from_timeout(timeout);
+    let deadline = match tdispatch.deadline() {
+        Some(current) => current.combine(&deadline),
+        None => deadline,
+    };
+    contexts::set(&mut tdispatch.contexts, &deadline);
+    deadline
+}
+
+// the response to a dispatch given its response frame
+fn rdispatch(frame: MessageFrame) -> Result<Rdispatch> {
+    match frame {
+        MessageFrame::Rdispatch(rdispatch) => Ok(rdispatch),
+        other => Err(Error::UnexpectedFrame(other.frame_id())),
+    }
+}
+
+// the annotation of the result of a traced dispatch
+fn client_recv(result: &Result<Rdispatch>) -> Annotation {
+    match *result {
+        Ok(_) => Annotation::ClientRecv,
+        Err(ref e) => Annotation::ClientRecvError(e.to_string()),
+    }
+}
+
+// the Tdiscarded of a request that timed out
+fn discard(id: u32) -> Message {
+    Message {
+        tag: Tag::new(true, MARKER_TAG),
+        frame: MessageFrame::Tdiscarded(Tdiscarded { id, msg: "Request timed out".to_string() }),
+    }
+}
+
+// the result of a call given the response frame, `None` if the frame isn't
+// a response
+fn response(frame: MessageFrame) -> Option<Result<MessageFrame>> {
+    match frame {
+        MessageFrame::Rerr(rerr) => Some(Err(Error::Rerr(rerr.msg))),
+        MessageFrame::Rdiscarded => Some(Err(Error::Discarded)),
+        frame @ MessageFrame::Rreq(_) |
+        frame @ MessageFrame::Rdispatch(_) |
+        frame @ MessageFrame::Rping => Some(Ok(frame)),
+        _ => None,
+    }
+}
+
+impl Inner {
+    #[inline]
+    fn state(&self) -> MutexGuard<'_, State<Sender<Result<MessageFrame>>>> {
+        self.state.lock().unwrap()
+    }
+
+    // allocate a free tag id for the response channel
+    fn register(&self, tx: Sender<Result<MessageFrame>>) -> Result<u32> {
+        self.state().register(tx)
+    }
+
+    // release the tag, returning the channel of its request
+    fn complete(&self, tag: &Tag) -> Option<Sender<Result<MessageFrame>>> {
+        let tx = self.state().complete(tag);
+        self.check_drained();
+        tx
+    }
+
+    // close the writing half once drained
+    fn check_drained(&self) {
+        let drained = {
+            let state = self.state();
+            if state.pending.is_empty() {
+                self.idle.notify_all();
+            }
+            state.draining && state.is_drained()
+        };
+
+        if drained {
+            if let Some(mut writer) = self.writer.lock().unwrap().take() {
+                let _ = writer.flush();
+            }
+        }
+    }
+
+    fn send(&self, msg: &Message) -> Result<()> {
+        // encode up front so the frame is written with a single call
+        let mut buf = Vec::with_capacity(8 + codec::size::frame_size(&msg.frame));
+        codec::write_message(&mut buf, msg)?;
+
+        match *self.writer.lock().unwrap() {
+            Some(ref mut writer) => {
+                writer.write_all(&buf)?;
+                writer.flush()?;
+                Ok(())
+            }
+            None => Err(Error::Closed),
+        }
+    }
+
+    fn read_loop<R: Read>(&self, mut reader: R) {
+        let mut reassembler = Reassembler::new();
+        loop {
+            let next = codec::read_fragment(&mut reader)
+                .and_then(|fragment| reassembler.push(fragment));
+
+            match next {
+                Ok(Some(msg)) => self.handle(msg),
+                Ok(None) => (),
+                Err(_) => break,
+            }
+        }
+
+        self.state().close();
+        self.idle.notify_all();
+    }
+
+    fn handle(&self, msg: Message) {
+        let Message { tag, frame } = msg;
+        let result = match frame {
+            MessageFrame::Tping => {
+                let _ = self.send(&Message { tag, frame: MessageFrame::Rping });
+                return;
+            }
+            MessageFrame::Tdrain => {
+                self.state().draining = true;
+                let _ = self.send(&Message { tag, frame: MessageFrame::Rdrain });
+                self.check_drained();
+                return;
+            }
+            MessageFrame::Tlease(ref tlease) => {
+                self.state().lease.observe(tlease);
+                return;
+            }
+            // unsolicited messages are of no interest
+            frame => match response(frame) {
+                Some(result) => result,
+                None => return,
+            },
+        };
+
+        if let Some(tx) = self.complete(&tag) {
+            let _ = tx.send(result);
+        }
+    }
+}
+
+impl Write for TcpWriter {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Drop for TcpWriter {
+    fn drop(&mut self) {
+        let _ = self.0.shutdown(Shutdown::Write);
+    }
+}
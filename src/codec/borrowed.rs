@@ -0,0 +1,304 @@
+// Decoders producing the borrowed message views. Rather than reading from a
+// `Read`, these consume a `&[u8]` holding the whole frame and hand out slices
+// of it.
+
+use std::io;
+use std::io::ErrorKind;
+use std::str;
+
+use byteorder::{BigEndian, ByteOrder};
+
+use super::super::*;
+use super::{decode_dentry, decode_tlease, is_unknown_lease_unit, DecoderConfig, Error, Result};
+
+// cursor over the frame handing out subslices with the frame's lifetime
+struct SliceReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> SliceReader<'a> {
+    #[inline]
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.buf.len() < n {
+            let err = io::Error::new(ErrorKind::UnexpectedEof, "failed to fill whole buffer");
+            return Err(Error::Io(err));
+        }
+
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    #[inline]
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    #[inline]
+    fn read_u16(&mut self) -> Result<u16> {
+        Ok(BigEndian::read_u16(self.take(2)?))
+    }
+
+    #[inline]
+    fn read_u16_slice(&mut self) -> Result<&'a [u8]> {
+        let len = self.read_u16()? as usize;
+        self.take(len)
+    }
+
+    #[inline]
+    fn read_u16_str(&mut self, field: &'static str) -> Result<&'a str> {
+        to_str(self.read_u16_slice()?, field)
+    }
+
+    // capacity for `len` entries of at least `entry_size` bytes, bounded by
+    // what the rest of the frame can hold
+    #[inline]
+    fn capacity(&self, len: usize, entry_size: usize) -> usize {
+        len.min(self.buf.len() / entry_size)
+    }
+
+    #[inline]
+    fn rest(&mut self) -> &'a [u8] {
+        let rest = self.buf;
+        self.buf = &[];
+        rest
+    }
+}
+
+/// Decode a mux `MessageRef` borrowing from the bytes of the frame
+///
+/// The buffer must contain exactly one message without its length prefix,
+/// as handed out by `read_message`'s framing. Bodies, contexts and
+/// destinations reference `buf` rather than being copied, dtab entries are
+/// parsed.
+///
+/// ```rust
+/// use mux::MessageFrameRef;
+/// use mux::codec;
+///
+/// let buf = [2,0,0,1, 0,0, 0,4,47,102,111,111, 0,0, 1,2,3]; // Tdispatch to /foo
+/// let msg = codec::decode_message_ref(&buf).unwrap();
+/// match msg.frame {
+///     MessageFrameRef::Tdispatch(ref t) => {
+///         assert_eq!(t.dest, "/foo");
+///         assert_eq!(t.body, &buf[14..]);
+///     }
+///     _ => panic!("Expected a Tdispatch"),
+/// }
+/// ```
+pub fn decode_message_ref<'a>(buf: &'a [u8]) -> Result<MessageRef<'a>> {
+    decode_message_ref_with(buf, &DecoderConfig::default())
+}
+
+/// Decode a mux `MessageRef` borrowing from the bytes of the frame according
+/// to `config`
+///
+/// The limits are applied like by `decode_message_with`, a `buf` larger
+/// than `config.max_frame_size` being rejected as `Error::FrameTooLarge`.
+pub fn decode_message_ref_with<'a>(buf: &'a [u8], config: &DecoderConfig)
+    -> Result<MessageRef<'a>>
+{
+    if buf.len() > config.max_frame_size {
+        return Err(Error::FrameTooLarge {
+            size: buf.len(),
+            max: config.max_frame_size,
+        });
+    }
+
+    let mut reader = SliceReader { buf };
+    let tpe = reader.read_u8()? as i8;
+    let tag = super::decode_tag(&mut reader.take(3)?)?;
+    let frame = decode_frame_ref_with(tpe, reader.rest(), config)?;
+
+    Ok(MessageRef {
+        tag,
+        frame,
+    })
+}
+
+/// Decode a mux `MessageFrameRef` borrowing from the bytes of the frame
+///
+/// The buffer is assumed to hold the whole frame body.
+pub fn decode_frame_ref<'a>(tpe: i8, buf: &'a [u8]) -> Result<MessageFrameRef<'a>> {
+    decode_frame_ref_with(tpe, buf, &DecoderConfig::default())
+}
+
+/// Decode a mux `MessageFrameRef` borrowing from the bytes of the frame
+/// according to `config`
+pub fn decode_frame_ref_with<'a>(tpe: i8, buf: &'a [u8], config: &DecoderConfig)
+    -> Result<MessageFrameRef<'a>>
+{
+    Ok(match tpe {
+        types::TREQ => MessageFrameRef::Treq(decode_treq_ref(buf)?),
+        types::RREQ => MessageFrameRef::Rreq(decode_rreq_ref(buf)?),
+        types::TDISPATCH => MessageFrameRef::Tdispatch(decode_tdispatch_ref_with(buf, config)?),
+        types::RDISPATCH => MessageFrameRef::Rdispatch(decode_rdispatch_ref_with(buf, config)?),
+        types::TINIT => MessageFrameRef::Tinit(decode_init_ref_with(buf, config)?),
+        types::RINIT => MessageFrameRef::Rinit(decode_init_ref_with(buf, config)?),
+        types::TDRAIN => MessageFrameRef::Tdrain,
+        types::RDRAIN => MessageFrameRef::Rdrain,
+        types::TPING => MessageFrameRef::Tping,
+        types::RPING => MessageFrameRef::Rping,
+        types::TDISCARDED | types::BAD_TDISCARDED => {
+            MessageFrameRef::Tdiscarded(decode_tdiscarded_ref(buf)?)
+        }
+        types::RDISCARDED => MessageFrameRef::Rdiscarded,
+        types::TLEASE if is_unknown_lease_unit(buf) => MessageFrameRef::Unknown { tpe, body: buf },
+        types::TLEASE => MessageFrameRef::Tlease(decode_tlease(buf)?),
+        types::RERR => MessageFrameRef::Rerr(RerrRef { msg: to_str(buf, "rerr message")? }),
+        other if config.allow_unknown_frames => MessageFrameRef::Unknown { tpe: other, body: buf },
+        other => {
+            return Err(Error::UnknownFrameType(other));
+        }
+    })
+}
+
+pub fn decode_treq_ref<'a>(buf: &'a [u8]) -> Result<TreqRef<'a>> {
+    let mut reader = SliceReader { buf };
+    let len = reader.read_u8()? as usize;
+    let mut headers = Vec::with_capacity(reader.capacity(len, 2));
+
+    for _ in 0..len {
+        let key = reader.read_u8()?;
+        let val_len = reader.read_u8()? as usize;
+        headers.push((key, reader.take(val_len)?));
+    }
+
+    Ok(TreqRef {
+        headers,
+        body: reader.rest(),
+    })
+}
+
+pub fn decode_rreq_ref<'a>(buf: &'a [u8]) -> Result<RmsgRef<'a>> {
+    let mut reader = SliceReader { buf };
+    let status = reader.read_u8()?;
+    decode_rmsg_body_ref(status, reader.rest())
+}
+
+pub fn decode_tdispatch_ref<'a>(buf: &'a [u8]) -> Result<TdispatchRef<'a>> {
+    decode_tdispatch_ref_with(buf, &DecoderConfig::default())
+}
+
+fn decode_tdispatch_ref_with<'a>(buf: &'a [u8], config: &DecoderConfig)
+    -> Result<TdispatchRef<'a>>
+{
+    let mut reader = SliceReader { buf };
+    let contexts = decode_contexts_ref(&mut reader, config)?;
+    let dest = reader.read_u16_str("dest")?;
+
+    let len = reader.read_u16()? as usize;
+    check_limit(len, config.max_dtab_entries, "dtab entry count")?;
+    let mut entries = Vec::with_capacity(reader.capacity(len, 4));
+    for _ in 0..len {
+        let key = reader.read_u16_str("dentry key")?;
+        let val = reader.read_u16_str("dentry val")?;
+        entries.push(decode_dentry(key, val)?);
+    }
+    let dtab = Dtab::from_entries(entries);
+
+    Ok(TdispatchRef {
+        contexts,
+        dest,
+        dtab,
+        body: reader.rest(),
+    })
+}
+
+pub fn decode_rdispatch_ref<'a>(buf: &'a [u8]) -> Result<RdispatchRef<'a>> {
+    decode_rdispatch_ref_with(buf, &DecoderConfig::default())
+}
+
+fn decode_rdispatch_ref_with<'a>(buf: &'a [u8], config: &DecoderConfig)
+    -> Result<RdispatchRef<'a>>
+{
+    let mut reader = SliceReader { buf };
+    let status = reader.read_u8()?;
+    let contexts = decode_contexts_ref(&mut reader, config)?;
+
+    Ok(RdispatchRef {
+        contexts,
+        msg: decode_rmsg_body_ref(status, reader.rest())?,
+    })
+}
+
+pub fn decode_init_ref<'a>(buf: &'a [u8]) -> Result<InitRef<'a>> {
+    decode_init_ref_with(buf, &DecoderConfig::default())
+}
+
+pub(super) fn decode_init_ref_with<'a>(buf: &'a [u8], config: &DecoderConfig) -> Result<InitRef<'a>> {
+    let mut reader = SliceReader { buf };
+    let version = reader.read_u16()?;
+    let mut headers = Vec::new();
+    let mut headers_size = 0;
+
+    while !reader.buf.is_empty() {
+        let klen = BigEndian::read_u32(reader.take(4)?) as usize;
+        headers_size += klen;
+        check_limit(headers_size, config.max_init_header_size, "init header size")?;
+        let k = reader.take(klen)?;
+        let vlen = BigEndian::read_u32(reader.take(4)?) as usize;
+        headers_size += vlen;
+        check_limit(headers_size, config.max_init_header_size, "init header size")?;
+        let v = reader.take(vlen)?;
+        headers.push((k, v));
+    }
+
+    Ok(InitRef {
+        version,
+        headers,
+    })
+}
+
+pub fn decode_tdiscarded_ref<'a>(buf: &'a [u8]) -> Result<TdiscardedRef<'a>> {
+    let mut reader = SliceReader { buf };
+    let bts = reader.take(3)?;
+    let id = (bts[0] as u32) << 16 | (bts[1] as u32) <<  8 | (bts[2] as u32);
+
+    Ok(TdiscardedRef {
+        id,
+        msg: to_str(reader.rest(), "tdiscarded message")?,
+    })
+}
+
+fn decode_contexts_ref<'a>(reader: &mut SliceReader<'a>, config: &DecoderConfig)
+    -> Result<ContextsRef<'a>>
+{
+    let len = reader.read_u16()? as usize;
+    check_limit(len, config.max_contexts, "context count")?;
+    let mut acc = Vec::with_capacity(reader.capacity(len, 4));
+
+    for _ in 0..len {
+        let key = reader.read_u16_slice()?;
+        let val = reader.read_u16_slice()?;
+        acc.push((key, val));
+    }
+
+    Ok(acc)
+}
+
+#[inline]
+fn decode_rmsg_body_ref<'a>(status: u8, body: &'a [u8]) -> Result<RmsgRef<'a>> {
+    match status {
+        0 => Ok(RmsgRef::Ok(body)),
+        1 => Ok(RmsgRef::Error(to_str(body, "error message")?)),
+        2 => Ok(RmsgRef::Nack(to_str(body, "nack message")?)),
+        other => Err(Error::InvalidRmsgStatus(other)),
+    }
+}
+
+#[inline]
+fn check_limit(len: usize, max: usize, field: &'static str) -> Result<()> {
+    if len > max {
+        return Err(Error::LimitExceeded { field, len, max });
+    }
+    Ok(())
+}
+
+#[inline]
+fn to_str<'a>(bytes: &'a [u8], field: &'static str) -> Result<&'a str> {
+    match str::from_utf8(bytes) {
+        Ok(s) => Ok(s),
+        Err(_) => Err(Error::InvalidUtf8 { field }),
+    }
+}
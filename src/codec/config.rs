@@ -0,0 +1,62 @@
+/// Limits applied while decoding messages from a peer.
+///
+/// Length prefixes on the wire are trusted only up to these limits so a
+/// malicious or corrupt peer cannot make the decoder allocate arbitrarily
+/// large buffers. Violations are reported as `Error::FrameTooLarge` or
+/// `Error::LimitExceeded` before any buffer for the offending field is
+/// allocated.
+///
+/// ```rust
+/// use mux::codec::{self, DecoderConfig, Error};
+///
+/// let config = DecoderConfig { max_frame_size: 16, ..DecoderConfig::default() };
+/// let mut r: &[u8] = &[0,0,0,32,65,0,0,1];
+/// match codec::read_message_with(&mut r, &config) {
+///     Err(Error::FrameTooLarge { size: 32, max: 16 }) => (),
+///     other => panic!("Unexpected result: {:?}", other),
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecoderConfig {
+    /// Maximum size of a frame, excluding its 4 byte length prefix.
+    pub max_frame_size: usize,
+    /// Maximum number of context entries of a `Tdispatch` or `Rdispatch`.
+    pub max_contexts: usize,
+    /// Maximum number of `Dentry`s in the `Dtab` of a `Tdispatch`.
+    pub max_dtab_entries: usize,
+    /// Maximum total size of the header keys and values of a `Tinit` or `Rinit`.
+    pub max_init_header_size: usize,
+    /// Maximum total size of the incomplete fragment sequences buffered by a
+    /// `Reassembler`, across all tags.
+    pub max_pending_fragments_size: usize,
+    /// Decode frames of unknown type as `MessageFrame::Unknown` rather than
+    /// failing with `Error::UnknownFrameType`.
+    pub allow_unknown_frames: bool,
+}
+
+impl Default for DecoderConfig {
+    fn default() -> DecoderConfig {
+        DecoderConfig {
+            max_frame_size: 16 * 1024 * 1024,
+            max_contexts: 1024,
+            max_dtab_entries: 1024,
+            max_init_header_size: 64 * 1024,
+            max_pending_fragments_size: 64 * 1024 * 1024,
+            allow_unknown_frames: false,
+        }
+    }
+}
+
+impl DecoderConfig {
+    /// A `DecoderConfig` that doesn't limit anything beyond the wire format.
+    pub fn unlimited() -> DecoderConfig {
+        DecoderConfig {
+            max_frame_size: i32::MAX as usize,
+            max_contexts: usize::MAX,
+            max_dtab_entries: usize::MAX,
+            max_init_header_size: usize::MAX,
+            max_pending_fragments_size: usize::MAX,
+            allow_unknown_frames: false,
+        }
+    }
+}
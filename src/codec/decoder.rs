@@ -0,0 +1,106 @@
+use byteorder::{BigEndian, ByteOrder};
+
+use super::{decode_message_with, DecoderConfig, Error, Result};
+use super::super::Message;
+
+// the frame length prefix is a big endian i32
+const LENGTH_PREFIX_SIZE: usize = 4;
+
+/// Incremental decoder of mux `Message`s from partial buffers
+///
+/// Unlike `read_message`, the `FrameDecoder` never blocks: bytes are handed to
+/// it in arbitrarily sized chunks as they arrive, for example from a
+/// non-blocking socket, and whole `Message`s are returned as soon as they have
+/// been framed. Bytes belonging to incomplete frames are retained between
+/// calls. Frames larger than the `DecoderConfig::max_frame_size` are rejected
+/// as soon as their length prefix arrives, before they are buffered.
+///
+/// ```rust
+/// use mux::MessageFrame;
+/// use mux::codec::FrameDecoder;
+///
+/// let mut decoder = FrameDecoder::new();
+/// // a ping frame split inside the tag
+/// assert!(decoder.decode(&[0,0,0,4,65,0]).unwrap().is_none());
+/// let msg = decoder.decode(&[0,1]).unwrap().unwrap();
+/// assert_eq!(msg.frame, MessageFrame::Tping);
+/// ```
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+    // start of the undecoded data in `buffer`
+    pos: usize,
+    config: DecoderConfig,
+}
+
+impl FrameDecoder {
+    /// Construct a new `FrameDecoder` with an empty buffer.
+    pub fn new() -> FrameDecoder {
+        FrameDecoder::default()
+    }
+
+    /// Construct a new `FrameDecoder` enforcing the limits of `config`.
+    pub fn with_config(config: DecoderConfig) -> FrameDecoder {
+        FrameDecoder {
+            buffer: Vec::new(),
+            pos: 0,
+            config,
+        }
+    }
+
+    /// Buffer the `bytes` and attempt to decode the next `Message`
+    ///
+    /// Returns `Ok(None)` if more bytes are needed to complete the next frame.
+    /// Only a single `Message` is returned per call so if `bytes` completed
+    /// several frames the remainder can be collected by calling `decode` with
+    /// an empty slice until it returns `Ok(None)`.
+    ///
+    /// A frame which fails to decode is consumed before the error is returned
+    /// so decoding may continue with the following frame. An invalid frame
+    /// size leaves the stream unframed and the buffer untouched.
+    pub fn decode(&mut self, bytes: &[u8]) -> Result<Option<Message>> {
+        self.buffer.extend_from_slice(bytes);
+
+        let available = &self.buffer[self.pos..];
+        if available.len() < LENGTH_PREFIX_SIZE {
+            return Ok(None);
+        }
+
+        let size = BigEndian::read_i32(&available[..LENGTH_PREFIX_SIZE]);
+        if size < 4 {
+            return Err(Error::FrameTooSmall(size));
+        } else if size as usize > self.config.max_frame_size {
+            return Err(Error::FrameTooLarge {
+                size: size as usize,
+                max: self.config.max_frame_size,
+            });
+        }
+
+        let end = LENGTH_PREFIX_SIZE + size as usize;
+        if available.len() < end {
+            return Ok(None);
+        }
+
+        let result = decode_message_with(&available[LENGTH_PREFIX_SIZE..end], &self.config);
+        self.consume(end);
+        result.map(Some)
+    }
+
+    /// Number of bytes buffered that have not yet been decoded.
+    #[inline]
+    pub fn buffered(&self) -> usize {
+        self.buffer.len() - self.pos
+    }
+
+    // advance past a decoded frame, reclaiming the space once it is all consumed
+    fn consume(&mut self, n: usize) {
+        self.pos += n;
+        if self.pos == self.buffer.len() {
+            self.buffer.clear();
+            self.pos = 0;
+        } else if self.pos > self.buffer.len() / 2 {
+            self.buffer.drain(..self.pos);
+            self.pos = 0;
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+use super::super::{DtabError, TagError};
+
+/// Result of the codec functions.
+pub type Result<T> = result::Result<T, Error>;
+
+/// Errors produced while encoding or decoding mux frames.
+///
+/// Protocol violations are reported by their own variant so that they can be
+/// told apart from failures of the underlying stream, which are wrapped in
+/// `Error::Io`. A truncated frame surfaces as an `Io` error of kind
+/// `UnexpectedEof`.
+#[derive(Debug)]
+pub enum Error {
+    /// The frame type is not a known mux message type.
+    UnknownFrameType(i8),
+    /// The status of an `Rreq` or `Rdispatch` is not Ok, Error or Nack.
+    InvalidRmsgStatus(u8),
+    /// A string field did not contain valid UTF8.
+    InvalidUtf8 {
+        /// Name of the offending field.
+        field: &'static str,
+    },
+    /// A field is too long to be represented by its length prefix.
+    LengthOverflow {
+        /// Name of the offending field.
+        field: &'static str,
+        /// Length of the field.
+        len: usize,
+        /// Maximum length of the field.
+        max: usize,
+    },
+    /// The frame size prefix is less than the 4 bytes of type and tag.
+    FrameTooSmall(i32),
+    /// The frame size prefix exceeds `DecoderConfig::max_frame_size`.
+    FrameTooLarge {
+        /// Size announced by the frame.
+        size: usize,
+        /// Maximum frame size.
+        max: usize,
+    },
+    /// A decoded field exceeds a limit of the `DecoderConfig`.
+    LimitExceeded {
+        /// Name of the limited field.
+        field: &'static str,
+        /// Length announced on the wire.
+        len: usize,
+        /// Configured maximum.
+        max: usize,
+    },
+    /// A fragment doesn't continue the sequence of its tag or is of a frame
+    /// type that cannot be fragmented.
+    InvalidFragment {
+        /// Frame type of the fragment.
+        tpe: i8,
+        /// Tag id of the fragment.
+        id: u32,
+    },
+    /// The 'howmuch' unit of a `Tlease` is not known. Such leases decode as
+    /// `MessageFrame::Unknown` within a message.
+    UnknownLeaseUnit(u8),
+    /// A tag id is out of range.
+    Tag(TagError),
+    /// The prefix or destination of a `Dentry` doesn't parse.
+    Dtab(DtabError),
+    /// Failure of the underlying `Read` or `Write`.
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnknownFrameType(tpe) => write!(f, "Invalid frame type: {}", tpe),
+            Error::InvalidRmsgStatus(status) => write!(f, "Invalid status code: {}", status),
+            Error::InvalidUtf8 { field } => write!(f, "Invalid UTF8 field: {}", field),
+            Error::LengthOverflow { field, len, max } => {
+                write!(f, "Length overflow of {}: {} exceeds {}", field, len, max)
+            }
+            Error::FrameTooSmall(size) => {
+                write!(f, "Invalid mux frame size: {}. Minimum 4 bytes.", size)
+            }
+            Error::FrameTooLarge { size, max } => {
+                write!(f, "Mux frame size {} exceeds the maximum of {}", size, max)
+            }
+            Error::LimitExceeded { field, len, max } => {
+                write!(f, "Limit of {} exceeded: {} exceeds {}", field, len, max)
+            }
+            Error::InvalidFragment { tpe, id } => {
+                write!(f, "Invalid fragment of type {} for tag {}", tpe, id)
+            }
+            Error::UnknownLeaseUnit(unit) => write!(f, "Unknown Tlease 'howmuch' code: {}", unit),
+            Error::Tag(ref e) => e.fmt(f),
+            Error::Dtab(ref e) => e.fmt(f),
+            Error::Io(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Tag(ref e) => Some(e),
+            Error::Dtab(ref e) => Some(e),
+            Error::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<TagError> for Error {
+    fn from(err: TagError) -> Error {
+        Error::Tag(err)
+    }
+}
+
+impl From<DtabError> for Error {
+    fn from(err: DtabError) -> Error {
+        Error::Dtab(err)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        match err {
+            Error::Io(e) => e,
+            Error::UnknownFrameType(_) | Error::LengthOverflow { .. } | Error::Tag(_) => {
+                io::Error::new(io::ErrorKind::InvalidInput, err)
+            }
+            other => io::Error::new(io::ErrorKind::InvalidData, other),
+        }
+    }
+}
@@ -0,0 +1,262 @@
+// Splitting of large dispatch messages into fragments and their reassembly.
+//
+// A fragmented message is sent as a sequence of frames sharing the type and
+// tag id of the message. Each carries the next chunk of the encoded frame
+// body and all but the last have the `end` flag of their tag cleared.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use super::super::*;
+use super::{decode_frame_with, decode_tag, encode_frame, encode_tag, size};
+use super::{DecoderConfig, Error, Result};
+
+/// A single frame of a possibly fragmented message.
+///
+/// The `body` is the raw chunk of the encoded frame body carried by this
+/// fragment. Only the final fragment of a sequence has `tag.end` set.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Fragment {
+    /// Frame type of the fragmented message.
+    pub tpe: i8,
+    /// Tag of the fragment.
+    pub tag: Tag,
+    /// Chunk of the encoded frame body.
+    pub body: Vec<u8>,
+}
+
+/// Splits `Tdispatch` and `Rdispatch` messages into `Fragment`s
+///
+/// ```rust
+/// use mux::{Message, MessageFrame, Tag, Tdispatch};
+/// use mux::codec::Fragmenter;
+///
+/// let msg = Message {
+///     tag: Tag::new(true, 1),
+///     frame: MessageFrame::Tdispatch(Tdispatch::new("/foo".to_string(), vec![0; 100])),
+/// };
+/// let fragments = Fragmenter::new(32).fragment(&msg).unwrap();
+/// assert_eq!(fragments.len(), 4);
+/// assert!(fragments.iter().take(3).all(|f| !f.tag.end));
+/// assert!(fragments[3].tag.end);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Fragmenter {
+    fragment_size: usize,
+}
+
+impl Fragmenter {
+    /// Construct a new `Fragmenter` producing fragment bodies of at most
+    /// `fragment_size` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fragment_size` is 0.
+    pub fn new(fragment_size: usize) -> Fragmenter {
+        assert!(fragment_size > 0);
+        Fragmenter { fragment_size }
+    }
+
+    /// Maximum size of the body of a fragment.
+    #[inline]
+    pub fn fragment_size(&self) -> usize {
+        self.fragment_size
+    }
+
+    /// Split `msg` into the ordered sequence of its `Fragment`s
+    ///
+    /// Only `Tdispatch` and `Rdispatch` messages may be fragmented, all other
+    /// messages and dispatch messages small enough to fit a single fragment
+    /// result in exactly one `Fragment` with the tag of `msg`. Fails with
+    /// `Error::Tag` if a fragmented message has an id above `MAX_TAG`.
+    pub fn fragment(&self, msg: &Message) -> Result<Vec<Fragment>> {
+        let tpe = msg.frame.frame_id();
+        let mut body = Vec::with_capacity(size::frame_size(&msg.frame));
+        encode_frame(&mut body, &msg.frame)?;
+
+        if !is_fragmentable(tpe) || body.len() <= self.fragment_size {
+            return Ok(vec![Fragment {
+                tpe,
+                tag: msg.tag.clone(),
+                body,
+            }]);
+        }
+
+        let count = body.len().div_ceil(self.fragment_size);
+        body.chunks(self.fragment_size).enumerate().map(|(i, chunk)| {
+            Ok(Fragment {
+                tpe,
+                tag: Tag::try_new(i + 1 == count, msg.tag.id)?,
+                body: chunk.to_vec(),
+            })
+        }).collect()
+    }
+}
+
+/// Reassembles `Fragment`s into whole `Message`s
+///
+/// Fragments of different tags may be interleaved. The fragments of each tag
+/// are buffered until the final one arrives at which point the message is
+/// decoded and returned. A sequence is abandoned, without error, when an
+/// `Rerr` arrives on its tag or a `Tdiscarded` for its id. The terminating
+/// message is returned in place of the fragmented one.
+///
+/// The buffered size of each message is limited by
+/// `DecoderConfig::max_frame_size` and the size of all incomplete messages by
+/// `DecoderConfig::max_pending_fragments_size`.
+#[derive(Debug, Default, Clone)]
+pub struct Reassembler {
+    pending: HashMap<u32, (i8, Vec<u8>)>,
+    buffered: usize,
+    config: DecoderConfig,
+}
+
+impl Reassembler {
+    /// Construct a new `Reassembler` without any pending fragments.
+    pub fn new() -> Reassembler {
+        Reassembler::default()
+    }
+
+    /// Construct a new `Reassembler` enforcing the limits of `config`.
+    pub fn with_config(config: DecoderConfig) -> Reassembler {
+        Reassembler {
+            pending: HashMap::new(),
+            buffered: 0,
+            config,
+        }
+    }
+
+    /// Add the next `Fragment` of a tag
+    ///
+    /// Returns the `Message` if `fragment` completed one, otherwise `Ok(None)`.
+    /// A fragment that doesn't continue the sequence of its tag drops the
+    /// sequence and fails with `Error::InvalidFragment`.
+    pub fn push(&mut self, fragment: Fragment) -> Result<Option<Message>> {
+        let Fragment { tpe, tag, body } = fragment;
+
+        if !is_fragmentable(tpe) {
+            if !tag.end {
+                self.abandon(tag.id);
+                return Err(Error::InvalidFragment { tpe, id: tag.id });
+            }
+
+            let frame = decode_frame_with(tpe, &body[..], &self.config)?;
+            match frame {
+                MessageFrame::Rerr(_) => {
+                    self.abandon(tag.id);
+                }
+                MessageFrame::Tdiscarded(ref t) => {
+                    self.abandon(t.id);
+                }
+                _ => (),
+            }
+            return Ok(Some(Message { tag, frame }));
+        }
+
+        let mut buffer = match self.abandon(tag.id) {
+            Some((pending_tpe, _)) if pending_tpe != tpe => {
+                return Err(Error::InvalidFragment { tpe, id: tag.id });
+            }
+            Some((_, buffer)) => buffer,
+            None if tag.end => {
+                // unfragmented message, no need to buffer it
+                let frame = decode_frame_with(tpe, &body[..], &self.config)?;
+                return Ok(Some(Message { tag, frame }));
+            }
+            None => Vec::new(),
+        };
+
+        let size = buffer.len() + body.len();
+        if size > self.config.max_frame_size {
+            return Err(Error::FrameTooLarge {
+                size,
+                max: self.config.max_frame_size,
+            });
+        }
+        buffer.extend_from_slice(&body);
+
+        if tag.end {
+            let frame = decode_frame_with(tpe, &buffer[..], &self.config)?;
+            Ok(Some(Message { tag, frame }))
+        } else {
+            let buffered = self.buffered + buffer.len();
+            if buffered > self.config.max_pending_fragments_size {
+                return Err(Error::LimitExceeded {
+                    field: "pending fragment size",
+                    len: buffered,
+                    max: self.config.max_pending_fragments_size,
+                });
+            }
+            self.buffered = buffered;
+            self.pending.insert(tag.id, (tpe, buffer));
+            Ok(None)
+        }
+    }
+
+    // drop the incomplete sequence of `id`, returning it
+    fn abandon(&mut self, id: u32) -> Option<(i8, Vec<u8>)> {
+        let pending = self.pending.remove(&id);
+        if let Some((_, ref buffer)) = pending {
+            self.buffered -= buffer.len();
+        }
+        pending
+    }
+
+    /// Number of tags with an incomplete sequence of fragments.
+    #[inline]
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+/// Synchronously read a single `Fragment` with its frame size
+///
+/// Unlike `read_message` the frame body is not decoded, so the non-final
+/// fragments of a message can be read. The frame size limit of the default
+/// `DecoderConfig` is enforced.
+pub fn read_fragment<R: Read + ?Sized>(input: &mut R) -> Result<Fragment> {
+    read_fragment_with(input, &DecoderConfig::default())
+}
+
+/// Synchronously read a single `Fragment` enforcing the frame size limit of
+/// `config`
+pub fn read_fragment_with<R: Read + ?Sized>(input: &mut R, config: &DecoderConfig)
+    -> Result<Fragment>
+{
+    let size = input.read_i32::<BigEndian>()?;
+    if size < 4 {
+        return Err(Error::FrameTooSmall(size));
+    } else if size as usize > config.max_frame_size {
+        return Err(Error::FrameTooLarge {
+            size: size as usize,
+            max: config.max_frame_size,
+        });
+    }
+
+    let tpe = input.read_i8()?;
+    let tag = decode_tag(input)?;
+    let mut body = vec![0; size as usize - 4];
+    input.read_exact(&mut body)?;
+
+    Ok(Fragment {
+        tpe,
+        tag,
+        body,
+    })
+}
+
+/// Synchronously encode a `Fragment` to the `Write` with the frame size
+pub fn write_fragment<W: Write + ?Sized>(buffer: &mut W, fragment: &Fragment) -> Result<()> {
+    buffer.write_i32::<BigEndian>(fragment.body.len() as i32 + 4)?;
+    buffer.write_i8(fragment.tpe)?;
+    encode_tag(buffer, &fragment.tag)?;
+    buffer.write_all(&fragment.body)?;
+    Ok(())
+}
+
+#[inline]
+fn is_fragmentable(tpe: i8) -> bool {
+    tpe == types::TDISPATCH || tpe == types::RDISPATCH
+}
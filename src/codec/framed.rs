@@ -0,0 +1,97 @@
+// `tokio_util` codec framing mux messages on async streams.
+
+use std::io;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use super::super::*;
+use super::{decode_message_ref_with, decode_tag, size, split_frame_with, write_message};
+use super::{DecoderConfig, Fragment, Reassembler, Result};
+
+/// `Decoder` and `Encoder` of length prefixed mux `Message`s
+///
+/// Wrap an async stream in a `tokio_util::codec::Framed` with a `MuxCodec`
+/// to get a `Stream` of `io::Result<Message>` and a `Sink<Message>`. The
+/// limits of the `DecoderConfig` are enforced on the decoded frames, an
+/// oversized frame is rejected as soon as its length prefix arrives. A
+/// `reassembling` codec puts fragmented messages back together, otherwise
+/// each fragment must decode on its own.
+///
+/// ```rust
+/// use bytes::BytesMut;
+/// use tokio_util::codec::Decoder;
+/// use mux::MessageFrame;
+/// use mux::codec::MuxCodec;
+///
+/// let mut buf = BytesMut::from(&[0,0,0,4,65,0,0,1][..]);
+/// let msg = MuxCodec::new().decode(&mut buf).unwrap().unwrap();
+/// assert_eq!(msg.frame, MessageFrame::Tping);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct MuxCodec {
+    config: DecoderConfig,
+    reassembler: Option<Reassembler>,
+}
+
+impl MuxCodec {
+    /// Construct a new `MuxCodec` enforcing the default `DecoderConfig`.
+    pub fn new() -> MuxCodec {
+        MuxCodec::default()
+    }
+
+    /// Construct a new `MuxCodec` enforcing the limits of `config`.
+    pub fn with_config(config: DecoderConfig) -> MuxCodec {
+        MuxCodec { config, reassembler: None }
+    }
+
+    /// Reassemble fragmented messages with a `Reassembler` enforcing the
+    /// limits of the codec.
+    pub fn reassembling(mut self) -> MuxCodec {
+        self.reassembler = Some(Reassembler::with_config(self.config));
+        self
+    }
+}
+
+impl Decoder for MuxCodec {
+    type Item = Message;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Message>> {
+        loop {
+            let frame = match split_frame_with(src, &self.config)? {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+
+            let reassembler = match self.reassembler {
+                Some(ref mut reassembler) if reassembler.pending() > 0 || frame[1] & 0x80 != 0 => {
+                    reassembler
+                }
+                // nothing to continue or abandon, decode the frame as is
+                _ => return Ok(Some(decode_message_ref_with(&frame, &self.config)?.into_owned())),
+            };
+
+            if let Some(msg) = reassembler.push(split_fragment(frame)?)? {
+                return Ok(Some(msg));
+            }
+        }
+    }
+}
+
+// the type and tag of a frame split off a read buffer, with its body
+pub(crate) fn split_fragment(mut frame: Bytes) -> Result<Fragment> {
+    let tpe = frame.get_i8();
+    let tag = decode_tag(&mut &frame[..3])?;
+    Ok(Fragment { tpe, tag, body: frame[3..].to_vec() })
+}
+
+impl Encoder<Message> for MuxCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, msg: Message, dst: &mut BytesMut) -> io::Result<()> {
+        dst.reserve(4 + 4 + size::frame_size(&msg.frame));
+        write_message(&mut dst.writer(), &msg)?;
+        Ok(())
+    }
+}
@@ -2,30 +2,69 @@
 //!
 //! Decode messages from a continuous stream using `read_message` to
 //! address message framing. Individual message decoding functions assume the
-//! remainder of the stream comprises the message.
+//! remainder of the stream comprises the message. For non-blocking streams,
+//! feed the received bytes to a `FrameDecoder` instead. When the whole frame
+//! is already in memory, `decode_message_ref` decodes it without copying the
+//! payloads. With the `bytes` feature, `split_frame` frames messages
+//! directly from a `BytesMut` read buffer, sharing it with the frames it
+//! splits off. Large dispatch messages are split into fragments by a
+//! `Fragmenter` and put back together by a `Reassembler`.
+//! The `tokio` feature provides `MuxCodec` for framing async streams.
 
 extern crate byteorder;
 
 use byteorder::{ReadBytesExt, BigEndian, WriteBytesExt};
 
-use std::io;
-use std::io::{ErrorKind, Read, Write};
+use std::io::{Read, Write};
 use std::time::Duration;
 
 use super::*;
 
-use std::{u8, u16};
-
 pub mod size;
+mod borrowed;
+mod config;
+mod decoder;
+mod error;
+#[cfg(feature = "tokio")]
+mod framed;
+mod fragment;
+#[cfg(feature = "bytes")]
+mod shared;
+
+pub use self::borrowed::*;
+pub use self::config::DecoderConfig;
+pub use self::decoder::FrameDecoder;
+pub use self::error::{Error, Result};
+pub use self::fragment::*;
+#[cfg(feature = "tokio")]
+pub use self::framed::MuxCodec;
+#[cfg(feature = "tokio")]
+pub(crate) use self::framed::split_fragment;
+#[cfg(feature = "bytes")]
+pub use self::shared::*;
+
+// concise checking of decoded lengths against the `DecoderConfig` limits
+macro_rules! chklimit {
+    ($len:expr, $max:expr, $field:expr) => {
+        if $len > $max {
+            return Err(Error::LimitExceeded {
+                field: $field,
+                len: $len,
+                max: $max,
+            });
+        }
+    };
+}
 
 // concise length checking for encoding length delimited fields
 macro_rules! chklen {
-    ($e:expr, $len:expr) => ({
-        chklen!($e, $len, "Length overflow.")
-    });
-    ($e:expr, $len:expr, $msg:expr) => {
+    ($e:expr, $len:expr, $field:expr) => {
         if $e.len() > $len as usize {
-            return Err(io::Error::new(ErrorKind::InvalidInput, $msg));
+            return Err(Error::LengthOverflow {
+                field: $field,
+                len: $e.len(),
+                max: $len as usize,
+            });
         }
     };
 }
@@ -33,7 +72,8 @@ macro_rules! chklen {
 /// Synchronously read a whole mux `Message`
 ///
 /// This function will synchronously read from the provided `&mut Read` until
-/// it has received an entire mux `Message`
+/// it has received an entire mux `Message`. The limits of the default
+/// `DecoderConfig` are enforced, see `read_message_with`.
 ///
 /// ```rust
 /// use std::io::Cursor;
@@ -44,18 +84,32 @@ macro_rules! chklen {
 /// let frame = codec::read_message(&mut r).unwrap().frame;
 /// assert_eq!(frame, MessageFrame::Tping);
 /// ```
-pub fn read_message<R: Read + ?Sized>(input: &mut R) -> io::Result<Message> {
+pub fn read_message<R: Read + ?Sized>(input: &mut R) -> Result<Message> {
+    read_message_with(input, &DecoderConfig::default())
+}
+
+/// Synchronously read a whole mux `Message` enforcing the limits of `config`
+///
+/// A frame size beyond `config.max_frame_size` is rejected before any of the
+/// frame is read.
+pub fn read_message_with<R: Read + ?Sized>(input: &mut R, config: &DecoderConfig)
+    -> Result<Message>
+{
     let size = {
-        let size = try!(input.read_i32::<BigEndian>());
+        let size = input.read_i32::<BigEndian>()?;
         if size < 4 {
-            let msg = format!("Invalid mux frame size: {}. Minimum 4 bytes.", size);
-            return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+            return Err(Error::FrameTooSmall(size));
+        } else if size as usize > config.max_frame_size {
+            return Err(Error::FrameTooLarge {
+                size: size as usize,
+                max: config.max_frame_size,
+            });
         }
 
         size as u64
     };
 
-    decode_message(input.take(size))
+    decode_message_with(input.take(size), config)
 }
 
 /// Synchronously decode a mux `Message`
@@ -63,6 +117,8 @@ pub fn read_message<R: Read + ?Sized>(input: &mut R) -> io::Result<Message> {
 /// This function will synchronously read from the provided `&mut Read` until
 /// it has decoded a message. Message length is assumed to be triggered by an EOF.
 /// When using use a continuous stream, such as a `TcpStream`, use `read_message`.
+/// The limits of the default `DecoderConfig` are enforced, see
+/// `decode_message_with`.
 ///
 /// ```rust
 /// use std::io::Cursor;
@@ -73,14 +129,38 @@ pub fn read_message<R: Read + ?Sized>(input: &mut R) -> io::Result<Message> {
 /// let frame = codec::decode_message(&mut r).unwrap().frame;
 /// assert_eq!(frame, MessageFrame::Tping);
 /// ```
-pub fn decode_message<R: Read>(mut read: R) -> io::Result<Message> {
-    let tpe = try!(read.read_i8());
-    let tag = try!(decode_tag(&mut read));
-    let frame = try!(decode_frame(tpe, &mut read));
+pub fn decode_message<R: Read>(read: R) -> Result<Message> {
+    decode_message_with(read, &DecoderConfig::default())
+}
+
+/// Synchronously decode a mux `Message` enforcing the limits of `config`
+///
+/// The per-field limits are checked before the fields are read. As the
+/// frame size is determined by EOF, at most `config.max_frame_size` bytes
+/// and one more are read: a frame reaching past the limit fails with an
+/// `Error::FrameTooLarge` whose `size` is the number of bytes read.
+pub fn decode_message_with<R: Read>(read: R, config: &DecoderConfig) -> Result<Message> {
+    let mut read = read.take((config.max_frame_size as u64).saturating_add(1));
+    let result = decode_frame_message(&mut read, config);
+
+    // the frame may fail to decode because it was cut at the limit
+    if read.limit() == 0 {
+        return Err(Error::FrameTooLarge {
+            size: config.max_frame_size.saturating_add(1),
+            max: config.max_frame_size,
+        });
+    }
+    result
+}
+
+fn decode_frame_message<R: Read>(mut read: R, config: &DecoderConfig) -> Result<Message> {
+    let tpe = read.read_i8()?;
+    let tag = decode_tag(&mut read)?;
+    let frame = decode_frame_with(tpe, &mut read, config)?;
 
     Ok(Message {
-        tag: tag,
-        frame: frame,
+        tag,
+        frame,
     })
 }
 
@@ -99,9 +179,9 @@ pub fn decode_message<R: Read>(mut read: R) -> io::Result<Message> {
 /// let _ = codec::write_message(&mut w, &Message { tag: tag, frame: MessageFrame::Tping });
 /// assert_eq!(w.into_inner(), vec![0,0,0,4,65,0,0,1]);
 /// ```
-pub fn write_message<W: Write + ?Sized>(buffer: &mut W, msg: &Message) -> io::Result<()> {
+pub fn write_message<W: Write + ?Sized>(buffer: &mut W, msg: &Message) -> Result<()> {
     // the size is the buffer size + the header (id + tag)
-    try!(buffer.write_i32::<BigEndian>(size::frame_size(&msg.frame) as i32 + 4));
+    buffer.write_i32::<BigEndian>(size::frame_size(&msg.frame) as i32 + 4)?;
     encode_message(buffer, msg)
 }
 
@@ -120,9 +200,9 @@ pub fn write_message<W: Write + ?Sized>(buffer: &mut W, msg: &Message) -> io::Re
 /// let _ = codec::encode_message(&mut w, &Message { tag: tag, frame: MessageFrame::Tping });
 /// assert_eq!(w.into_inner(), vec![65,0,0,1]);
 /// ```
-pub fn encode_message<W: Write + ?Sized>(buffer: &mut W, msg: &Message) -> io::Result<()> {
-    try!(buffer.write_i8(msg.frame.frame_id()));
-    try!(encode_tag(buffer, &msg.tag));
+pub fn encode_message<W: Write + ?Sized>(buffer: &mut W, msg: &Message) -> Result<()> {
+    buffer.write_i8(msg.frame.frame_id())?;
+    encode_tag(buffer, &msg.tag)?;
     encode_frame(buffer, &msg.frame)
 }
 
@@ -143,7 +223,7 @@ pub fn encode_message<W: Write + ?Sized>(buffer: &mut W, msg: &Message) -> io::R
 /// let _ = codec::encode_frame(&mut w, &MessageFrame::Tping);
 /// assert_eq!(w.into_inner(), vec![]); // Tping is 0 length
 /// ```
-pub fn encode_frame<W: Write + ?Sized>(writer: &mut W, frame: &MessageFrame) -> io::Result<()> {
+pub fn encode_frame<W: Write + ?Sized>(writer: &mut W, frame: &MessageFrame) -> Result<()> {
     match *frame {
         MessageFrame::Treq(ref f) => encode_treq(writer, f),
         MessageFrame::Rreq(ref f) => encode_rreq(writer, f),
@@ -156,9 +236,14 @@ pub fn encode_frame<W: Write + ?Sized>(writer: &mut W, frame: &MessageFrame) ->
         MessageFrame::Rping => Ok(()),
         MessageFrame::Tdrain => Ok(()),
         MessageFrame::Rdrain => Ok(()),
+        MessageFrame::Rdiscarded => Ok(()),
         MessageFrame::Tdiscarded(ref f) => encode_tdiscarded(writer, f),
         MessageFrame::Tlease(ref f) => encode_tlease(writer, f),
         MessageFrame::Rerr(ref f) => encode_rerr(writer, f),
+        MessageFrame::Unknown { ref body, .. } => {
+            writer.write_all(body)?;
+            Ok(())
+        }
     }
 }
 
@@ -180,25 +265,49 @@ pub fn encode_frame<W: Write + ?Sized>(writer: &mut W, frame: &MessageFrame) ->
 /// let frame = codec::decode_frame(types::TPING, &mut r).unwrap();
 /// assert_eq!(frame, MessageFrame::Tping);
 /// ```
-pub fn decode_frame<R: Read>(tpe: i8, reader: R) -> io::Result<MessageFrame> {
+pub fn decode_frame<R: Read>(tpe: i8, reader: R) -> Result<MessageFrame> {
+    decode_frame_with(tpe, reader, &DecoderConfig::default())
+}
+
+/// Synchronously decode a mux `MessageFrame` enforcing the limits of `config`
+pub fn decode_frame_with<R: Read>(tpe: i8, mut reader: R, config: &DecoderConfig)
+    -> Result<MessageFrame>
+{
     Ok(match tpe {
-        types::TREQ => MessageFrame::Treq(try!(decode_treq(reader))),
-        types::RREQ => MessageFrame::Rreq(try!(decode_rreq(reader))),
-        types::TDISPATCH => MessageFrame::Tdispatch(try!(decode_tdispatch(reader))),
-        types::RDISPATCH => MessageFrame::Rdispatch(try!(decode_rdispatch(reader))),
-        types::TINIT => MessageFrame::Tinit(try!(decode_init(reader))),
-        types::RINIT => MessageFrame::Rinit(try!(decode_init(reader))),
+        types::TREQ => MessageFrame::Treq(decode_treq(reader)?),
+        types::RREQ => MessageFrame::Rreq(decode_rreq(reader)?),
+        types::TDISPATCH => MessageFrame::Tdispatch(decode_tdispatch_with(reader, config)?),
+        types::RDISPATCH => MessageFrame::Rdispatch(decode_rdispatch_with(reader, config)?),
+        types::TINIT => MessageFrame::Tinit(decode_init_with(reader, config)?),
+        types::RINIT => MessageFrame::Rinit(decode_init_with(reader, config)?),
         types::TDRAIN => MessageFrame::Tdrain,
         types::RDRAIN => MessageFrame::Rdrain,
         types::TPING => MessageFrame::Tping,
         types::RPING => MessageFrame::Rping,
-        types::TLEASE => MessageFrame::Tlease(try!(decode_tlease(reader))),
-        types::RERR => MessageFrame::Rerr(try!(decode_rerr(reader))),
+        types::TDISCARDED | types::BAD_TDISCARDED => {
+            MessageFrame::Tdiscarded(decode_tdiscarded(reader)?)
+        }
+        types::RDISCARDED => MessageFrame::Rdiscarded,
+        types::TLEASE => {
+            let mut body = Vec::new();
+            let _ = reader.read_to_end(&mut body)?;
+            if is_unknown_lease_unit(&body) {
+                MessageFrame::Unknown { tpe, body }
+            } else {
+                MessageFrame::Tlease(decode_tlease(&body[..])?)
+            }
+        }
+        types::RERR => MessageFrame::Rerr(decode_rerr(reader)?),
+        other if config.allow_unknown_frames => {
+            let mut body = Vec::new();
+            let _ = reader.read_to_end(&mut body)?;
+            MessageFrame::Unknown {
+                tpe: other,
+                body,
+            }
+        }
         other => {
-            return Err(
-                io::Error::new(io::ErrorKind::InvalidInput,
-                    format!("Invalid frame type: {}", other))
-                );
+            return Err(Error::UnknownFrameType(other));
         }
     })
 }
@@ -206,52 +315,64 @@ pub fn decode_frame<R: Read>(tpe: i8, reader: R) -> io::Result<MessageFrame> {
 
 ///////////// Tlease codec function
 
-pub fn decode_tlease<R: Read>(mut reader: R) -> io::Result<Tlease> {
-    let howmuch = try!(reader.read_u8());
-    let ticks = try!(reader.read_u64::<BigEndian>());
-
-    if howmuch == 0 {
-        Ok(Tlease {
-            duration: Duration::from_millis(ticks),
-        })
-    } else {
-        let message = format!("Unknown Tlease 'howmuch' code: {}", howmuch);
-        Err(io::Error::new(ErrorKind::InvalidData, message))
-    }
+// 'howmuch' unit of leases in milliseconds
+const LEASE_MILLIS: u8 = 0;
+// milliseconds of an infinite lease
+const INFINITE_LEASE_TICKS: u64 = i64::MAX as u64;
+
+pub fn decode_tlease<R: Read>(mut reader: R) -> Result<Tlease> {
+    let howmuch = reader.read_u8()?;
+    let ticks = reader.read_u64::<BigEndian>()?;
+
+    Ok(match (howmuch, ticks) {
+        (LEASE_MILLIS, INFINITE_LEASE_TICKS) => Tlease::infinite(),
+        (LEASE_MILLIS, ticks) => Tlease { duration: Duration::from_millis(ticks) },
+        (unit, _) => return Err(Error::UnknownLeaseUnit(unit)),
+    })
 }
 
-pub fn encode_tlease<W: Write + ?Sized>(writer: &mut W, tlease: &Tlease) -> io::Result<()> {
-    let d = &tlease.duration;
-    let millis = d.as_secs()*1000 + (((d.subsec_nanos() as f64)/1e6) as u64);
-    try!(writer.write_u8(0));
-    try!(writer.write_u64::<BigEndian>(millis));
+pub fn encode_tlease<W: Write + ?Sized>(writer: &mut W, tlease: &Tlease) -> Result<()> {
+    // durations too long to encode are as good as infinite
+    let d = tlease.duration;
+    let millis = d.as_secs().saturating_mul(1000).saturating_add(d.subsec_millis() as u64);
+
+    writer.write_u8(LEASE_MILLIS)?;
+    writer.write_u64::<BigEndian>(millis.min(INFINITE_LEASE_TICKS))?;
     Ok(())
 }
 
+// whether a Tlease body is in a unit without known semantics, such leases
+// are kept as unknown frames rather than failing the decode
+#[inline]
+fn is_unknown_lease_unit(body: &[u8]) -> bool {
+    body.first().is_some_and(|&unit| unit != LEASE_MILLIS)
+}
+
 ///////////// Tdiscarded codecs
 
 #[inline]
-pub fn decode_tdiscarded<R: Read>(mut reader: R) -> io::Result<Tdiscarded> {
+pub fn decode_tdiscarded<R: Read>(mut reader: R) -> Result<Tdiscarded> {
     let mut bts = [0;3];
-    try!(reader.read_exact(&mut bts[..]));
+    reader.read_exact(&mut bts[..])?;
     let id = (bts[0] as u32) << 16 | (bts[1] as u32) <<  8 | (bts[2] as u32);
-    let msg = try!(body_as_string(reader));
+    let msg = body_as_string(reader, "tdiscarded message")?;
 
     Ok(Tdiscarded {
-        id: id,
-        msg: msg,
+        id,
+        msg,
     })
 }
 
 #[inline]
-pub fn encode_tdiscarded<W: Write + ?Sized>(writer: &mut W, msg: &Tdiscarded) -> io::Result<()> {
+pub fn encode_tdiscarded<W: Write + ?Sized>(writer: &mut W, msg: &Tdiscarded) -> Result<()> {
     let bts = [
         ((msg.id >> 16) & 0xff) as u8,
         ((msg.id >>  8) & 0xff) as u8,
         ( msg.id        & 0xff) as u8,
     ];
-    try!(writer.write_all(&bts[..]));
-    writer.write_all(msg.msg.as_bytes())
+    writer.write_all(&bts[..])?;
+    writer.write_all(msg.msg.as_bytes())?;
+    Ok(())
 }
 
 ///////////// Tag codec functions
@@ -259,9 +380,9 @@ pub fn encode_tdiscarded<W: Write + ?Sized>(writer: &mut W, msg: &Tdiscarded) ->
 const TAG_END_MASK: u32 = 1 << 23; // 24th bit of tag
 const TAG_ID_MASK: u32 = MAX_TAG;
 
-pub fn decode_tag<R: Read + ?Sized>(reader: &mut R) -> io::Result<Tag> {
+pub fn decode_tag<R: Read + ?Sized>(reader: &mut R) -> Result<Tag> {
     let mut bts = [0; 3];
-    let _ = try!(reader.read(&mut bts));
+    reader.read_exact(&mut bts)?;
 
     let id = (bts[0] as u32) << 16 |
              (bts[1] as u32) <<  8 |
@@ -274,43 +395,44 @@ pub fn decode_tag<R: Read + ?Sized>(reader: &mut R) -> io::Result<Tag> {
 }
 
 #[inline]
-pub fn encode_tag<W: Write + ?Sized>(buffer: &mut W, tag: &Tag) -> io::Result<()> {
+pub fn encode_tag<W: Write + ?Sized>(buffer: &mut W, tag: &Tag) -> Result<()> {
     let bytes = {
         let id = tag.id;
         let endbit = if tag.end { 0 } else { 1 };
         [(id >> 16 & 0x7f) as u8 | (endbit << 7),
          (id >> 8 & 0xff) as u8,
-         (id >> 0 & 0xff) as u8]
+         (id & 0xff) as u8]
     };
 
-    buffer.write_all(&bytes)
+    buffer.write_all(&bytes)?;
+    Ok(())
 }
 
 ///////////// headers codec functions
 
-pub fn encode_headers<W: Write + ?Sized>(writer: &mut W, headers: &Headers) -> io::Result<()> {
-    chklen!(headers, u8::MAX, "Header count overflow");
-    try!(writer.write_u8(headers.len() as u8));
+pub fn encode_headers<W: Write + ?Sized>(writer: &mut W, headers: &Headers) -> Result<()> {
+    chklen!(headers, u8::MAX, "header count");
+    writer.write_u8(headers.len() as u8)?;
 
-    for &(ref k, ref v) in headers {
-        chklen!(v, u8::MAX, "Header length overflow");
+    for (k, v) in headers {
+        chklen!(v, u8::MAX, "header value");
 
-        try!(writer.write_u8(*k));
-        try!(writer.write_u8(v.len() as u8));
-        try!(writer.write_all(v));
+        writer.write_u8(*k)?;
+        writer.write_u8(v.len() as u8)?;
+        writer.write_all(v)?;
     }
     Ok(())
 }
 
-pub fn decode_headers<R: Read + ?Sized>(reader: &mut R) -> io::Result<Headers> {
-    let len = try!(reader.read_u8()) as usize;
+pub fn decode_headers<R: Read + ?Sized>(reader: &mut R) -> Result<Headers> {
+    let len = reader.read_u8()? as usize;
     let mut acc = Vec::with_capacity(len);
 
     for _ in 0..len {
-        let key = try!(reader.read_u8());
-        let val_len = try!(reader.read_u8());
+        let key = reader.read_u8()?;
+        let val_len = reader.read_u8()?;
         let mut val = vec![0;val_len as usize];
-        try!(reader.read_exact(&mut val[..]));
+        reader.read_exact(&mut val[..])?;
         acc.push((key, val));
     }
 
@@ -319,36 +441,43 @@ pub fn decode_headers<R: Read + ?Sized>(reader: &mut R) -> io::Result<Headers> {
 
 ///////////// Contexts codec functions
 
-pub fn encode_contexts<W: Write + ?Sized>(writer: &mut W, contexts: &Contexts) -> io::Result<()> {
-    chklen!(contexts, u16::MAX, "Context entries overflow");
+pub fn encode_contexts<W: Write + ?Sized>(writer: &mut W, contexts: &Contexts) -> Result<()> {
+    chklen!(contexts, u16::MAX, "context count");
 
-    try!(writer.write_u16::<BigEndian>(contexts.len() as u16));
-    for &(ref k, ref v) in contexts {
-        chklen!(k, u16::MAX, "Context key overflow");
-        try!(writer.write_u16::<BigEndian>(k.len() as u16));
-        try!(writer.write_all(&k[..]));
+    writer.write_u16::<BigEndian>(contexts.len() as u16)?;
+    for (k, v) in contexts {
+        chklen!(k, u16::MAX, "context key");
+        writer.write_u16::<BigEndian>(k.len() as u16)?;
+        writer.write_all(&k[..])?;
 
-        chklen!(v, u16::MAX, "Context value overflow");
-        try!(writer.write_u16::<BigEndian>(v.len() as u16));
-        try!(writer.write_all(&v[..]));
+        chklen!(v, u16::MAX, "context value");
+        writer.write_u16::<BigEndian>(v.len() as u16)?;
+        writer.write_all(&v[..])?;
     }
 
     Ok(())
 }
 
-pub fn decode_contexts<R: Read + ?Sized>(reader: &mut R) -> io::Result<Contexts> {
-    let len = try!(reader.read_u16::<BigEndian>()) as usize;
+pub fn decode_contexts<R: Read + ?Sized>(reader: &mut R) -> Result<Contexts> {
+    decode_contexts_with(reader, &DecoderConfig::default())
+}
+
+fn decode_contexts_with<R: Read + ?Sized>(reader: &mut R, config: &DecoderConfig)
+    -> Result<Contexts>
+{
+    let len = reader.read_u16::<BigEndian>()? as usize;
+    chklimit!(len, config.max_contexts, "context count");
 
     let mut acc = Vec::with_capacity(len);
 
     for _ in 0..len {
-        let key_len = try!(reader.read_u16::<BigEndian>());
+        let key_len = reader.read_u16::<BigEndian>()?;
         let mut key = vec![0;key_len as usize];
-        try!(reader.read_exact(&mut key[..]));
+        reader.read_exact(&mut key[..])?;
 
-        let val_len = try!(reader.read_u16::<BigEndian>());
+        let val_len = reader.read_u16::<BigEndian>()?;
         let mut val = vec![0;val_len as usize];
-        try!(reader.read_exact(&mut val[..]));
+        reader.read_exact(&mut val[..])?;
         acc.push((key, val));
     }
 
@@ -356,32 +485,42 @@ pub fn decode_contexts<R: Read + ?Sized>(reader: &mut R) -> io::Result<Contexts>
 }
 
 ///////////// Dtab codec functions
-pub fn decode_dtab<R: Read + ?Sized>(reader: &mut R) -> io::Result<Dtab> {
-    let len = try!(reader.read_u16::<BigEndian>()) as usize;
+pub fn decode_dtab<R: Read + ?Sized>(reader: &mut R) -> Result<Dtab> {
+    decode_dtab_with(reader, &DecoderConfig::default())
+}
+
+fn decode_dtab_with<R: Read + ?Sized>(reader: &mut R, config: &DecoderConfig) -> Result<Dtab> {
+    let len = reader.read_u16::<BigEndian>()? as usize;
+    chklimit!(len, config.max_dtab_entries, "dtab entry count");
     let mut acc = Vec::with_capacity(len);
 
     for _ in 0..len {
-        let key_len = try!(reader.read_u16::<BigEndian>());
+        let key_len = reader.read_u16::<BigEndian>()?;
         let mut key = vec![0;key_len as usize];
-        try!(reader.read_exact(&mut key[..]));
+        reader.read_exact(&mut key[..])?;
 
-        let val_len = try!(reader.read_u16::<BigEndian>());
+        let val_len = reader.read_u16::<BigEndian>()?;
         let mut val = vec![0;val_len as usize];
-        try!(reader.read_exact(&mut val[..]));
-        acc.push(Dentry::new(try!(to_string(key)), try!(to_string(val))));
+        reader.read_exact(&mut val[..])?;
+        acc.push(decode_dentry(&to_string(key, "dentry key")?, &to_string(val, "dentry val")?)?);
     }
 
     Ok(Dtab::from_entries(acc))
 }
 
-pub fn encode_dtab<W: Write + ?Sized>(writer: &mut W, table: &Dtab) -> io::Result<()> {
-    chklen!(table.entries, u16::MAX, "Dtable length overflow");
-    try!(writer.write_u16::<BigEndian>(table.entries.len() as u16));
+// entries are parsed as they are decoded so a malformed one fails the frame
+#[inline]
+fn decode_dentry(key: &str, val: &str) -> Result<Dentry> {
+    Ok(Dentry::new(Prefix::read(key)?, NameTree::read(val)?))
+}
+
+pub fn encode_dtab<W: Write + ?Sized>(writer: &mut W, table: &Dtab) -> Result<()> {
+    chklen!(table.entries, u16::MAX, "dtab entry count");
+    writer.write_u16::<BigEndian>(table.entries.len() as u16)?;
 
     for dentry in &table.entries {
-        // the string encoder will check for overflows
-        try!(encode_u16_string(writer, &dentry.key));
-        try!(encode_u16_string(writer, &dentry.val));
+        encode_u16_field(writer, &dentry.prefix.to_string(), "dentry key")?;
+        encode_u16_field(writer, &dentry.dst.to_string(), "dentry val")?;
     }
     Ok(())
 }
@@ -389,145 +528,140 @@ pub fn encode_dtab<W: Write + ?Sized>(writer: &mut W, table: &Dtab) -> io::Resul
 ///////////// Rerr codec functions
 
 #[inline]
-pub fn decode_rerr<R: Read>(reader: R) -> io::Result<Rerr> {
-    let msg = try!(body_as_string(reader));
-    Ok(Rerr { msg: msg, })
+pub fn decode_rerr<R: Read>(reader: R) -> Result<Rerr> {
+    let msg = body_as_string(reader, "rerr message")?;
+    Ok(Rerr { msg, })
 }
 
 #[inline]
-pub fn encode_rerr<W: Write + ?Sized>(writer: &mut W, rerr: &Rerr) -> io::Result<()> {
-    writer.write_all(rerr.msg.as_bytes())
+pub fn encode_rerr<W: Write + ?Sized>(writer: &mut W, rerr: &Rerr) -> Result<()> {
+    writer.write_all(rerr.msg.as_bytes())?;
+    Ok(())
 }
 
 ///////////// Init codec functions
 
-pub fn encode_init<W: Write + ?Sized>(writer: &mut W, msg: &Init) -> io::Result<()> {
-    try!(writer.write_u16::<BigEndian>(msg.version));
+pub fn encode_init<W: Write + ?Sized>(writer: &mut W, msg: &Init) -> Result<()> {
+    writer.write_u16::<BigEndian>(msg.version)?;
 
     // Not going to bother checking for overflow: if a single one of the
     // entries overflows then the entire frame overflows which is not the
     // pervue of this function.
 
-    for &(ref k, ref v) in &msg.headers {
-        try!(writer.write_u32::<BigEndian>(k.len() as u32));
-        try!(writer.write_all(k));
-        try!(writer.write_u32::<BigEndian>(v.len() as u32));
-        try!(writer.write_all(v));
+    for (k, v) in &msg.headers {
+        writer.write_u32::<BigEndian>(k.len() as u32)?;
+        writer.write_all(k)?;
+        writer.write_u32::<BigEndian>(v.len() as u32)?;
+        writer.write_all(v)?;
     }
 
     Ok(())
 }
 
-pub fn decode_init<R: Read>(mut reader: R) -> io::Result<Init> {
-    let mut headers = Vec::new();
-    let version = try!(reader.read_u16::<BigEndian>());
-
-    loop {
-        let klen = match reader.read_u32::<BigEndian>() {
-            Ok(len) => len,
-            Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => {
-                // termination: out of buffer.
-                return Ok(
-                    Init {
-                        version: version,
-                        headers: headers,
-                    }
-                );
-            }
-            Err(other) => { return Err(other); }
-        };
-
-        let mut k = vec![0;klen as usize];
-        try!(reader.read_exact(&mut k));
-
-        let vlen = try!(reader.read_u32::<BigEndian>());
-        let mut v = vec![0;vlen as usize];
-        try!(reader.read_exact(&mut v));
+pub fn decode_init<R: Read>(reader: R) -> Result<Init> {
+    decode_init_with(reader, &DecoderConfig::default())
+}
 
-        headers.push((k, v));
-    }
+fn decode_init_with<R: Read>(mut reader: R, config: &DecoderConfig) -> Result<Init> {
+    // the headers run to the end of the frame, it is decoded like a borrowed
+    // frame so that both agree on how the headers end
+    let mut buf = Vec::new();
+    let _ = reader.read_to_end(&mut buf)?;
+    decode_init_ref_with(&buf, config).map(InitRef::into_owned)
 }
 
 ///////////// Rdispatch codec functions
 
-pub fn encode_rdispatch<W: Write + ?Sized>(writer: &mut W, frame: &Rdispatch) -> io::Result<()> {
+pub fn encode_rdispatch<W: Write + ?Sized>(writer: &mut W, frame: &Rdispatch) -> Result<()> {
     let (status, body) = rmsg_status_body(&frame.msg);
 
-    try!(writer.write_u8(status));
-    try!(encode_contexts(writer, &frame.contexts));
-    writer.write_all(body)
+    writer.write_u8(status)?;
+    encode_contexts(writer, &frame.contexts)?;
+    writer.write_all(body)?;
+    Ok(())
 }
 
 // Expects to consume the whole stream
-pub fn decode_rdispatch<R: Read>(mut reader: R) -> io::Result<Rdispatch> {
-    let status = try!(reader.read_u8());
-    let contexts = try!(decode_contexts(&mut reader));
+pub fn decode_rdispatch<R: Read>(reader: R) -> Result<Rdispatch> {
+    decode_rdispatch_with(reader, &DecoderConfig::default())
+}
+
+fn decode_rdispatch_with<R: Read>(mut reader: R, config: &DecoderConfig) -> Result<Rdispatch> {
+    let status = reader.read_u8()?;
+    let contexts = decode_contexts_with(&mut reader, config)?;
     let mut body = Vec::new();
-    let _ = try!(reader.read_to_end(&mut body));
+    let _ = reader.read_to_end(&mut body)?;
 
     Ok(Rdispatch {
-        contexts: contexts,
-        msg: try!(decode_rmsg_body(status, body)),
+        contexts,
+        msg: decode_rmsg_body(status, body)?,
     })
 }
 
 ///////////// Rreq codec functions
 
-pub fn encode_rreq<W: Write + ?Sized>(writer: &mut W, frame: &Rmsg) -> io::Result<()> {
+pub fn encode_rreq<W: Write + ?Sized>(writer: &mut W, frame: &Rmsg) -> Result<()> {
     let (status, body) = rmsg_status_body(frame);
-    try!(writer.write_u8(status));
-    writer.write_all(body)
+    writer.write_u8(status)?;
+    writer.write_all(body)?;
+    Ok(())
 }
 
-pub fn decode_rreq<R: Read>(mut reader: R) -> io::Result<Rmsg> {
-    let status = try!(reader.read_u8());
+pub fn decode_rreq<R: Read>(mut reader: R) -> Result<Rmsg> {
+    let status = reader.read_u8()?;
     let mut body = Vec::new();
-    try!(reader.read_to_end(&mut body));
+    reader.read_to_end(&mut body)?;
     decode_rmsg_body(status, body)
 }
 
 ///////////// Tdispatch codec functions
 
-pub fn decode_tdispatch<R: Read>(mut reader: R) -> io::Result<Tdispatch> {
-    let contexts = try!(decode_contexts(&mut reader));
-    let dest = try!(decode_u16_string(&mut reader));
-    let dtab = try!(decode_dtab(&mut reader));
+pub fn decode_tdispatch<R: Read>(reader: R) -> Result<Tdispatch> {
+    decode_tdispatch_with(reader, &DecoderConfig::default())
+}
+
+fn decode_tdispatch_with<R: Read>(mut reader: R, config: &DecoderConfig) -> Result<Tdispatch> {
+    let contexts = decode_contexts_with(&mut reader, config)?;
+    let dest = decode_u16_field(&mut reader, "dest")?;
+    let dtab = decode_dtab_with(&mut reader, config)?;
 
     let mut body = Vec::new();
-    let _ = try!(reader.read_to_end(&mut body));
+    let _ = reader.read_to_end(&mut body)?;
 
     Ok(Tdispatch {
-        contexts: contexts,
-        dest: dest,
-        dtab: dtab,
-        body: body,
+        contexts,
+        dest,
+        dtab,
+        body,
     })
 }
 
-pub fn encode_tdispatch<W: Write + ?Sized>(writer: &mut W, msg: &Tdispatch) -> io::Result<()> {
-    try!(encode_contexts(writer, &msg.contexts));
-    try!(encode_u16_string(writer, &msg.dest));
-    try!(encode_dtab(writer, &msg.dtab));
-    writer.write_all(&msg.body)
+pub fn encode_tdispatch<W: Write + ?Sized>(writer: &mut W, msg: &Tdispatch) -> Result<()> {
+    encode_contexts(writer, &msg.contexts)?;
+    encode_u16_field(writer, &msg.dest, "dest")?;
+    encode_dtab(writer, &msg.dtab)?;
+    writer.write_all(&msg.body)?;
+    Ok(())
 }
 
 ///////////// Treq codec functions
 
-pub fn decode_treq<R: Read>(mut reader: R) -> io::Result<Treq> {
-    let headers = try!(decode_headers(&mut reader));
+pub fn decode_treq<R: Read>(mut reader: R) -> Result<Treq> {
+    let headers = decode_headers(&mut reader)?;
     let mut body = Vec::new();
 
-    let _ = try!(reader.read_to_end(&mut body));
+    let _ = reader.read_to_end(&mut body)?;
     Ok(Treq {
-        headers: headers,
-        body: body,
+        headers,
+        body,
     })
 }
 
 #[inline]
-pub fn encode_treq<W: Write + ?Sized>(writer: &mut W, msg: &Treq) -> io::Result<()> {
-    try!(encode_headers(writer, &msg.headers));
-    writer.write_all(&msg.body)
+pub fn encode_treq<W: Write + ?Sized>(writer: &mut W, msg: &Treq) -> Result<()> {
+    encode_headers(writer, &msg.headers)?;
+    writer.write_all(&msg.body)?;
+    Ok(())
 }
 
 #[inline]
@@ -540,14 +674,12 @@ fn rmsg_status_body(msg: &Rmsg) -> (u8, &[u8]) {
 }
 
 #[inline]
-fn decode_rmsg_body(status: u8, body: Vec<u8>) -> io::Result<Rmsg> {
+fn decode_rmsg_body(status: u8, body: Vec<u8>) -> Result<Rmsg> {
     match status {
         0 => Ok(Rmsg::Ok(body)),
-        1 => Ok(Rmsg::Error(try!(to_string(body)))),
-        2 => Ok(Rmsg::Nack(try!(to_string(body)))),
-        other => Err(
-            io::Error::new(ErrorKind::InvalidData, format!("Invalid status code: {}", other))
-        )
+        1 => Ok(Rmsg::Error(to_string(body, "error message")?)),
+        2 => Ok(Rmsg::Nack(to_string(body, "nack message")?)),
+        other => Err(Error::InvalidRmsgStatus(other)),
     }
 }
 
@@ -555,35 +687,46 @@ fn decode_rmsg_body(status: u8, body: Vec<u8>) -> io::Result<Rmsg> {
 
 // decode a utf8 string with length specified by a u16 prefix byte
 #[inline]
-pub fn decode_u16_string<R: Read + ?Sized>(reader: &mut R) -> io::Result<String> {
-    let str_len = try!(reader.read_u16::<BigEndian>());
+pub fn decode_u16_string<R: Read + ?Sized>(reader: &mut R) -> Result<String> {
+    decode_u16_field(reader, "u16 string")
+}
+
+#[inline]
+pub fn encode_u16_string<W: Write + ?Sized>(writer: &mut W, s: &str) -> Result<()> {
+    encode_u16_field(writer, s, "u16 string")
+}
+
+#[inline]
+fn decode_u16_field<R: Read + ?Sized>(reader: &mut R, field: &'static str) -> Result<String> {
+    let str_len = reader.read_u16::<BigEndian>()?;
     let mut s = vec![0; str_len as usize];
 
-    try!(reader.read_exact(&mut s));
+    reader.read_exact(&mut s)?;
 
-    to_string(s)
+    to_string(s, field)
 }
 
 #[inline]
-pub fn encode_u16_string<W: Write + ?Sized>(writer: &mut W, s: &str) -> io::Result<()> {
+fn encode_u16_field<W: Write + ?Sized>(writer: &mut W, s: &str, field: &'static str) -> Result<()> {
     let bytes = s.as_bytes();
 
-    chklen!(bytes, u16::MAX, "16bit length delimited string overflow");
-    try!(writer.write_u16::<BigEndian>(bytes.len() as u16));
-    writer.write_all(bytes)
+    chklen!(bytes, u16::MAX, field);
+    writer.write_u16::<BigEndian>(bytes.len() as u16)?;
+    writer.write_all(bytes)?;
+    Ok(())
 }
 
 #[inline]
-fn to_string(vec: Vec<u8>) -> io::Result<String> {
+fn to_string(vec: Vec<u8>, field: &'static str) -> Result<String> {
     match String::from_utf8(vec) {
         Ok(s) => Ok(s),
-        Err(_) => Err(io::Error::new(ErrorKind::InvalidData, "Invalid UTF8 field")),
+        Err(_) => Err(Error::InvalidUtf8 { field }),
     }
 }
 
 #[inline]
-fn body_as_string<R: Read>(mut reader: R) -> io::Result<String> {
+fn body_as_string<R: Read>(mut reader: R, field: &'static str) -> Result<String> {
     let mut data = Vec::new();
-    let _ = try!(reader.read_to_end(&mut data));
-    to_string(data)
+    let _ = reader.read_to_end(&mut data)?;
+    to_string(data, field)
 }
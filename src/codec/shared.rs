@@ -0,0 +1,115 @@
+// Codec functions operating on `bytes` buffers. Frames split off a read
+// buffer keep sharing it, so their payloads can be looked at through the
+// borrowed views and passed on without being copied.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use super::super::*;
+use super::{decode_message_ref_with, size, write_message, DecoderConfig, Error, Result};
+
+/// Split the next whole frame off the front of `buf`
+///
+/// Returns `Ok(None)` if `buf` does not yet hold a complete frame, in which
+/// case it is left untouched. Otherwise the frame is removed from `buf` and
+/// returned without its length prefix, still sharing the memory of `buf`.
+/// Decode it without copying with `decode_message_ref`, and take shared
+/// handles of its payloads with `Bytes::slice_ref`:
+///
+/// ```rust
+/// extern crate bytes;
+/// extern crate mux;
+///
+/// use bytes::BytesMut;
+/// use mux::{Message, MessageFrame, MessageFrameRef, Rmsg, RmsgRef, Tag};
+/// use mux::codec;
+///
+/// # fn main() {
+/// let mut buf = BytesMut::new();
+/// let frame = MessageFrame::Rreq(Rmsg::Ok(b"hello".to_vec()));
+/// codec::put_message(&mut buf, &Message { tag: Tag::new(true, 1), frame }).unwrap();
+///
+/// let frame = codec::split_frame(&mut buf).unwrap().unwrap();
+/// let body = match codec::decode_message_ref(&frame).unwrap().frame {
+///     MessageFrameRef::Rreq(RmsgRef::Ok(body)) => frame.slice_ref(body),
+///     other => panic!("Unexpected frame: {:?}", other),
+/// };
+/// assert_eq!(&body[..], b"hello");
+/// # }
+/// ```
+///
+/// The frame may be forwarded to another connection as is by writing its
+/// length as a big endian `i32` followed by the frame.
+pub fn split_frame(buf: &mut BytesMut) -> Result<Option<Bytes>> {
+    split_frame_with(buf, &DecoderConfig::default())
+}
+
+/// Split the next whole frame off the front of `buf`, rejecting frames
+/// larger than `config.max_frame_size` as soon as their length is read.
+pub fn split_frame_with(buf: &mut BytesMut, config: &DecoderConfig) -> Result<Option<Bytes>> {
+    if buf.len() < 4 {
+        return Ok(None);
+    }
+
+    let size = (&buf[..4]).get_i32();
+    if size < 4 {
+        return Err(Error::FrameTooSmall(size));
+    } else if size as usize > config.max_frame_size {
+        return Err(Error::FrameTooLarge {
+            size: size as usize,
+            max: config.max_frame_size,
+        });
+    }
+
+    let end = 4 + size as usize;
+    if buf.len() < end {
+        buf.reserve(end - buf.len());
+        return Ok(None);
+    }
+
+    let mut frame = buf.split_to(end);
+    frame.advance(4);
+    Ok(Some(frame.freeze()))
+}
+
+/// Split the next whole `Message` off the front of `buf`
+///
+/// Like `split_frame`, but the frame is decoded into an owned `Message`.
+///
+/// ```rust
+/// extern crate bytes;
+/// extern crate mux;
+///
+/// use bytes::BytesMut;
+/// use mux::MessageFrame;
+/// use mux::codec;
+///
+/// # fn main() {
+/// let mut buf = BytesMut::from(&[0,0,0,4,65,0,0,1,0,0][..]); // ping frame and partial frame
+/// let msg = codec::split_message(&mut buf).unwrap().unwrap();
+/// assert_eq!(msg.frame, MessageFrame::Tping);
+/// assert!(codec::split_message(&mut buf).unwrap().is_none());
+/// # }
+/// ```
+pub fn split_message(buf: &mut BytesMut) -> Result<Option<Message>> {
+    split_message_with(buf, &DecoderConfig::default())
+}
+
+/// Split the next whole `Message` off the front of `buf` enforcing the
+/// limits of `config`
+///
+/// Frames of unknown type are split off as `MessageFrame::Unknown` if
+/// `config.allow_unknown_frames` is set.
+pub fn split_message_with(buf: &mut BytesMut, config: &DecoderConfig)
+    -> Result<Option<Message>>
+{
+    match split_frame_with(buf, config)? {
+        Some(frame) => Ok(Some(decode_message_ref_with(&frame, config)?.into_owned())),
+        None => Ok(None),
+    }
+}
+
+/// Encode a `Message` with its frame size to the end of `buf`
+pub fn put_message(buf: &mut BytesMut, msg: &Message) -> Result<()> {
+    buf.reserve(4 + 4 + size::frame_size(&msg.frame));
+    write_message(&mut buf.writer(), msg)
+}
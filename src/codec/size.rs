@@ -14,9 +14,11 @@ pub fn frame_size(frame: &MessageFrame) -> usize {
         MessageFrame::Rdrain => 0,
         MessageFrame::Tping => 0,
         MessageFrame::Rping => 0,
+        MessageFrame::Rdiscarded => 0,
         MessageFrame::Tlease(_) => 9,
-        MessageFrame::Tdiscarded(ref f) => 3 + f.msg.as_bytes().len(),
-        MessageFrame::Rerr(ref r) => r.msg.as_bytes().len(),
+        MessageFrame::Tdiscarded(ref f) => 3 + f.msg.len(),
+        MessageFrame::Rerr(ref r) => r.msg.len(),
+        MessageFrame::Unknown { ref body, .. } => body.len(),
     }
 }
 
@@ -25,7 +27,7 @@ pub fn tdispatch_size(msg: &Tdispatch) -> usize {
                    context_size(&msg.contexts) +
                    dtab_size(&msg.dtab);
 
-    size += msg.dest.as_bytes().len();
+    size += msg.dest.len();
     size += msg.body.len();
     size
 }
@@ -33,14 +35,14 @@ pub fn tdispatch_size(msg: &Tdispatch) -> usize {
 pub fn rdispatch_size(msg: &Rdispatch) -> usize {
     1 + context_size(&msg.contexts) + match msg.msg {
         Rmsg::Ok(ref body) => body.len(),
-        Rmsg::Error(ref msg) => msg.as_bytes().len(),
-        Rmsg::Nack(ref msg) => msg.as_bytes().len(),
+        Rmsg::Error(ref msg) => msg.len(),
+        Rmsg::Nack(ref msg) => msg.len(),
     }
 }
 
 pub fn treq_size(treq: &Treq) -> usize {
     let mut size = 1; // header count
-    for &(_, ref v) in &treq.headers {
+    for (_, v) in &treq.headers {
         size += 2; // key and value lengths
         size += v.len();
     }
@@ -52,8 +54,8 @@ pub fn treq_size(treq: &Treq) -> usize {
 pub fn rmsg_size(msg: &Rmsg) -> usize {
     match *msg {
         Rmsg::Ok(ref b) => b.len(),
-        Rmsg::Error(ref m) => m.as_bytes().len(),
-        Rmsg::Nack(ref m) => m.as_bytes().len(),
+        Rmsg::Error(ref m) => m.len(),
+        Rmsg::Nack(ref m) => m.len(),
     }
 }
 
@@ -61,7 +63,7 @@ pub fn rmsg_size(msg: &Rmsg) -> usize {
 pub fn init_size(init: &Init) -> usize {
     let mut size = 2; // version
 
-    for &(ref k, ref v) in &init.headers {
+    for (k, v) in &init.headers {
         // each value preceeded by its len (i32)
         size += 8 + k.len() + v.len();
     }
@@ -72,7 +74,7 @@ pub fn init_size(init: &Init) -> usize {
 pub fn context_size(contexts: &Contexts) -> usize {
     let mut size = 2; // context size
 
-    for &(ref k, ref v) in contexts {
+    for (k, v) in contexts {
         size += 4; // two lengths
         size += k.len();
         size += v.len();
@@ -86,8 +88,8 @@ pub fn dtab_size(table: &Dtab) -> usize {
 
     for dentry in &table.entries {
         size += 4; // the two lengths
-        size += dentry.key.as_bytes().len();
-        size += dentry.val.as_bytes().len();
+        size += dentry.prefix.to_string().len();
+        size += dentry.dst.to_string().len();
     }
 
     size
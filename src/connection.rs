@@ -0,0 +1,208 @@
+//! Integration of mux connections with mio event loops.
+//!
+//! A `Connection` wraps a non-blocking stream registered with a
+//! `mio::Poll`. Readable events are turned into decoded `Message`s by an
+//! incremental `FrameDecoder` and outgoing `Message`s are queued until the
+//! stream is writable, so a single thread can multiplex many connections.
+
+use std::io::{self, ErrorKind, Read, Write};
+
+use ::mio::event::Source;
+use ::mio::{Interest, Registry, Token};
+
+use super::*;
+use super::codec::{DecoderConfig, Error, FrameDecoder, Result};
+
+// size of the chunks read from the stream
+const READ_CHUNK_SIZE: usize = 8 * 1024;
+
+/// A mux connection driven by a mio event loop
+///
+/// The event loop owns the `Poll` and dispatches the events of the
+/// connection's `Token` to `on_readable` and `on_writable`. After either
+/// call, or after queueing messages with `send`, the connection should be
+/// `reregister`ed so that it is only polled for writability while it has
+/// data to flush.
+///
+/// ```rust,no_run
+/// use mio::{Events, Interest, Poll, Token};
+/// use mio::net::TcpStream;
+/// use mux::{Message, MessageFrame, Tag};
+/// use mux::connection::Connection;
+///
+/// let mut poll = Poll::new().unwrap();
+/// let stream = TcpStream::connect("127.0.0.1:9000".parse().unwrap()).unwrap();
+/// let mut conn = Connection::new(stream);
+///
+/// conn.send(&Message { tag: Tag::new(true, 1), frame: MessageFrame::Tping }).unwrap();
+/// conn.register(poll.registry(), Token(0)).unwrap();
+///
+/// let mut events = Events::with_capacity(128);
+/// while !conn.is_closed() {
+///     poll.poll(&mut events, None).unwrap();
+///     for event in &events {
+///         if event.is_writable() {
+///             conn.on_writable().unwrap();
+///         }
+///         if event.is_readable() {
+///             for msg in conn.on_readable() {
+///                 println!("Received {:?}", msg.unwrap());
+///             }
+///         }
+///     }
+///     conn.reregister(poll.registry(), Token(0)).unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Connection<S> {
+    stream: S,
+    decoder: FrameDecoder,
+    write_buf: Vec<u8>,
+    // start of the unwritten data in `write_buf`
+    write_pos: usize,
+    closed: bool,
+}
+
+impl<S: Read + Write + Source> Connection<S> {
+    /// Construct a new `Connection` over a non-blocking `stream`.
+    pub fn new(stream: S) -> Connection<S> {
+        Connection::with_config(stream, DecoderConfig::default())
+    }
+
+    /// Construct a new `Connection` decoding with the limits of `config`.
+    pub fn with_config(stream: S, config: DecoderConfig) -> Connection<S> {
+        Connection {
+            stream,
+            decoder: FrameDecoder::with_config(config),
+            write_buf: Vec::new(),
+            write_pos: 0,
+            closed: false,
+        }
+    }
+
+    /// Register the stream with `registry` for the current `interest`.
+    pub fn register(&mut self, registry: &Registry, token: Token) -> io::Result<()> {
+        let interest = self.interest();
+        registry.register(&mut self.stream, token, interest)
+    }
+
+    /// Update the registration of the stream to the current `interest`.
+    pub fn reregister(&mut self, registry: &Registry, token: Token) -> io::Result<()> {
+        let interest = self.interest();
+        registry.reregister(&mut self.stream, token, interest)
+    }
+
+    /// Remove the stream from `registry`.
+    pub fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        registry.deregister(&mut self.stream)
+    }
+
+    /// The events of interest: readable, and writable while data is queued.
+    pub fn interest(&self) -> Interest {
+        if self.wants_write() {
+            Interest::READABLE | Interest::WRITABLE
+        } else {
+            Interest::READABLE
+        }
+    }
+
+    /// Whether encoded messages are waiting for the stream to be writable.
+    #[inline]
+    pub fn wants_write(&self) -> bool {
+        self.write_pos < self.write_buf.len()
+    }
+
+    /// Whether the peer closed the stream.
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Encode `msg` onto the write queue
+    ///
+    /// Nothing is written to the stream until `on_writable` is called.
+    pub fn send(&mut self, msg: &Message) -> Result<()> {
+        codec::write_message(&mut self.write_buf, msg)
+    }
+
+    /// Read everything available from the stream and decode it
+    ///
+    /// Reads until the stream would block, returning the outcome of each
+    /// frame completed by the data read in order. A frame that fails to
+    /// decode is skipped and reading continues. Partial frames are kept for
+    /// the next readable event. If the peer closed the stream, `is_closed`
+    /// becomes true.
+    ///
+    /// An invalid frame size or a failure of the stream ends the results, the
+    /// connection can't be read further and should be closed.
+    pub fn on_readable(&mut self) -> Vec<Result<Message>> {
+        let mut results = Vec::new();
+        let mut chunk = [0; READ_CHUNK_SIZE];
+
+        loop {
+            let n = match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    self.closed = true;
+                    break;
+                }
+                Ok(n) => n,
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    results.push(Err(e.into()));
+                    break;
+                }
+            };
+
+            let mut bytes = &chunk[..n];
+            loop {
+                match self.decoder.decode(bytes) {
+                    Ok(Some(msg)) => results.push(Ok(msg)),
+                    Ok(None) => break,
+                    // the stream is no longer framed
+                    Err(e @ Error::FrameTooSmall(_)) | Err(e @ Error::FrameTooLarge { .. }) => {
+                        results.push(Err(e));
+                        return results;
+                    }
+                    Err(e) => results.push(Err(e)),
+                }
+                bytes = &[];
+            }
+        }
+
+        results
+    }
+
+    /// Write as much of the queued data as the stream accepts
+    ///
+    /// Partial writes leave the remainder queued for the next writable
+    /// event.
+    pub fn on_writable(&mut self) -> io::Result<()> {
+        while self.wants_write() {
+            match self.stream.write(&self.write_buf[self.write_pos..]) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(n) => self.write_pos += n,
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        if !self.wants_write() {
+            self.write_buf.clear();
+            self.write_pos = 0;
+        }
+        self.stream.flush()
+    }
+
+    /// Reference to the underlying stream.
+    #[inline]
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Unwrap the underlying stream, dropping any queued data.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
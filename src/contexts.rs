@@ -0,0 +1,319 @@
+//! Typed broadcast contexts of `Tdispatch` and `Rdispatch` frames.
+//!
+//! Contexts are key-value pairs of bytes propagated along with requests.
+//! Finagle defines the binary layout of a number of well-known contexts,
+//! which are typed here by implementing `ContextKey`. `get` and `set` read
+//! and write the typed values of `Contexts`, leaving the entries with other
+//! keys as they are.
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use mux::Tdispatch;
+//! use mux::contexts::{self, Deadline, Retries};
+//!
+//! let mut tdispatch = Tdispatch::new("/s/users".to_string(), b"ping".to_vec());
+//! contexts::set(&mut tdispatch.contexts, &Deadline::from_timeout(Duration::from_secs(1)));
+//! contexts::set(&mut tdispatch.contexts, &Retries(2));
+//!
+//! assert_eq!(contexts::get::<Retries, _>(&tdispatch.contexts).unwrap(), Some(Retries(2)));
+//! assert!(contexts::get::<Deadline, _>(&tdispatch.contexts).unwrap().is_some());
+//! ```
+
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+use std::result;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use byteorder::{BigEndian, ByteOrder};
+
+use super::*;
+
+/// Key of the `TraceId` context.
+pub const TRACE_CONTEXT_KEY: &str = "com.twitter.finagle.tracing.TraceContext";
+/// Key of the `Deadline` context.
+pub const DEADLINE_KEY: &str = "com.twitter.finagle.Deadline";
+/// Key of the `Retries` context.
+pub const RETRIES_KEY: &str = "com.twitter.finagle.Retries";
+/// Key of the `ClientId` context.
+pub const CLIENT_ID_KEY: &str = "com.twitter.finagle.thrift.ClientIdContext";
+/// Key of the `BackupRequest` context.
+pub const BACKUP_REQUEST_KEY: &str = "com.twitter.finagle.BackupRequest";
+
+// flags of the trace context
+const FLAG_DEBUG: u64 = 1 << 0;
+const FLAG_SAMPLING_KNOWN: u64 = 1 << 1;
+const FLAG_SAMPLED: u64 = 1 << 2;
+
+/// Result of the context operations.
+pub type Result<T> = result::Result<T, ContextError>;
+
+/// Errors decoding the value of a context.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContextError {
+    /// The value of the context with the key doesn't decode.
+    InvalidValue(&'static str),
+}
+
+impl fmt::Display for ContextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ContextError::InvalidValue(key) => write!(f, "Invalid value for context '{}'", key),
+        }
+    }
+}
+
+impl error::Error for ContextError {}
+
+/// A context with a typed value.
+pub trait ContextKey: Sized {
+    /// Key of the context.
+    const KEY: &'static str;
+
+    /// Encode the value of the context.
+    fn encode(&self) -> Vec<u8>;
+
+    /// Decode the value of the context.
+    fn decode(value: &[u8]) -> Result<Self>;
+}
+
+/// The value of `K` in `contexts`, if any.
+///
+/// Works with both the `Contexts` of decoded messages and the borrowed
+/// `ContextsRef`.
+pub fn get<K: ContextKey, B: AsRef<[u8]>>(contexts: &[(B, B)]) -> Result<Option<K>> {
+    contexts.iter()
+        .find(|(key, _)| key.as_ref() == K::KEY.as_bytes())
+        .map(|(_, value)| K::decode(value.as_ref()))
+        .transpose()
+}
+
+/// Set the value of `K` in `contexts`, replacing any previous value.
+pub fn set<K: ContextKey>(contexts: &mut Contexts, value: &K) {
+    remove::<K>(contexts);
+    contexts.push((K::KEY.as_bytes().to_vec(), value.encode()));
+}
+
+/// Remove the value of `K` from `contexts`, returning whether it was set.
+pub fn remove<K: ContextKey>(contexts: &mut Contexts) -> bool {
+    let len = contexts.len();
+    contexts.retain(|(key, _)| &key[..] != K::KEY.as_bytes());
+    contexts.len() != len
+}
+
+/// Identifiers of the span of a request in a trace
+///
+/// Encoded as the span, parent and trace ids and the flags, followed by the
+/// high bits of 128-bit trace ids, all as 8 bytes big endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceId {
+    /// Id of the span.
+    pub span_id: u64,
+    /// Id of the parent span, the span itself for root spans.
+    pub parent_id: u64,
+    /// Id of the trace, the low bits of 128-bit trace ids.
+    pub trace_id: u64,
+    /// High bits of 128-bit trace ids.
+    pub trace_id_high: Option<u64>,
+    /// Whether the trace is sampled, `None` leaving the decision to the
+    /// receiver.
+    pub sampled: Option<bool>,
+    /// Whether the trace is forced to be sampled and kept.
+    pub debug: bool,
+}
+
+/// Time by which the response to a request is expected
+///
+/// Encoded as the times the deadline was set and is due in nanoseconds
+/// since the epoch, both as 8 bytes big endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Deadline {
+    /// When the deadline was set.
+    pub timestamp: SystemTime,
+    /// When the deadline is due.
+    pub deadline: SystemTime,
+}
+
+/// Number of times the request was retried, encoded as 4 bytes big endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Retries(pub u32);
+
+/// Name of the client issuing the request, encoded in UTF-8.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClientId(pub String);
+
+/// Marks backup requests, sent when the original request is slow. The value
+/// is empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct BackupRequest;
+
+impl ContextKey for TraceId {
+    const KEY: &'static str = TRACE_CONTEXT_KEY;
+
+    fn encode(&self) -> Vec<u8> {
+        let mut flags = if self.debug { FLAG_DEBUG } else { 0 };
+        match self.sampled {
+            Some(true) => flags |= FLAG_SAMPLING_KNOWN | FLAG_SAMPLED,
+            Some(false) => flags |= FLAG_SAMPLING_KNOWN,
+            None => (),
+        }
+
+        let mut value = vec![0; if self.trace_id_high.is_some() { 40 } else { 32 }];
+        BigEndian::write_u64(&mut value[0..8], self.span_id);
+        BigEndian::write_u64(&mut value[8..16], self.parent_id);
+        BigEndian::write_u64(&mut value[16..24], self.trace_id);
+        BigEndian::write_u64(&mut value[24..32], flags);
+        if let Some(high) = self.trace_id_high {
+            BigEndian::write_u64(&mut value[32..40], high);
+        }
+        value
+    }
+
+    fn decode(value: &[u8]) -> Result<TraceId> {
+        let trace_id_high = match value.len() {
+            32 => None,
+            40 => Some(BigEndian::read_u64(&value[32..40])),
+            _ => return Err(ContextError::InvalidValue(TRACE_CONTEXT_KEY)),
+        };
+
+        let flags = BigEndian::read_u64(&value[24..32]);
+        let sampled = if flags & FLAG_SAMPLING_KNOWN != 0 {
+            Some(flags & FLAG_SAMPLED != 0)
+        } else {
+            None
+        };
+
+        Ok(TraceId {
+            span_id: BigEndian::read_u64(&value[0..8]),
+            parent_id: BigEndian::read_u64(&value[8..16]),
+            trace_id: BigEndian::read_u64(&value[16..24]),
+            trace_id_high,
+            sampled,
+            debug: flags & FLAG_DEBUG != 0,
+        })
+    }
+}
+
+impl Deadline {
+    /// Construct a new `Deadline` due `timeout` from now.
+    pub fn from_timeout(timeout: Duration) -> Deadline {
+        let now = SystemTime::now();
+        Deadline {
+            timestamp: now,
+            deadline: now.checked_add(timeout).unwrap_or_else(max_time),
+        }
+    }
+
+    /// Time left until the deadline is due, zero if it is.
+    pub fn remaining(&self) -> Duration {
+        self.deadline.duration_since(SystemTime::now()).unwrap_or_default()
+    }
+
+    /// Whether the deadline is due.
+    pub fn is_expired(&self) -> bool {
+        self.deadline <= SystemTime::now()
+    }
+
+    /// The sooner of the two deadlines, set at the later timestamp.
+    pub fn combine(&self, other: &Deadline) -> Deadline {
+        Deadline {
+            timestamp: self.timestamp.max(other.timestamp),
+            deadline: self.deadline.min(other.deadline),
+        }
+    }
+}
+
+impl Tdispatch {
+    /// The `Deadline` of the request, if it carries a valid one.
+    pub fn deadline(&self) -> Option<Deadline> {
+        get(&self.contexts).ok().and_then(|deadline| deadline)
+    }
+
+    /// The `TraceId` of the request, if it carries a valid one.
+    pub fn trace_id(&self) -> Option<TraceId> {
+        get(&self.contexts).ok().and_then(|trace_id| trace_id)
+    }
+}
+
+impl ContextKey for Deadline {
+    const KEY: &'static str = DEADLINE_KEY;
+
+    fn encode(&self) -> Vec<u8> {
+        let mut value = vec![0; 16];
+        BigEndian::write_i64(&mut value[0..8], to_nanos(self.timestamp));
+        BigEndian::write_i64(&mut value[8..16], to_nanos(self.deadline));
+        value
+    }
+
+    fn decode(value: &[u8]) -> Result<Deadline> {
+        if value.len() != 16 {
+            return Err(ContextError::InvalidValue(DEADLINE_KEY));
+        }
+
+        Ok(Deadline {
+            timestamp: from_nanos(BigEndian::read_i64(&value[0..8])),
+            deadline: from_nanos(BigEndian::read_i64(&value[8..16])),
+        })
+    }
+}
+
+impl ContextKey for Retries {
+    const KEY: &'static str = RETRIES_KEY;
+
+    fn encode(&self) -> Vec<u8> {
+        self.0.to_be_bytes().to_vec()
+    }
+
+    fn decode(value: &[u8]) -> Result<Retries> {
+        match *value {
+            [a, b, c, d] => Ok(Retries(u32::from_be_bytes([a, b, c, d]))),
+            _ => Err(ContextError::InvalidValue(RETRIES_KEY)),
+        }
+    }
+}
+
+impl ContextKey for ClientId {
+    const KEY: &'static str = CLIENT_ID_KEY;
+
+    fn encode(&self) -> Vec<u8> {
+        self.0.as_bytes().to_vec()
+    }
+
+    fn decode(value: &[u8]) -> Result<ClientId> {
+        String::from_utf8(value.to_vec())
+            .map(ClientId)
+            .map_err(|_| ContextError::InvalidValue(CLIENT_ID_KEY))
+    }
+}
+
+impl ContextKey for BackupRequest {
+    const KEY: &'static str = BACKUP_REQUEST_KEY;
+
+    fn encode(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    // the presence of the context is what matters
+    fn decode(_: &[u8]) -> Result<BackupRequest> {
+        Ok(BackupRequest)
+    }
+}
+
+// times are nanoseconds since the epoch, saturating at the bounds of i64
+// like the `Time.Top` and `Time.Bottom` of Finagle
+
+fn max_time() -> SystemTime {
+    from_nanos(i64::MAX)
+}
+
+fn to_nanos(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => i64::try_from(since.as_nanos()).unwrap_or(i64::MAX),
+        Err(before) => i64::try_from(before.duration().as_nanos()).map_or(i64::MIN, |n| -n),
+    }
+}
+
+fn from_nanos(nanos: i64) -> SystemTime {
+    let since = Duration::from_nanos(nanos.unsigned_abs());
+    if nanos >= 0 { UNIX_EPOCH + since } else { UNIX_EPOCH - since }
+}
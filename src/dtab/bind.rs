@@ -0,0 +1,187 @@
+use std::cell::Cell;
+use std::error;
+use std::fmt;
+
+use super::*;
+use super::super::Tdispatch;
+
+/// Maximum number of successive rewrites of a name when binding.
+pub const MAX_BIND_DEPTH: usize = 100;
+
+/// Maximum number of lookups of a single bind, across all of its branches.
+pub const MAX_BIND_LOOKUPS: usize = 10_000;
+
+/// Failure to bind a name through a `Dtab`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum BindError {
+    /// The name bound doesn't parse.
+    Syntax(DtabError),
+    /// More than `MAX_BIND_DEPTH` rewrites, likely due to a cycle.
+    DepthExceeded,
+    /// More than `MAX_BIND_LOOKUPS` lookups, likely due to entries fanning
+    /// out to alternatives or unions of each other.
+    LookupsExceeded,
+}
+
+impl fmt::Display for BindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BindError::Syntax(ref e) => e.fmt(f),
+            BindError::DepthExceeded => write!(f, "Max dtab bind depth of {} exceeded", MAX_BIND_DEPTH),
+            BindError::LookupsExceeded => {
+                write!(f, "Max dtab bind lookups of {} exceeded", MAX_BIND_LOOKUPS)
+            }
+        }
+    }
+}
+
+impl error::Error for BindError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            BindError::Syntax(ref e) => Some(e),
+            BindError::DepthExceeded | BindError::LookupsExceeded => None,
+        }
+    }
+}
+
+impl From<DtabError> for BindError {
+    fn from(e: DtabError) -> BindError {
+        BindError::Syntax(e)
+    }
+}
+
+// the entries of dtabs
+struct Binder<'a> {
+    entries: Vec<&'a Dentry>,
+    // lookups made by the bind so far
+    lookups: Cell<usize>,
+}
+
+impl Dtab {
+    /// Rewrite `path` by the entries with a prefix matching it
+    ///
+    /// The matched prefix of `path` is replaced by the destinations of the
+    /// matching entries, later entries taking precedence as the first
+    /// alternatives. Without a match the path is negative.
+    ///
+    /// ```rust
+    /// use mux::{Dtab, NameTree, Path};
+    ///
+    /// let dtab = Dtab::parse("/s=>/a;/s/*=>/b").unwrap();
+    /// let tree = dtab.lookup(&Path::read("/s/foo/bar").unwrap());
+    /// assert_eq!(tree, NameTree::read("/b/bar | /a/foo/bar").unwrap());
+    /// ```
+    pub fn lookup(&self, path: &Path) -> NameTree<Path> {
+        Binder::new(&[self]).lookup(path)
+    }
+
+    /// Bind the names of `tree` through the entries
+    ///
+    /// Names are rewritten by `lookup` until they are bound: paths under
+    /// `/$` name system namers, for example `/$/inet/localhost/8080`, and
+    /// are bound as is. The resulting tree is simplified to the bound names
+    /// of the first alternatives that aren't negative. Binds needing more
+    /// than `MAX_BIND_DEPTH` successive rewrites or `MAX_BIND_LOOKUPS`
+    /// lookups in all fail.
+    ///
+    /// ```rust
+    /// use mux::{Dtab, NameTree, Path};
+    ///
+    /// let dtab = Dtab::parse("/s=>/$/inet/localhost;/s/users=>/$/inet/users|/s/users").unwrap();
+    /// let tree = dtab.bind(&NameTree::read("/s/users/8080").unwrap()).unwrap();
+    /// assert_eq!(tree, NameTree::read("/$/inet/users/8080").unwrap());
+    /// ```
+    pub fn bind(&self, tree: &NameTree<Path>) -> Result<NameTree<Path>, BindError> {
+        Binder::new(&[self]).bind(tree, 0)
+    }
+}
+
+impl Tdispatch {
+    /// Bind the destination through `base` followed by the dtab of the
+    /// request, whose entries take precedence.
+    pub fn bind(&self, base: &Dtab) -> Result<NameTree<Path>, BindError> {
+        let dest = NameTree::Leaf(Path::read(&self.dest)?);
+        Binder::new(&[base, &self.dtab]).bind(&dest, 0)
+    }
+}
+
+impl<'a> Binder<'a> {
+    fn new(dtabs: &[&'a Dtab]) -> Binder<'a> {
+        let entries = dtabs.iter().flat_map(|dtab| &dtab.entries).collect();
+        Binder { entries, lookups: Cell::new(0) }
+    }
+
+    fn lookup(&self, path: &Path) -> NameTree<Path> {
+        let mut matches: Vec<_> = self.entries.iter()
+            .rev()
+            .filter(|dentry| dentry.prefix.matches(path))
+            .map(|dentry| {
+                let rest = path.skip(dentry.prefix.len());
+                dentry.dst.map(|name| name.concat(&rest))
+            })
+            .collect();
+
+        match matches.len() {
+            0 => NameTree::Neg,
+            1 => matches.pop().unwrap(),
+            _ => NameTree::Alt(matches),
+        }
+    }
+
+    fn bind(&self, tree: &NameTree<Path>, depth: usize) -> Result<NameTree<Path>, BindError> {
+        if depth > MAX_BIND_DEPTH {
+            return Err(BindError::DepthExceeded);
+        }
+
+        match *tree {
+            NameTree::Leaf(ref path) if is_bound(path) => Ok(NameTree::Leaf(path.clone())),
+            NameTree::Leaf(ref path) => {
+                if self.lookups.get() == MAX_BIND_LOOKUPS {
+                    return Err(BindError::LookupsExceeded);
+                }
+                self.lookups.set(self.lookups.get() + 1);
+                self.bind(&self.lookup(path), depth + 1)
+            }
+            // the later alternatives are only bound if needed
+            NameTree::Alt(ref trees) => {
+                for tree in trees {
+                    match self.bind(tree, depth)? {
+                        NameTree::Neg => continue,
+                        bound => return Ok(bound),
+                    }
+                }
+                Ok(NameTree::Neg)
+            }
+            NameTree::Union(ref trees) => {
+                let mut bound = Vec::new();
+                for weighted in trees {
+                    match self.bind(&weighted.tree, depth)? {
+                        NameTree::Neg => (),
+                        NameTree::Fail => return Ok(NameTree::Fail),
+                        tree => bound.push(Weighted { weight: weighted.weight, tree }),
+                    }
+                }
+
+                // empty branches only matter if all of them are
+                if !bound.is_empty() && bound.iter().all(|w| w.tree == NameTree::Empty) {
+                    return Ok(NameTree::Empty);
+                }
+                bound.retain(|w| w.tree != NameTree::Empty);
+                Ok(match bound.len() {
+                    0 => NameTree::Neg,
+                    1 => bound.pop().unwrap().tree,
+                    _ => NameTree::Union(bound),
+                })
+            }
+            NameTree::Neg => Ok(NameTree::Neg),
+            NameTree::Fail => Ok(NameTree::Fail),
+            NameTree::Empty => Ok(NameTree::Empty),
+        }
+    }
+}
+
+// whether the path names a system namer
+#[inline]
+fn is_bound(path: &Path) -> bool {
+    path.elems().first().is_some_and(|elem| elem == b"$")
+}
@@ -0,0 +1,158 @@
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+mod bind;
+mod name_tree;
+mod parser;
+mod path;
+
+pub use self::bind::*;
+pub use self::name_tree::*;
+pub use self::path::*;
+
+use self::parser::Parser;
+
+/// Single entry of the `Dtab`
+///
+/// The prefix and destination are sent on the wire in their canonical
+/// textual form.
+#[derive(PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dentry {
+    /// Prefix of the paths rewritten by the entry.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::text"))]
+    pub prefix: Prefix,
+    /// Name tree the prefix is rewritten to.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::text"))]
+    pub dst: NameTree<Path>,
+}
+
+// weights parsed from the textual form are never NaN
+impl Eq for Dentry {}
+
+/// Delegate table.
+#[derive(PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dtab {
+    pub entries: Vec<Dentry>,
+}
+
+/// Failure to parse the textual form of a `Path`, `NameTree` or `Dtab`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct DtabError {
+    /// Byte offset of the failure in the input.
+    pub position: usize,
+    /// What was expected at the offset.
+    pub message: &'static str,
+}
+
+impl fmt::Display for DtabError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid dtab syntax at {}: {}", self.position, self.message)
+    }
+}
+
+impl error::Error for DtabError {}
+
+impl Dentry {
+    /// Create a new `Dentry` delegating `prefix` to `dst`.
+    pub fn new(prefix: Prefix, dst: NameTree<Path>) -> Dentry {
+        Dentry {
+            prefix,
+            dst,
+        }
+    }
+
+    /// Parse a `Dentry` of the form `prefix=>dst`.
+    pub fn read(s: &str) -> Result<Dentry, DtabError> {
+        let mut parser = Parser::new(s);
+        let (prefix, dst) = parser.dentry()?;
+        parser.finish()?;
+        Ok(Dentry::new(prefix, dst))
+    }
+}
+
+impl Dtab {
+    /// Create a new, empty `Dtab`.
+    #[inline]
+    pub fn new() -> Dtab {
+        Dtab::from_entries(Vec::new())
+    }
+
+    /// Create a new `Dtab` containing the `Dentry`s.
+    #[inline]
+    pub fn from_entries(entries: Vec<Dentry>) -> Dtab {
+        Dtab {
+            entries,
+        }
+    }
+
+    /// Parse a `Dtab` of `;` separated `Dentry`s
+    ///
+    /// The `Dtab` displays the canonical forms of the prefixes and
+    /// destinations of its entries.
+    ///
+    /// ```rust
+    /// use mux::{Dtab, NameTree, Prefix};
+    ///
+    /// let dtab = Dtab::parse("/s => /a|/b ; /x=>/y").unwrap();
+    /// assert_eq!(dtab.entries[0].prefix, Prefix::read("/s").unwrap());
+    /// assert_eq!(dtab.entries[1].dst, NameTree::read("/y").unwrap());
+    /// assert_eq!(dtab.to_string(), "/s=>/a | /b;/x=>/y");
+    /// ```
+    pub fn parse(s: &str) -> Result<Dtab, DtabError> {
+        let entries = Parser::new(s).dtab()?
+            .into_iter()
+            .map(|(prefix, dst)| Dentry::new(prefix, dst))
+            .collect();
+        Ok(Dtab::from_entries(entries))
+    }
+
+    /// Add an entry to this `Dtab`.
+    #[inline]
+    pub fn add_entry(&mut self, prefix: Prefix, dst: NameTree<Path>) -> &Self {
+        self.entries.push(Dentry::new(prefix, dst));
+        self
+    }
+}
+
+impl Default for Dtab {
+    fn default() -> Dtab {
+        Dtab::new()
+    }
+}
+
+impl FromStr for Dentry {
+    type Err = DtabError;
+
+    fn from_str(s: &str) -> Result<Dentry, DtabError> {
+        Dentry::read(s)
+    }
+}
+
+impl FromStr for Dtab {
+    type Err = DtabError;
+
+    fn from_str(s: &str) -> Result<Dtab, DtabError> {
+        Dtab::parse(s)
+    }
+}
+
+impl fmt::Display for Dentry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}=>{}", self.prefix, self.dst)
+    }
+}
+
+impl fmt::Display for Dtab {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, dentry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                f.write_str(";")?;
+            }
+            dentry.fmt(f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::*;
+use super::parser::Parser;
+
+/// Weight of the branches of a `NameTree::Union` given none.
+pub const DEFAULT_WEIGHT: f64 = 1.0;
+
+/// An expression over names, the destination of a `Dentry`
+///
+/// The textual form combines paths with alternation `|`, binding the
+/// first branch that isn't negative, and weighted union `&`, which binds
+/// higher than alternation. `~` is the negative tree, `!` the failing tree
+/// and `$` the empty tree.
+///
+/// ```rust
+/// use mux::{NameTree, Path, Weighted};
+///
+/// let tree = NameTree::read("/a | 2*/b & /c").unwrap();
+/// assert_eq!(tree, NameTree::Alt(vec![
+///     NameTree::Leaf(Path::read("/a").unwrap()),
+///     NameTree::Union(vec![
+///         Weighted { weight: 2.0, tree: NameTree::Leaf(Path::read("/b").unwrap()) },
+///         Weighted { weight: 1.0, tree: NameTree::Leaf(Path::read("/c").unwrap()) },
+///     ]),
+/// ]));
+/// assert_eq!(tree.to_string(), "/a | 2.0*/b & /c");
+/// ```
+#[derive(PartialEq, Clone, Debug)]
+pub enum NameTree<T> {
+    /// A name.
+    Leaf(T),
+    /// The first of the trees that isn't negative.
+    Alt(Vec<NameTree<T>>),
+    /// All of the trees, weighted.
+    Union(Vec<Weighted<T>>),
+    /// A negative tree: there is no binding, try the next alternative.
+    Neg,
+    /// A failing tree: binding fails without trying other alternatives.
+    Fail,
+    /// An empty tree: the binding is valid but has no names.
+    Empty,
+}
+
+/// A branch of a `NameTree::Union`.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Weighted<T> {
+    /// Share of the branch in the union.
+    pub weight: f64,
+    /// The tree of the branch.
+    pub tree: NameTree<T>,
+}
+
+impl NameTree<Path> {
+    /// Parse the textual form of a `NameTree` of paths, nested at most 100
+    /// parentheses deep.
+    pub fn read(s: &str) -> Result<NameTree<Path>, DtabError> {
+        let mut parser = Parser::new(s);
+        let tree = parser.tree()?;
+        parser.finish()?;
+        Ok(tree)
+    }
+}
+
+impl FromStr for NameTree<Path> {
+    type Err = DtabError;
+
+    fn from_str(s: &str) -> Result<NameTree<Path>, DtabError> {
+        NameTree::read(s)
+    }
+}
+
+// precedence of the trees, nested trees of lower precedence are parenthesized
+const ALT: u8 = 0;
+const UNION: u8 = 1;
+const SIMPLE: u8 = 2;
+
+impl<T> NameTree<T> {
+    /// Map the names of the tree.
+    pub fn map<U, F: FnMut(&T) -> U>(&self, mut f: F) -> NameTree<U> {
+        self.map_with(&mut f)
+    }
+
+    fn map_with<U, F: FnMut(&T) -> U>(&self, f: &mut F) -> NameTree<U> {
+        match *self {
+            NameTree::Leaf(ref name) => NameTree::Leaf(f(name)),
+            NameTree::Alt(ref trees) => NameTree::Alt(trees.iter().map(|t| t.map_with(f)).collect()),
+            NameTree::Union(ref trees) => NameTree::Union(trees.iter().map(|w| Weighted {
+                weight: w.weight,
+                tree: w.tree.map_with(f),
+            }).collect()),
+            NameTree::Neg => NameTree::Neg,
+            NameTree::Fail => NameTree::Fail,
+            NameTree::Empty => NameTree::Empty,
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match *self {
+            NameTree::Alt(ref trees) if trees.len() > 1 => ALT,
+            NameTree::Union(ref trees) if trees.len() > 1 => UNION,
+            NameTree::Union(ref trees) if trees.iter().any(|w| w.weight != DEFAULT_WEIGHT) => UNION,
+            _ => SIMPLE,
+        }
+    }
+}
+
+impl<T: fmt::Display> NameTree<T> {
+    // show the tree, parenthesized unless of at least `precedence`
+    fn show(&self, f: &mut fmt::Formatter, precedence: u8) -> fmt::Result {
+        if self.precedence() < precedence {
+            f.write_str("(")?;
+            self.show(f, ALT)?;
+            return f.write_str(")");
+        }
+
+        match *self {
+            NameTree::Leaf(ref name) => name.fmt(f),
+            // alternations and unions of a single tree show as the tree,
+            // and of none as the negative tree they bind to
+            NameTree::Alt(ref trees) if trees.len() == 1 => trees[0].show(f, precedence),
+            NameTree::Union(ref trees) if trees.len() == 1 && trees[0].weight == DEFAULT_WEIGHT => {
+                trees[0].tree.show(f, precedence)
+            }
+            NameTree::Alt(ref trees) if trees.is_empty() => f.write_str("~"),
+            NameTree::Union(ref trees) if trees.is_empty() => f.write_str("~"),
+            NameTree::Alt(ref trees) => {
+                for (i, tree) in trees.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" | ")?;
+                    }
+                    tree.show(f, UNION)?;
+                }
+                Ok(())
+            }
+            NameTree::Union(ref trees) => {
+                for (i, weighted) in trees.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" & ")?;
+                    }
+                    if weighted.weight != DEFAULT_WEIGHT {
+                        write!(f, "{:?}*", weighted.weight)?;
+                    }
+                    weighted.tree.show(f, SIMPLE)?;
+                }
+                Ok(())
+            }
+            NameTree::Neg => f.write_str("~"),
+            NameTree::Fail => f.write_str("!"),
+            NameTree::Empty => f.write_str("$"),
+        }
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for NameTree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.show(f, ALT)
+    }
+}
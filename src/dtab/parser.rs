@@ -0,0 +1,263 @@
+// Parser of the textual forms of paths, name trees and dtabs.
+
+use super::*;
+
+// maximum nesting of parenthesized name trees, bounding the recursion
+const MAX_DEPTH: usize = 100;
+
+pub(super) struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    // parentheses open at `pos`
+    depth: usize,
+}
+
+// characters of path elements shown without escaping
+#[inline]
+pub(super) fn is_showable(ch: u8) -> bool {
+    ch.is_ascii_alphanumeric() || b"_:.#$%-".contains(&ch)
+}
+
+impl<'a> Parser<'a> {
+    pub(super) fn new(input: &'a str) -> Parser<'a> {
+        Parser { input: input.as_bytes(), pos: 0, depth: 0 }
+    }
+
+    #[inline]
+    pub(super) fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).cloned()
+    }
+
+    #[inline]
+    pub(super) fn at_end(&self) -> bool {
+        self.pos == self.input.len()
+    }
+
+    // consume `ch` if it is next
+    pub(super) fn eat(&mut self, ch: u8) -> bool {
+        if self.peek() == Some(ch) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // consume `s` if it is next
+    pub(super) fn eat_str(&mut self, s: &str) -> bool {
+        if self.input[self.pos..].starts_with(s.as_bytes()) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(super) fn expect(&mut self, ch: u8, message: &'static str) -> Result<(), DtabError> {
+        if self.eat(ch) { Ok(()) } else { Err(self.error(message)) }
+    }
+
+    pub(super) fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|ch| ch.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    // fail unless the input is consumed, ignoring trailing whitespace
+    pub(super) fn finish(&mut self) -> Result<(), DtabError> {
+        self.skip_whitespace();
+        if self.at_end() { Ok(()) } else { Err(self.error("unexpected trailing input")) }
+    }
+
+    pub(super) fn error(&self, message: &'static str) -> DtabError {
+        DtabError { position: self.pos, message }
+    }
+
+    pub(super) fn path(&mut self) -> Result<Path, DtabError> {
+        self.expect(b'/', "expected '/'")?;
+
+        // a lone slash is the empty path
+        let mut elems = Vec::new();
+        if !self.peek().is_some_and(|ch| is_showable(ch) || ch == b'\\') {
+            return Ok(Path::empty());
+        }
+
+        loop {
+            elems.push(self.path_elem()?);
+            if !self.eat(b'/') {
+                return Ok(Path::new(elems));
+            }
+        }
+    }
+
+    // like a path but with `*` elements
+    pub(super) fn prefix(&mut self) -> Result<Prefix, DtabError> {
+        self.expect(b'/', "expected '/'")?;
+
+        let mut elems = Vec::new();
+        if !self.peek().is_some_and(|ch| is_showable(ch) || ch == b'\\' || ch == b'*') {
+            return Ok(Prefix::default());
+        }
+
+        loop {
+            if self.eat(b'*') {
+                elems.push(PrefixElem::Any);
+            } else {
+                elems.push(PrefixElem::Label(self.path_elem()?));
+            }
+            if !self.eat(b'/') {
+                return Ok(Prefix::new(elems));
+            }
+        }
+    }
+
+    fn path_elem(&mut self) -> Result<Vec<u8>, DtabError> {
+        let mut elem = Vec::new();
+        loop {
+            match self.peek() {
+                Some(ch) if is_showable(ch) => {
+                    elem.push(ch);
+                    self.pos += 1;
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    elem.push(self.escape()?);
+                }
+                _ if elem.is_empty() => return Err(self.error("empty path element")),
+                _ => return Ok(elem),
+            }
+        }
+    }
+
+    // the byte of a `\xHH` escape, past the backslash
+    fn escape(&mut self) -> Result<u8, DtabError> {
+        self.expect(b'x', "expected 'x' in escape")?;
+        let hex = self.input.get(self.pos..self.pos + 2)
+            .and_then(|hex| ::std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| self.error("expected two hex digits in escape"))?;
+        self.pos += 2;
+        Ok(hex)
+    }
+
+    // tree := branch ('|' branch)*
+    pub(super) fn tree(&mut self) -> Result<NameTree<Path>, DtabError> {
+        let mut alts = vec![self.branch()?];
+        loop {
+            self.skip_whitespace();
+            if !self.eat(b'|') {
+                break;
+            }
+            alts.push(self.branch()?);
+        }
+
+        Ok(if alts.len() == 1 { alts.pop().unwrap() } else { NameTree::Alt(alts) })
+    }
+
+    // branch := weighted ('&' weighted)*
+    fn branch(&mut self) -> Result<NameTree<Path>, DtabError> {
+        let mut trees = vec![self.weighted()?];
+        loop {
+            self.skip_whitespace();
+            if !self.eat(b'&') {
+                break;
+            }
+            trees.push(self.weighted()?);
+        }
+
+        Ok(if trees.len() == 1 && trees[0].weight == DEFAULT_WEIGHT {
+            trees.pop().unwrap().tree
+        } else {
+            NameTree::Union(trees)
+        })
+    }
+
+    // weighted := (number '*')? simple
+    fn weighted(&mut self) -> Result<Weighted<Path>, DtabError> {
+        self.skip_whitespace();
+        let weight = match self.peek() {
+            Some(ch) if ch.is_ascii_digit() || ch == b'.' => {
+                let weight = self.number()?;
+                self.skip_whitespace();
+                self.expect(b'*', "expected '*' after weight")?;
+                weight
+            }
+            _ => DEFAULT_WEIGHT,
+        };
+
+        Ok(Weighted { weight, tree: self.simple()? })
+    }
+
+    fn number(&mut self) -> Result<f64, DtabError> {
+        let start = self.pos;
+        while self.peek().is_some_and(|ch| ch.is_ascii_digit() || ch == b'.') {
+            self.pos += 1;
+        }
+
+        ::std::str::from_utf8(&self.input[start..self.pos]).ok()
+            .and_then(|number| number.parse().ok())
+            .ok_or(DtabError { position: start, message: "invalid weight" })
+    }
+
+    // simple := '(' tree ')' | path | '!' | '~' | '$'
+    fn simple(&mut self) -> Result<NameTree<Path>, DtabError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'(') => {
+                if self.depth == MAX_DEPTH {
+                    return Err(self.error("name tree nested too deeply"));
+                }
+                self.pos += 1;
+                self.depth += 1;
+                let tree = self.tree()?;
+                self.skip_whitespace();
+                self.expect(b')', "expected ')'")?;
+                self.depth -= 1;
+                Ok(tree)
+            }
+            Some(b'/') => self.path().map(NameTree::Leaf),
+            Some(b'!') => {
+                self.pos += 1;
+                Ok(NameTree::Fail)
+            }
+            Some(b'~') => {
+                self.pos += 1;
+                Ok(NameTree::Neg)
+            }
+            Some(b'$') => {
+                self.pos += 1;
+                Ok(NameTree::Empty)
+            }
+            _ => Err(self.error("expected a name tree")),
+        }
+    }
+
+    // dentry := prefix '=>' tree
+    pub(super) fn dentry(&mut self) -> Result<(Prefix, NameTree<Path>), DtabError> {
+        self.skip_whitespace();
+        let prefix = self.prefix()?;
+        self.skip_whitespace();
+        if !self.eat_str("=>") {
+            return Err(self.error("expected '=>'"));
+        }
+        Ok((prefix, self.tree()?))
+    }
+
+    // dtab := (dentry (';' dentry)*)? ';'?
+    pub(super) fn dtab(&mut self) -> Result<Vec<(Prefix, NameTree<Path>)>, DtabError> {
+        let mut dentries = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.at_end() {
+                return Ok(dentries);
+            }
+
+            dentries.push(self.dentry()?);
+            self.skip_whitespace();
+            if !self.eat(b';') {
+                self.finish()?;
+                return Ok(dentries);
+            }
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::*;
+use super::parser::{is_showable, Parser};
+
+/// A hierarchical name made of byte string elements
+///
+/// The textual form separates the elements with slashes, `/` alone being
+/// the empty path. Bytes other than ASCII letters and digits and `_:.#$%-`
+/// are escaped as `\xHH`.
+///
+/// ```rust
+/// use mux::Path;
+///
+/// let path = Path::read("/s/my\\x20service").unwrap();
+/// assert_eq!(path.elems()[1], b"my service");
+/// assert_eq!(path.to_string(), "/s/my\\x20service");
+/// ```
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Default)]
+pub struct Path {
+    elems: Vec<Vec<u8>>,
+}
+
+impl Path {
+    /// Construct a new `Path` of `elems`.
+    #[inline]
+    pub fn new(elems: Vec<Vec<u8>>) -> Path {
+        Path { elems }
+    }
+
+    /// The empty `Path`, shown as `/`.
+    #[inline]
+    pub fn empty() -> Path {
+        Path::default()
+    }
+
+    /// Parse the textual form of a `Path`.
+    pub fn read(s: &str) -> Result<Path, DtabError> {
+        let mut parser = Parser::new(s);
+        let path = parser.path()?;
+        parser.finish()?;
+        Ok(path)
+    }
+
+    /// The elements of the `Path`.
+    #[inline]
+    pub fn elems(&self) -> &[Vec<u8>] {
+        &self.elems
+    }
+
+    /// Number of elements.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.elems.len()
+    }
+
+    /// Whether the `Path` has no elements.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.elems.is_empty()
+    }
+
+    /// Whether the first elements of the `Path` are those of `prefix`.
+    #[inline]
+    pub fn starts_with(&self, prefix: &Path) -> bool {
+        self.elems.starts_with(&prefix.elems)
+    }
+
+    /// The `Path` without its first `n` elements.
+    pub fn skip(&self, n: usize) -> Path {
+        Path::new(self.elems.iter().skip(n).cloned().collect())
+    }
+
+    /// The `Path` followed by the elements of `suffix`.
+    pub fn concat(&self, suffix: &Path) -> Path {
+        Path::new(self.elems.iter().chain(&suffix.elems).cloned().collect())
+    }
+}
+
+impl FromStr for Path {
+    type Err = DtabError;
+
+    fn from_str(s: &str) -> Result<Path, DtabError> {
+        Path::read(s)
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.elems.is_empty() {
+            return f.write_str("/");
+        }
+
+        for elem in &self.elems {
+            f.write_str("/")?;
+            show_elem(f, elem)?;
+        }
+        Ok(())
+    }
+}
+
+// show an element, escaping the bytes that aren't showable
+fn show_elem(f: &mut fmt::Formatter, elem: &[u8]) -> fmt::Result {
+    for &ch in elem {
+        if is_showable(ch) {
+            write!(f, "{}", ch as char)?;
+        } else {
+            write!(f, "\\x{:02x}", ch)?;
+        }
+    }
+    Ok(())
+}
+
+/// The prefix of a `Dentry`: a `Path` whose `*` elements match any element.
+///
+/// ```rust
+/// use mux::{Path, Prefix};
+///
+/// let prefix = Prefix::read("/s/*/http").unwrap();
+/// assert!(prefix.matches(&Path::read("/s/foo/http/bar").unwrap()));
+/// assert!(!prefix.matches(&Path::read("/s/foo/grpc").unwrap()));
+/// ```
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Default)]
+pub struct Prefix {
+    elems: Vec<PrefixElem>,
+}
+
+/// An element of a `Prefix`.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub enum PrefixElem {
+    /// Matches the same element.
+    Label(Vec<u8>),
+    /// Matches any element, shown as `*`.
+    Any,
+}
+
+impl Prefix {
+    /// Construct a new `Prefix` of `elems`.
+    #[inline]
+    pub fn new(elems: Vec<PrefixElem>) -> Prefix {
+        Prefix { elems }
+    }
+
+    /// Parse the textual form of a `Prefix`.
+    pub fn read(s: &str) -> Result<Prefix, DtabError> {
+        let mut parser = Parser::new(s);
+        let prefix = parser.prefix()?;
+        parser.finish()?;
+        Ok(prefix)
+    }
+
+    /// The elements of the `Prefix`.
+    #[inline]
+    pub fn elems(&self) -> &[PrefixElem] {
+        &self.elems
+    }
+
+    /// Number of elements.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.elems.len()
+    }
+
+    /// Whether the `Prefix` has no elements, matching every path.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.elems.is_empty()
+    }
+
+    /// Whether `path` starts with elements matching the `Prefix`.
+    pub fn matches(&self, path: &Path) -> bool {
+        self.len() <= path.len() &&
+            self.elems.iter().zip(path.elems()).all(|(prefix, elem)| match *prefix {
+                PrefixElem::Label(ref label) => label == elem,
+                PrefixElem::Any => true,
+            })
+    }
+}
+
+impl From<Path> for Prefix {
+    fn from(path: Path) -> Prefix {
+        Prefix::new(path.elems.into_iter().map(PrefixElem::Label).collect())
+    }
+}
+
+impl FromStr for Prefix {
+    type Err = DtabError;
+
+    fn from_str(s: &str) -> Result<Prefix, DtabError> {
+        Prefix::read(s)
+    }
+}
+
+impl fmt::Display for Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.elems.is_empty() {
+            return f.write_str("/");
+        }
+
+        for elem in &self.elems {
+            match *elem {
+                PrefixElem::Label(ref label) => {
+                    f.write_str("/")?;
+                    show_elem(f, label)?;
+                }
+                PrefixElem::Any => f.write_str("/*")?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,392 @@
+//! Typed negotiation of the headers of `Tinit` and `Rinit` frames.
+//!
+//! The client advertises its `InitOptions` in the headers of its `Tinit`
+//! and the server answers with its own in the `Rinit`, see
+//! `client::Session::with_init_options` and
+//! `server::Server::with_init_options`. A `Negotiator` combines the two into
+//! the `NegotiatedParams` of the session. Besides the standard keys,
+//! applications negotiate keys of their own by implementing `InitKey` and
+//! registering it with the `Negotiator`.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::result;
+
+use super::*;
+
+/// Key of the TLS header.
+pub const TLS_KEY: &str = "tls";
+/// Key of the header advertising the maximum size of the frames accepted.
+pub const MUX_FRAMER_KEY: &str = "mux-framer";
+/// Key of the compression header.
+pub const COMPRESSION_KEY: &str = "compression";
+
+/// Result of the negotiation operations.
+pub type Result<T> = result::Result<T, InitError>;
+
+/// Errors of the negotiation of init headers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InitError {
+    /// The value of the header with the key doesn't decode.
+    InvalidValue(String),
+    /// One side requires TLS and the other has it off.
+    TlsRequired,
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InitError::InvalidValue(ref key) => write!(f, "Invalid value for init key '{}'", key),
+            InitError::TlsRequired => write!(f, "TLS required by one side but off on the other"),
+        }
+    }
+}
+
+impl error::Error for InitError {}
+
+/// Level of support for TLS advertised under the "tls" key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Tls {
+    /// TLS isn't supported. Peers not advertising TLS have it off.
+    #[default]
+    Off,
+    /// TLS is used if the peer supports it.
+    Desired,
+    /// The session fails unless the peer supports TLS.
+    Required,
+}
+
+/// Preference for compression advertised under the "compression" key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompressionLevel {
+    /// Compression isn't supported.
+    #[default]
+    Off,
+    /// Compression is used if the peer desires it.
+    Accepted,
+    /// Compression is used if the peer accepts it.
+    Desired,
+}
+
+/// Compression preferences: the level and the supported formats, most
+/// preferred first
+///
+/// Encoded as the level and the comma separated formats, for example
+/// `desired:lz4,gzip`. The encoding is this crate's own, peers of other mux
+/// implementations won't understand it.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Compression {
+    /// Preference for compressing the session.
+    pub level: CompressionLevel,
+    /// Names of the formats supported.
+    pub formats: Vec<String>,
+}
+
+/// A custom init header negotiated by applications.
+pub trait InitKey: Sized + Send + Sync + 'static {
+    /// Key of the header.
+    const KEY: &'static str;
+
+    /// Encode the value of the header.
+    fn encode(&self) -> Vec<u8>;
+
+    /// Decode the value of the header.
+    fn decode(value: &[u8]) -> Result<Self>;
+
+    /// The value agreed on given the values advertised by the client and
+    /// the server, `None` if they don't agree.
+    fn negotiate(client: Self, server: Self) -> Option<Self>;
+}
+
+/// The init headers advertised by one side of a session
+///
+/// Standard options are typed, headers with any other key are kept encoded
+/// in `custom`. Options that are off or unset aren't advertised.
+///
+/// ```rust
+/// use mux::init::{InitOptions, Tls};
+///
+/// let mut options = InitOptions::new();
+/// options.tls = Tls::Desired;
+/// options.max_frame_size = Some(64 * 1024);
+///
+/// let tinit = options.to_init(mux::VERSION);
+/// assert_eq!(InitOptions::from_init(&tinit).unwrap(), options);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct InitOptions {
+    /// Support for TLS.
+    pub tls: Tls,
+    /// Maximum size of the frames accepted, larger messages being split
+    /// into fragments.
+    pub max_frame_size: Option<u32>,
+    /// Compression preferences.
+    pub compression: Compression,
+    /// Headers with other keys.
+    pub custom: Contexts,
+}
+
+/// The parameters of a session agreed on by the client and the server.
+pub struct NegotiatedParams {
+    /// Mux protocol version, the lowest of the two.
+    pub version: u16,
+    /// Whether the session is upgraded to TLS.
+    pub tls: bool,
+    /// Maximum size of the frames of the session, if both sides fragment.
+    pub max_frame_size: Option<u32>,
+    /// Compression format of the session, if any.
+    pub compression: Option<String>,
+    // negotiated values of the registered `InitKey`s by key
+    custom: HashMap<&'static str, Box<dyn Any + Send + Sync>>,
+}
+
+// negotiates the encoded values of an `InitKey`
+type NegotiateFn = fn(&[u8], &[u8]) -> Result<Option<Box<dyn Any + Send + Sync>>>;
+
+/// Negotiator of the `NegotiatedParams` of a session
+///
+/// ```rust
+/// use mux::init::{InitKey, InitOptions, Negotiator, Result};
+///
+/// // the lowest of the two values
+/// struct Window(u8);
+///
+/// impl InitKey for Window {
+///     const KEY: &'static str = "window";
+///
+///     fn encode(&self) -> Vec<u8> {
+///         vec![self.0]
+///     }
+///
+///     fn decode(value: &[u8]) -> Result<Window> {
+///         Ok(Window(value[0]))
+///     }
+///
+///     fn negotiate(client: Window, server: Window) -> Option<Window> {
+///         Some(Window(client.0.min(server.0)))
+///     }
+/// }
+///
+/// let mut client = InitOptions::new();
+/// client.max_frame_size = Some(1024);
+/// client.set(&Window(8));
+/// let mut server = InitOptions::new();
+/// server.max_frame_size = Some(4096);
+/// server.set(&Window(4));
+///
+/// let mut negotiator = Negotiator::new();
+/// negotiator.register::<Window>();
+/// let params = negotiator.negotiate(&client.to_init(2), &server.to_init(1)).unwrap();
+///
+/// assert_eq!(params.version, 1);
+/// assert_eq!(params.max_frame_size, Some(1024));
+/// assert_eq!(params.get::<Window>().map(|w| w.0), Some(4));
+/// ```
+#[derive(Default)]
+pub struct Negotiator {
+    custom: HashMap<&'static str, NegotiateFn>,
+}
+
+impl InitOptions {
+    /// Construct new `InitOptions` advertising nothing.
+    pub fn new() -> InitOptions {
+        InitOptions::default()
+    }
+
+    /// Decode the options advertised in the headers of `init`.
+    pub fn from_init(init: &Init) -> Result<InitOptions> {
+        let mut options = InitOptions::new();
+        for (key, value) in &init.headers {
+            match &key[..] {
+                k if k == TLS_KEY.as_bytes() => options.tls = decode_tls(value)?,
+                k if k == MUX_FRAMER_KEY.as_bytes() => {
+                    options.max_frame_size = Some(decode_max_frame_size(value)?);
+                }
+                k if k == COMPRESSION_KEY.as_bytes() => {
+                    options.compression = decode_compression(value)?;
+                }
+                _ => options.custom.push((key.clone(), value.clone())),
+            }
+        }
+        Ok(options)
+    }
+
+    /// Encode the options as the headers of an `Init` of `version`.
+    pub fn to_init(&self, version: u16) -> Init {
+        let mut headers = Contexts::new();
+        if self.tls != Tls::Off {
+            headers.push(header(TLS_KEY, encode_tls(self.tls)));
+        }
+        if let Some(size) = self.max_frame_size {
+            headers.push(header(MUX_FRAMER_KEY, size.to_be_bytes().to_vec()));
+        }
+        if self.compression.level != CompressionLevel::Off {
+            headers.push(header(COMPRESSION_KEY, encode_compression(&self.compression)));
+        }
+        headers.extend(self.custom.iter().cloned());
+
+        Init { version, headers }
+    }
+
+    /// Advertise `value` for its `InitKey`, replacing any previous value.
+    pub fn set<K: InitKey>(&mut self, value: &K) {
+        self.custom.retain(|(key, _)| &key[..] != K::KEY.as_bytes());
+        self.custom.push(header(K::KEY, value.encode()));
+    }
+
+    /// The value advertised for `K`, if any.
+    pub fn get<K: InitKey>(&self) -> Result<Option<K>> {
+        custom_value(&self.custom, K::KEY).map(K::decode).transpose()
+    }
+}
+
+impl NegotiatedParams {
+    /// The negotiated value of `K`, if it was registered and agreed on.
+    pub fn get<K: InitKey>(&self) -> Option<&K> {
+        self.custom.get(K::KEY).and_then(|value| value.downcast_ref())
+    }
+}
+
+impl fmt::Debug for NegotiatedParams {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NegotiatedParams")
+            .field("version", &self.version)
+            .field("tls", &self.tls)
+            .field("max_frame_size", &self.max_frame_size)
+            .field("compression", &self.compression)
+            .field("custom", &self.custom.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Negotiator {
+    /// Construct a new `Negotiator` of the standard keys.
+    pub fn new() -> Negotiator {
+        Negotiator::default()
+    }
+
+    /// Negotiate `K` too. Keys that aren't registered are ignored.
+    pub fn register<K: InitKey>(&mut self) -> &mut Self {
+        self.custom.insert(K::KEY, negotiate_key::<K>);
+        self
+    }
+
+    /// Negotiate the parameters of a session from its `Tinit` and `Rinit`.
+    pub fn negotiate(&self, tinit: &Init, rinit: &Init) -> Result<NegotiatedParams> {
+        let client = InitOptions::from_init(tinit)?;
+        let server = InitOptions::from_init(rinit)?;
+
+        let tls = match (client.tls, server.tls) {
+            (Tls::Required, Tls::Off) | (Tls::Off, Tls::Required) => {
+                return Err(InitError::TlsRequired);
+            }
+            (Tls::Off, _) | (_, Tls::Off) => false,
+            _ => true,
+        };
+
+        let max_frame_size = match (client.max_frame_size, server.max_frame_size) {
+            (Some(c), Some(s)) => Some(c.min(s)),
+            _ => None,
+        };
+
+        let mut custom = HashMap::new();
+        for (&key, negotiate) in &self.custom {
+            let values = (custom_value(&client.custom, key), custom_value(&server.custom, key));
+            if let (Some(c), Some(s)) = values {
+                if let Some(value) = negotiate(c, s)? {
+                    custom.insert(key, value);
+                }
+            }
+        }
+
+        Ok(NegotiatedParams {
+            version: tinit.version.min(rinit.version),
+            tls,
+            max_frame_size,
+            compression: negotiate_compression(&client.compression, &server.compression),
+            custom,
+        })
+    }
+}
+
+fn negotiate_key<K: InitKey>(client: &[u8], server: &[u8]) -> Result<Option<Box<dyn Any + Send + Sync>>> {
+    let value = K::negotiate(K::decode(client)?, K::decode(server)?);
+    Ok(value.map(|v| Box::new(v) as Box<dyn Any + Send + Sync>))
+}
+
+// the client's most preferred format supported by the server, if either
+// side desires compression and the other accepts it
+fn negotiate_compression(client: &Compression, server: &Compression) -> Option<String> {
+    use self::CompressionLevel::*;
+    match (client.level, server.level) {
+        (Off, _) | (_, Off) | (Accepted, Accepted) => None,
+        _ => client.formats.iter().find(|f| server.formats.contains(f)).cloned(),
+    }
+}
+
+#[inline]
+fn header(key: &str, value: Vec<u8>) -> (Vec<u8>, Vec<u8>) {
+    (key.as_bytes().to_vec(), value)
+}
+
+fn custom_value<'a>(headers: &'a Contexts, key: &str) -> Option<&'a [u8]> {
+    headers.iter()
+        .find(|(k, _)| &k[..] == key.as_bytes())
+        .map(|(_, v)| &v[..])
+}
+
+fn encode_tls(tls: Tls) -> Vec<u8> {
+    let value = match tls {
+        Tls::Off => "off",
+        Tls::Desired => "desired",
+        Tls::Required => "required",
+    };
+    value.as_bytes().to_vec()
+}
+
+fn decode_tls(value: &[u8]) -> Result<Tls> {
+    match value {
+        b"off" => Ok(Tls::Off),
+        // peers predating the levels advertise TLS with an empty value
+        b"desired" | b"" => Ok(Tls::Desired),
+        b"required" => Ok(Tls::Required),
+        _ => Err(InitError::InvalidValue(TLS_KEY.to_string())),
+    }
+}
+
+fn decode_max_frame_size(value: &[u8]) -> Result<u32> {
+    match *value {
+        [a, b, c, d] => Ok(u32::from_be_bytes([a, b, c, d])),
+        _ => Err(InitError::InvalidValue(MUX_FRAMER_KEY.to_string())),
+    }
+}
+
+fn encode_compression(compression: &Compression) -> Vec<u8> {
+    let level = match compression.level {
+        CompressionLevel::Off => "off",
+        CompressionLevel::Accepted => "accepted",
+        CompressionLevel::Desired => "desired",
+    };
+    format!("{}:{}", level, compression.formats.join(",")).into_bytes()
+}
+
+fn decode_compression(value: &[u8]) -> Result<Compression> {
+    let invalid = || InitError::InvalidValue(COMPRESSION_KEY.to_string());
+    let value = ::std::str::from_utf8(value).map_err(|_| invalid())?;
+    let (level, formats) = value.split_once(':').unwrap_or((value, ""));
+
+    let level = match level {
+        "off" => CompressionLevel::Off,
+        "accepted" => CompressionLevel::Accepted,
+        "desired" => CompressionLevel::Desired,
+        _ => return Err(invalid()),
+    };
+    let formats = formats.split(',')
+        .filter(|f| !f.is_empty())
+        .map(|f| f.to_string())
+        .collect();
+
+    Ok(Compression { level, formats })
+}
@@ -0,0 +1,129 @@
+//! Tracking and issuance of `Tlease`s.
+//!
+//! Servers grant leases to shed load: a client holding an expired lease
+//! should refrain from issuing requests on the session until a new lease
+//! is granted. A `LeaseTracker` records the leases received by a client
+//! while a `LeasePolicy` decides the leases a server grants.
+
+use std::time::{Duration, Instant};
+
+use super::*;
+
+/// The lease currently held by a client
+///
+/// Until a lease is observed the client holds an indefinite lease.
+///
+/// ```rust
+/// use std::time::{Duration, Instant};
+/// use mux::Tlease;
+/// use mux::lease::LeaseTracker;
+///
+/// let start = Instant::now();
+/// let mut tracker = LeaseTracker::new();
+/// assert_eq!(tracker.remaining_at(start), None);
+///
+/// tracker.observe_at(&Tlease { duration: Duration::from_secs(10) }, start);
+/// let later = start + Duration::from_secs(4);
+/// assert_eq!(tracker.remaining_at(later), Some(Duration::from_secs(6)));
+/// assert!(tracker.is_expired_at(start + Duration::from_secs(10)));
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LeaseTracker {
+    // the latest lease and when it was received
+    latest: Option<(Instant, Tlease)>,
+}
+
+impl LeaseTracker {
+    /// Construct a new `LeaseTracker` holding an indefinite lease.
+    pub fn new() -> LeaseTracker {
+        LeaseTracker::default()
+    }
+
+    /// Record `tlease` as received now.
+    pub fn observe(&mut self, tlease: &Tlease) {
+        self.observe_at(tlease, Instant::now())
+    }
+
+    /// Record `tlease` as received at `at`.
+    pub fn observe_at(&mut self, tlease: &Tlease, at: Instant) {
+        self.latest = Some((at, *tlease));
+    }
+
+    /// The latest lease observed, if any.
+    #[inline]
+    pub fn latest(&self) -> Option<&Tlease> {
+        self.latest.as_ref().map(|(_, lease)| lease)
+    }
+
+    /// Time left on the lease, `None` if the lease is indefinite.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.remaining_at(Instant::now())
+    }
+
+    /// Time left on the lease at `now`, `None` if the lease is indefinite.
+    pub fn remaining_at(&self, now: Instant) -> Option<Duration> {
+        let (at, lease) = self.latest?;
+        let duration = lease.expires_in()?;
+        let elapsed = now.saturating_duration_since(at);
+        Some(duration.checked_sub(elapsed).unwrap_or(Duration::ZERO))
+    }
+
+    /// Whether the lease has run out.
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(Instant::now())
+    }
+
+    /// Whether the lease has run out at `now`.
+    pub fn is_expired_at(&self, now: Instant) -> bool {
+        self.remaining_at(now) == Some(Duration::ZERO)
+    }
+}
+
+/// Policy deciding the leases a server grants
+///
+/// The policy is consulted whenever the number of requests outstanding on
+/// a connection changes. The server only sends a `Tlease` when the lease
+/// differs from the last one it granted on the connection.
+pub trait LeasePolicy: Send + Sync {
+    /// The lease to grant with `outstanding` requests in progress, `None`
+    /// to leave the current lease in place.
+    fn lease(&self, outstanding: usize) -> Option<Tlease>;
+}
+
+/// A `LeasePolicy` revoking the lease of loaded connections
+///
+/// Connections with fewer than `max_outstanding` requests in progress are
+/// granted a lease of `duration`. Beyond that the lease is revoked with a
+/// zero length lease until the load drops.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use mux::Tlease;
+/// use mux::lease::{LeasePolicy, LoadLeasePolicy};
+///
+/// let policy = LoadLeasePolicy::new(2, Duration::from_secs(30));
+/// assert_eq!(policy.lease(1), Some(Tlease { duration: Duration::from_secs(30) }));
+/// assert_eq!(policy.lease(2), Some(Tlease { duration: Duration::ZERO }));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadLeasePolicy {
+    max_outstanding: usize,
+    duration: Duration,
+}
+
+impl LoadLeasePolicy {
+    /// Construct a new `LoadLeasePolicy`.
+    pub fn new(max_outstanding: usize, duration: Duration) -> LoadLeasePolicy {
+        LoadLeasePolicy { max_outstanding, duration }
+    }
+}
+
+impl LeasePolicy for LoadLeasePolicy {
+    fn lease(&self, outstanding: usize) -> Option<Tlease> {
+        if outstanding < self.max_outstanding {
+            Some(Tlease { duration: self.duration })
+        } else {
+            Some(Tlease { duration: Duration::ZERO })
+        }
+    }
+}
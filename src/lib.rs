@@ -7,11 +7,25 @@
 
 extern crate byteorder;
 
+mod borrowed;
 mod dtab;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod client;
 pub mod codec;
+pub mod contexts;
+#[cfg(feature = "mio")]
+pub mod connection;
+pub mod init;
+pub mod lease;
+pub mod server;
+mod tags;
+pub mod trace;
 pub mod types;
 
+pub use borrowed::*;
 pub use dtab::*;
+pub use tags::*;
 use std::time::Duration;
 
 /// Headers for a `Treq`.
@@ -20,6 +34,9 @@ pub type Headers = Vec<(u8, Vec<u8>)>;
 /// Contexts of dispatch and init messages.
 pub type Contexts = Vec<(Vec<u8>, Vec<u8>)>;
 
+/// Version of the mux protocol implemented by the sessions.
+pub const VERSION: u16 = 1;
+
 /// Maximum value of a mux Tag
 pub const MAX_TAG: u32 = (1 << 23) - 1;
 
@@ -30,12 +47,14 @@ pub const MAX_TAG: u32 = (1 << 23) - 1;
 /// to 23 bits of precision while bit 24 signals if the message stream is ending.
 /// This only applies to the `Tdispatch` and `Rdispatch` frames.
 #[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Tag {
     /// Signal that this frame is the end of the stream of fragments.
     ///
     /// Currently, only Tdispatch and Rdispatch messages may be split into an
-    /// ordered sequence of fragments. TdispatchError message ends a Tdispatch
-    /// sequence and an Rerr ends an Rdispatch sequence.
+    /// ordered sequence of fragments. A Tdiscarded abandons a Tdispatch
+    /// sequence and an Rerr ends an Rdispatch sequence. See
+    /// `codec::Fragmenter` and `codec::Reassembler`.
     pub end: bool,
     /// Identification number associated with this stream.
     pub id: u32,
@@ -43,6 +62,7 @@ pub struct Tag {
 
 /// Representation of an entire mux packet.
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Message {
     /// Identification and termination information about the associated stream.
     pub tag: Tag,
@@ -52,6 +72,7 @@ pub struct Message {
 
 /// Type wrapper for the mux packet representations.
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MessageFrame {
     Treq(Treq),
     Rreq(Rmsg),
@@ -64,26 +85,45 @@ pub enum MessageFrame {
     Tping,
     Rping,
     Tdiscarded(Tdiscarded),
+    /// Acknowledgement of a `Tdiscarded`. The `Tag` of the enclosing
+    /// `Message` is the id of the discarded request.
+    Rdiscarded,
     Tlease(Tlease),
     Rerr(Rerr),
+    /// A frame of a type unknown to this crate, preserved verbatim.
+    ///
+    /// Only produced when `DecoderConfig::allow_unknown_frames` is set so
+    /// that relays can forward frames they don't understand. It is encoded
+    /// back with its original type and body.
+    Unknown {
+        /// Type of the frame as read from the wire.
+        tpe: i8,
+        /// Raw body of the frame.
+        #[cfg_attr(feature = "serde", serde(with = "crate::serialize::body"))]
+        body: Vec<u8>,
+    },
 }
 
 // Structs that model the message frame types of the mux protocol
 
 /// Representation of the mux `Treq` types.
 #[derive(PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Treq {
     /// Request headers.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::headers"))]
     pub headers: Headers,
     /// Body of the request.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::body"))]
     pub body: Vec<u8>,
 }
 
 /// Representation of a mux `Rreq` and `Rdispatch` message body.
 #[derive(PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Rmsg {
     /// Successful response containing a body.
-    Ok(Vec<u8>),
+    Ok(#[cfg_attr(feature = "serde", serde(with = "crate::serialize::body"))] Vec<u8>),
     /// Response failed. The `String` describes the error.
     Error(String),
     /// Negative acknowledgment. The `String` describes the reason.
@@ -92,21 +132,26 @@ pub enum Rmsg {
 
 /// Representation of a mux `Tdispatch` frame.
 #[derive(PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Tdispatch {
     /// Context information associated with this request.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::contexts"))]
     pub contexts: Contexts,
     /// Destination of this request.
     pub dest: String,
     /// Table of delegation rules for 'rewriting' the destination.
     pub dtab: Dtab,
     /// Message payload.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::body"))]
     pub body: Vec<u8>,
 }
 
 /// Representation of a mux `Rdispatch` frame.
 #[derive(PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Rdispatch {
     /// Context information associated with this request.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::contexts"))]
     pub contexts: Contexts,
     /// Response of the dispatch request.
     pub msg: Rmsg,
@@ -120,11 +165,13 @@ pub struct Rdispatch {
 /// issue any more T messages. Once the `Rinit` is received, the session state
 /// is considered reset. The version return in `Rinit` is the accepted protocol
 /// version and may be lower than that of the issued `Tinit`.
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Init {
     /// Mux protocol version.
     pub version: u16,
     /// Additional negotiation related information.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::contexts"))]
     pub headers: Contexts,
 }
 
@@ -133,8 +180,10 @@ pub struct Init {
 /// A `Tdiscarded` frame is a marker message alerting the server that the
 /// client has discarded the `Tdispatch` issued with the associated id. This
 /// does not free the server from the obligation of replying to the origional
-/// request.
+/// request. The server may acknowledge the discard with an `Rdiscarded`
+/// carrying the discarded id as its `Tag`, after which the id can be reused.
 #[derive(PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Tdiscarded {
     /// Stream id of the discarded `Tdispatch` request.
     pub id: u32,
@@ -148,10 +197,14 @@ pub struct Tdiscarded {
 /// it has been allocated resources for a specific duration. In the abscence
 /// of a `Tlease`, the client assumes it holds an indefinate lease.
 /// Adhering to the lease is optional but the server may reject requests or
-/// operate at a degraded capacity under and expired lease.
-#[derive(PartialEq, Eq, Debug)]
+/// operate at a degraded capacity under and expired lease. See
+/// `lease::LeaseTracker`. Leases in 'howmuch' units other than milliseconds
+/// are decoded as `MessageFrame::Unknown`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Tlease {
     /// `Duration` of the lease allocated to the client.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::millis"))]
     pub duration: Duration,
 }
 
@@ -160,6 +213,7 @@ pub struct Tlease {
 /// An `Rerr` is sent from the server in the even that the server failed to
 /// interpret or act on a request T message.
 #[derive(PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Rerr {
     /// Description of the error.
     pub msg: String,
@@ -168,9 +222,23 @@ pub struct Rerr {
 impl Tag {
     #[inline]
     /// Construct a new `Tag`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` exceeds `MAX_TAG`, see `Tag::try_new`.
     pub fn new(end: bool, id: u32) -> Tag {
         assert!(id <= MAX_TAG);
-        Tag { end: end, id: id, }
+        Tag { end, id, }
+    }
+
+    #[inline]
+    /// Construct a new `Tag`, failing if `id` exceeds `MAX_TAG`.
+    pub fn try_new(end: bool, id: u32) -> Result<Tag, TagError> {
+        if id <= MAX_TAG {
+            Ok(Tag { end, id, })
+        } else {
+            Err(TagError::OutOfRange(id))
+        }
     }
 }
 
@@ -189,8 +257,27 @@ impl MessageFrame {
             MessageFrame::Tping => types::TPING,
             MessageFrame::Rping => types::RPING,
             MessageFrame::Tdiscarded(_) => types::TDISCARDED,
+            MessageFrame::Rdiscarded => types::RDISCARDED,
             MessageFrame::Tlease(_) => types::TLEASE,
             MessageFrame::Rerr(_) => types::RERR,
+            MessageFrame::Unknown { tpe, .. } => tpe,
+        }
+    }
+}
+
+impl Tlease {
+    /// Construct a lease that never expires, encoded as the maximum `i64`
+    /// milliseconds.
+    pub fn infinite() -> Tlease {
+        Tlease { duration: Duration::MAX }
+    }
+
+    /// Time until the lease expires, `None` if it never does.
+    pub fn expires_in(&self) -> Option<Duration> {
+        if self.duration.as_millis() >= i64::MAX as u128 {
+            None
+        } else {
+            Some(self.duration)
         }
     }
 }
@@ -200,9 +287,9 @@ impl Tdispatch {
     pub fn new(dest: String, body: Vec<u8>) -> Tdispatch {
         Tdispatch {
             contexts: Vec::new(),
-            dest: dest,
+            dest,
             dtab: Dtab::new(),
-            body: body,
+            body,
         }
     }
 }
@@ -0,0 +1,146 @@
+// Serde representations of the fields of messages that have no natural one.
+//
+// Payloads are base64 strings in human readable formats, such as JSON, and
+// byte arrays in the others. Arrays of bytes are accepted in either. Lease
+// durations are milliseconds and dtab entries are in their textual form.
+
+use std::fmt;
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+// payload borrowed for serialization
+struct BytesRef<'a>(&'a [u8]);
+
+// payload deserialized from either representation
+struct BytesBuf(Vec<u8>);
+
+struct BytesVisitor;
+
+impl Serialize for BytesRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&STANDARD.encode(self.0))
+        } else {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BytesBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<BytesBuf, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(BytesVisitor)
+        } else {
+            deserializer.deserialize_byte_buf(BytesVisitor)
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = BytesBuf;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a base64 string or an array of bytes")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<BytesBuf, E> {
+        STANDARD.decode(v).map(BytesBuf).map_err(E::custom)
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<BytesBuf, E> {
+        Ok(BytesBuf(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<BytesBuf, E> {
+        Ok(BytesBuf(v))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<BytesBuf, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(BytesBuf(bytes))
+    }
+}
+
+pub(crate) mod body {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(body: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        BytesRef(body).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        BytesBuf::deserialize(deserializer).map(|bytes| bytes.0)
+    }
+}
+
+// contexts are sequences of key-value pairs
+pub(crate) mod contexts {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(contexts: &Contexts, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(contexts.iter().map(|(key, value)| (BytesRef(key), BytesRef(value))))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Contexts, D::Error> {
+        let contexts = Vec::<(BytesBuf, BytesBuf)>::deserialize(deserializer)?;
+        Ok(contexts.into_iter()
+            .map(|(key, value)| (key.0, value.0))
+            .collect())
+    }
+}
+
+// headers are sequences of key-value pairs too
+pub(crate) mod headers {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(headers: &Headers, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(headers.iter().map(|(key, value)| (key, BytesRef(value))))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Headers, D::Error> {
+        let headers = Vec::<(u8, BytesBuf)>::deserialize(deserializer)?;
+        Ok(headers.into_iter().map(|(key, value)| (key, value.0)).collect())
+    }
+}
+
+pub(crate) mod millis {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis().min(u64::MAX as u128) as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+// values in their textual form, the prefixes and destinations of dentries
+pub(crate) mod text {
+    use std::str::FromStr;
+
+    use super::*;
+
+    pub fn serialize<T: fmt::Display, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+        where T: FromStr,
+              T::Err: fmt::Display,
+              D: Deserializer<'de>
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
@@ -0,0 +1,297 @@
+// Async server session running on tokio.
+
+use std::future::Future;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use bytes::BytesMut;
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+use super::super::*;
+use super::super::codec::{self, DecoderConfig, Error, MuxCodec, Reassembler, Result};
+use super::super::init::InitOptions;
+use super::super::lease::LeasePolicy;
+use super::super::trace::{Annotation, Span, Tracer};
+use super::drain::Connections;
+use super::{deadline_exceeded, is_expired, rerr, Leases, Pending, DRAIN_TAG};
+
+/// An async mux server
+///
+/// Every `Tdispatch` is answered by the async `handler` on its own tokio
+/// task so the dispatches multiplexed on a connection are handled
+/// concurrently. Session control messages are answered by the server and
+/// `Treq`s, which have no handler, are failed. Connections are drained,
+/// discards are acknowledged, fragmented dispatches are reassembled,
+/// dispatches past their deadline are nacked and leases are granted, like
+/// those of a `Server`.
+///
+/// ```rust,no_run
+/// use mux::{Rdispatch, Rmsg, Tdispatch};
+/// use mux::server::AsyncServer;
+///
+/// # async fn run() -> mux::codec::Result<()> {
+/// let server = AsyncServer::new(|tdispatch: Tdispatch| async move {
+///     Rdispatch { contexts: Vec::new(), msg: Rmsg::Ok(tdispatch.body) }
+/// });
+///
+/// let listener = tokio::net::TcpListener::bind("localhost:9000").await?;
+/// let (stream, _) = listener.accept().await?;
+/// server.serve(stream).await
+/// # }
+/// ```
+pub struct AsyncServer<F> {
+    handler: Arc<F>,
+    init_options: Arc<InitOptions>,
+    lease_policy: Option<Arc<dyn LeasePolicy>>,
+    tracer: Option<Arc<dyn Tracer>>,
+    connections: Arc<Connections>,
+}
+
+impl<F> Clone for AsyncServer<F> {
+    fn clone(&self) -> AsyncServer<F> {
+        AsyncServer {
+            handler: self.handler.clone(),
+            init_options: self.init_options.clone(),
+            lease_policy: self.lease_policy.clone(),
+            tracer: self.tracer.clone(),
+            connections: self.connections.clone(),
+        }
+    }
+}
+
+impl<F, Fut> AsyncServer<F>
+    where F: Fn(Tdispatch) -> Fut + Send + Sync + 'static,
+          Fut: Future<Output = Rdispatch> + Send + 'static
+{
+    /// Construct a new `AsyncServer` answering `Tdispatch`s with `handler`.
+    pub fn new(handler: F) -> AsyncServer<F> {
+        AsyncServer {
+            handler: Arc::new(handler),
+            init_options: Arc::new(InitOptions::new()),
+            lease_policy: None,
+            tracer: None,
+            connections: Arc::new(Connections::default()),
+        }
+    }
+
+    /// Advertise `options` in the headers of the `Rinit`, like a `Server`.
+    pub fn with_init_options(mut self, options: InitOptions) -> AsyncServer<F> {
+        self.init_options = Arc::new(options);
+        self
+    }
+
+    /// Grant leases to each connection according to `policy`, like a
+    /// `Server`.
+    pub fn with_lease_policy<P: LeasePolicy + 'static>(mut self, policy: P) -> AsyncServer<F> {
+        self.lease_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Trace the dispatches answered with `tracer`, like a `Server`.
+    pub fn with_tracer<T: Tracer + 'static>(mut self, tracer: T) -> AsyncServer<F> {
+        self.tracer = Some(Arc::new(tracer));
+        self
+    }
+
+    /// Drain every connection served and wait for them to close
+    ///
+    /// The `AsyncServer` and its clones share their connections.
+    /// Connections opened while draining are drained right away. Bound the
+    /// wait with `tokio::time::timeout` to force a deadline.
+    pub async fn drain(&self) {
+        self.connections.drain();
+        let connections = self.connections.clone();
+        let _ = tokio::task::spawn_blocking(move || connections.wait(None)).await;
+    }
+
+    /// Whether the server is draining its connections.
+    pub fn is_draining(&self) -> bool {
+        self.connections.is_draining()
+    }
+
+    /// Number of connections being served.
+    pub fn connections(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// Serve a mux session over `io`
+    ///
+    /// Completes once the peer closes the connection, or acknowledges a
+    /// drain, and the responses to the dispatches still in progress have
+    /// been written. Frames that fail to decode are answered with an `Rerr`
+    /// and the session continues. A frame that can't be read ends the
+    /// session with an `Rerr` on tag 0.
+    pub async fn serve<T>(&self, io: T) -> Result<()>
+        where T: AsyncRead + AsyncWrite + Send + Unpin + 'static
+    {
+        let (mut sink, mut stream) = Framed::new(io, RequestCodec::new()).split();
+        let (writer, mut queue) = mpsc::unbounded_channel::<Message>();
+
+        let write_task = tokio::spawn(async move {
+            while let Some(msg) = queue.recv().await {
+                if sink.send(msg).await.is_err() {
+                    break;
+                }
+            }
+            let _ = sink.close().await;
+        });
+
+        // a weak sender so the write task isn't kept alive by the registration
+        let drain = writer.downgrade();
+        let registration = Connections::open(&self.connections, Arc::new(move || {
+            if let Some(writer) = drain.upgrade() {
+                let _ = writer.send(Message { tag: Tag::new(true, DRAIN_TAG), frame: MessageFrame::Tdrain });
+            }
+        }));
+
+        let pending = Arc::new(Mutex::new(Pending::default()));
+        let leases = self.lease_policy.as_ref().map(|policy| Arc::new(Mutex::new(Leases::new(policy.clone()))));
+        let mut dispatches = JoinSet::new();
+
+        let mut result = Ok(());
+        while let Some(msg) = stream.next().await {
+            // forget the dispatches already answered
+            while dispatches.try_join_next().is_some() {}
+
+            let Message { tag, frame } = match msg {
+                Ok(Ok(msg)) => msg,
+                // the frame is already read so this is never a stream failure
+                Ok(Err((tag, e))) => {
+                    let _ = writer.send(Message { tag: Tag::new(true, tag.id), frame: rerr(e.to_string()) });
+                    continue;
+                }
+                Err(e) => {
+                    // the stream can't be read past the failure, tell the
+                    // client why the session ends
+                    let _ = writer.send(Message { tag: Tag::new(true, 0), frame: rerr(e.to_string()) });
+                    result = Err(e);
+                    break;
+                }
+            };
+
+            let frame = match frame {
+                MessageFrame::Tinit(tinit) => {
+                    let rinit = MessageFrame::Rinit(self.init_options.to_init(tinit.version.min(VERSION)));
+                    let _ = writer.send(Message { tag, frame: rinit });
+                    // the initial lease follows the negotiation
+                    update_lease(&leases, &writer, 0);
+                    continue;
+                }
+                MessageFrame::Tping => MessageFrame::Rping,
+                MessageFrame::Tdrain => MessageFrame::Rdrain,
+                // the client issues no more requests once it acknowledged a drain
+                MessageFrame::Rdrain => break,
+                MessageFrame::Tdispatch(ref tdispatch) if is_expired(tdispatch) => deadline_exceeded(),
+                MessageFrame::Tdispatch(mut tdispatch) => {
+                    let handler = self.handler.clone();
+                    let writer = writer.clone();
+                    let pending = pending.clone();
+                    let leases = leases.clone();
+                    let span = self.tracer.as_ref().map(|tracer| Span::server(tracer, &mut tdispatch));
+                    update_lease(&leases, &writer, 1);
+                    let token = pending.lock().unwrap().insert(tag.id);
+                    dispatches.spawn(async move {
+                        let frame = MessageFrame::Rdispatch(handler(tdispatch).await);
+                        if let Some(span) = span {
+                            span.record(Annotation::ServerSend);
+                        }
+                        // the response of a discarded dispatch is dropped
+                        if pending.lock().unwrap().remove(tag.id, token) {
+                            let _ = writer.send(Message { tag, frame });
+                        }
+                        update_lease(&leases, &writer, -1);
+                    });
+                    continue;
+                }
+                MessageFrame::Treq(_) => {
+                    MessageFrame::Rreq(Rmsg::Error("Treq not supported".to_string()))
+                }
+                MessageFrame::Tdiscarded(tdiscarded) => {
+                    if pending.lock().unwrap().discard(tdiscarded.id) {
+                        let tag = Tag::new(true, tdiscarded.id);
+                        let _ = writer.send(Message { tag, frame: MessageFrame::Rdiscarded });
+                    }
+                    continue;
+                }
+                ref other if other.frame_id() < 0 => continue,
+                ref other => rerr(format!("Unexpected frame type: {}", other.frame_id())),
+            };
+
+            let _ = writer.send(Message { tag, frame });
+        }
+
+        // the write task finishes once the dispatches in progress have
+        // answered, the connection only counts as closed after that
+        drop(writer);
+        while dispatches.join_next().await.is_some() {}
+        let _ = write_task.await;
+        drop(registration);
+        result
+    }
+}
+
+// account for `delta` more dispatches in progress, granting a new lease if
+// the policy decides on one that differs from the last
+fn update_lease(leases: &Option<Arc<Mutex<Leases>>>, writer: &mpsc::UnboundedSender<Message>, delta: isize) {
+    if let Some(ref leases) = *leases {
+        // the lock is held while queueing so leases are granted in order
+        let mut leases = leases.lock().unwrap();
+        if let Some(lease) = leases.update(delta) {
+            let _ = writer.send(Message { tag: Tag::new(true, 0), frame: MessageFrame::Tlease(lease) });
+        }
+    }
+}
+
+// Frames the requests of a session. Frames that fail to decode are yielded
+// with the tag they were read with so they can be answered, only a frame
+// that can't be read fails the stream.
+struct RequestCodec {
+    config: DecoderConfig,
+    reassembler: Reassembler,
+    encoder: MuxCodec,
+}
+
+impl RequestCodec {
+    fn new() -> RequestCodec {
+        let config = DecoderConfig::default();
+        RequestCodec {
+            config,
+            reassembler: Reassembler::with_config(config),
+            encoder: MuxCodec::with_config(config),
+        }
+    }
+}
+
+impl Decoder for RequestCodec {
+    type Item = std::result::Result<Message, (Tag, Error)>;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>> {
+        loop {
+            let frame = match codec::split_frame_with(src, &self.config)? {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+
+            let fragment = codec::split_fragment(frame)?;
+            let tag = fragment.tag.clone();
+            match self.reassembler.push(fragment) {
+                Ok(Some(msg)) => return Ok(Some(Ok(msg))),
+                Ok(None) => (),
+                Err(e) => return Ok(Some(Err((tag, e)))),
+            }
+        }
+    }
+}
+
+impl Encoder<Message> for RequestCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, msg: Message, dst: &mut BytesMut) -> io::Result<()> {
+        self.encoder.encode(msg, dst)
+    }
+}
@@ -0,0 +1,108 @@
+// Tracking of the connections of a server, for draining them.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+// initiates the drain of a connection
+type DrainFn = Arc<dyn Fn() + Send + Sync>;
+
+#[derive(Default)]
+pub(super) struct Connections {
+    state: Mutex<State>,
+    // signaled when the last connection closes
+    idle: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    draining: bool,
+    open: HashMap<u64, DrainFn>,
+}
+
+// keeps a connection open in `Connections` until dropped
+pub(super) struct Registration {
+    connections: Arc<Connections>,
+    id: u64,
+}
+
+impl Connections {
+    #[inline]
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    // track a connection until the `Registration` is dropped. A connection
+    // opened while draining is drained right away.
+    pub(super) fn open(connections: &Arc<Connections>, drain: DrainFn) -> Registration {
+        let (id, draining) = {
+            let mut state = connections.state();
+            let id = state.next_id;
+            state.next_id += 1;
+            state.open.insert(id, drain.clone());
+            (id, state.draining)
+        };
+
+        // drains run unlocked, they may take a while and call back into
+        // the server
+        if draining {
+            drain();
+        }
+        Registration { connections: connections.clone(), id }
+    }
+
+    // initiate the drain of every connection, once
+    pub(super) fn drain(&self) {
+        let drains: Vec<DrainFn> = {
+            let mut state = self.state();
+            if state.draining {
+                return;
+            }
+            state.draining = true;
+            state.open.values().cloned().collect()
+        };
+
+        for drain in drains {
+            drain();
+        }
+    }
+
+    pub(super) fn is_draining(&self) -> bool {
+        self.state().draining
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.state().open.len()
+    }
+
+    // wait for every connection to close, at most `timeout`. Returns
+    // whether they did.
+    pub(super) fn wait(&self, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.state();
+        while !state.open.is_empty() {
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    self.idle.wait_timeout(state, deadline - now).unwrap().0
+                }
+                None => self.idle.wait(state).unwrap(),
+            };
+        }
+        true
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let mut state = self.connections.state();
+        state.open.remove(&self.id);
+        if state.open.is_empty() {
+            self.connections.idle.notify_all();
+        }
+    }
+}
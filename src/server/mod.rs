@@ -0,0 +1,458 @@
+//! Blocking mux server sessions.
+//!
+//! A `Server` answers the session control messages of a connection itself
+//! and hands the application messages to a `Service`. Each `Tdispatch` and
+//! `Treq` is handled on its own thread so a slow request doesn't hold up the
+//! others multiplexed on the same connection. With the `tokio` feature,
+//! the `AsyncServer` serves async handlers on tokio tasks instead.
+//!
+//! A server given a `LeasePolicy` grants each connection leases according to
+//! the number of requests in progress on it.
+//!
+//! Servers shed their connections gracefully by draining them: a `Tdrain` is
+//! sent to the client, which acknowledges it with an `Rdrain` and stops
+//! issuing requests. The connection closes once the requests in progress
+//! are answered.
+//!
+//! A `Tdiscarded` of a request in progress is acknowledged with an
+//! `Rdiscarded` and the response of the request is dropped, so the client
+//! may reuse its tag right away. Discards of requests already answered are
+//! ignored.
+//!
+//! Dispatches received past the `contexts::Deadline` they carry are answered
+//! with an `Rmsg::Nack` without reaching the service. Servers given a
+//! `trace::Tracer` trace the dispatches they answer in the span they carry.
+
+use std::collections::HashMap;
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use super::*;
+use super::codec::{Error, Reassembler, Result};
+use super::init::InitOptions;
+use super::lease::LeasePolicy;
+use super::trace::{Annotation, Span, Tracer};
+
+#[cfg(feature = "tokio")]
+mod async_server;
+mod drain;
+
+#[cfg(feature = "tokio")]
+pub use self::async_server::AsyncServer;
+
+use self::drain::Connections;
+
+// tag of the Tdrain issued by a draining server
+const DRAIN_TAG: u32 = 1;
+
+/// Handler of the application messages of a mux session.
+pub trait Service: Send + Sync + 'static {
+    /// Answer a `Tdispatch`
+    ///
+    /// If the client set a deadline, the time left before it gives up is
+    /// `tdispatch.deadline().map(|deadline| deadline.remaining())`.
+    fn dispatch(&self, tdispatch: Tdispatch) -> Rdispatch;
+
+    /// Answer a `Treq`. Fails the request unless overridden.
+    fn request(&self, _treq: Treq) -> Rmsg {
+        Rmsg::Error("Treq not supported".to_string())
+    }
+}
+
+/// A blocking mux server
+///
+/// ```rust,no_run
+/// use std::net::TcpListener;
+/// use mux::{Rdispatch, Rmsg, Tdispatch};
+/// use mux::server::{Server, Service};
+///
+/// struct Echo;
+///
+/// impl Service for Echo {
+///     fn dispatch(&self, tdispatch: Tdispatch) -> Rdispatch {
+///         Rdispatch { contexts: Vec::new(), msg: Rmsg::Ok(tdispatch.body) }
+///     }
+/// }
+///
+/// let listener = TcpListener::bind("localhost:9000").unwrap();
+/// Server::new(Echo).listen(listener).unwrap();
+/// ```
+pub struct Server<S> {
+    service: Arc<S>,
+    init_options: Arc<InitOptions>,
+    lease_policy: Option<Arc<dyn LeasePolicy>>,
+    tracer: Option<Arc<dyn Tracer>>,
+    connections: Arc<Connections>,
+}
+
+type SharedWriter = Arc<Mutex<Box<dyn Write + Send>>>;
+
+// the lease granted on a connection and the load it was granted for
+struct Leases {
+    policy: Arc<dyn LeasePolicy>,
+    outstanding: usize,
+    granted: Option<Tlease>,
+}
+
+// the number of requests in progress on a connection
+#[derive(Default)]
+struct InFlight {
+    count: Mutex<usize>,
+    // signaled when the last request in progress is answered
+    idle: Condvar,
+}
+
+// the state of a connection shared with the threads answering requests
+#[derive(Clone)]
+struct Conn {
+    writer: SharedWriter,
+    in_flight: Arc<InFlight>,
+    pending: Arc<Mutex<Pending>>,
+    leases: Option<Arc<Mutex<Leases>>>,
+}
+
+// the requests in progress that weren't discarded, by tag id. Each request
+// is told apart by a token of its own, so that once discarded its response
+// isn't mistaken for that of a later request reusing the tag.
+#[derive(Default)]
+struct Pending {
+    next_token: u64,
+    tokens: HashMap<u32, u64>,
+}
+
+impl<S> Clone for Server<S> {
+    fn clone(&self) -> Server<S> {
+        Server {
+            service: self.service.clone(),
+            init_options: self.init_options.clone(),
+            lease_policy: self.lease_policy.clone(),
+            tracer: self.tracer.clone(),
+            connections: self.connections.clone(),
+        }
+    }
+}
+
+impl<S: Service> Server<S> {
+    /// Construct a new `Server` answering requests with `service`.
+    pub fn new(service: S) -> Server<S> {
+        Server {
+            service: Arc::new(service),
+            init_options: Arc::new(InitOptions::new()),
+            lease_policy: None,
+            tracer: None,
+            connections: Arc::new(Connections::default()),
+        }
+    }
+
+    /// Advertise `options` in the headers of the `Rinit` answering a `Tinit`.
+    pub fn with_init_options(mut self, options: InitOptions) -> Server<S> {
+        self.init_options = Arc::new(options);
+        self
+    }
+
+    /// Grant leases to each connection according to `policy`.
+    pub fn with_lease_policy<P: LeasePolicy + 'static>(mut self, policy: P) -> Server<S> {
+        self.lease_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Trace the dispatches answered with `tracer`
+    ///
+    /// The service is handed dispatches carrying their span, a new trace
+    /// being started for those without one, so that the dispatches it issues
+    /// in turn are its children.
+    pub fn with_tracer<T: Tracer + 'static>(mut self, tracer: T) -> Server<S> {
+        self.tracer = Some(Arc::new(tracer));
+        self
+    }
+
+    /// Drain every connection served and wait for them to close
+    ///
+    /// The `Server` and its clones share their connections so a clone may
+    /// drain the connections of a `listen`ing server. Connections opened
+    /// while draining are drained right away.
+    pub fn drain(&self) {
+        self.connections.drain();
+        self.connections.wait(None);
+    }
+
+    /// Drain every connection served, waiting at most `timeout` for them to
+    /// close. Returns whether they all did.
+    pub fn drain_timeout(&self, timeout: Duration) -> bool {
+        self.connections.drain();
+        self.connections.wait(Some(timeout))
+    }
+
+    /// Whether the server is draining its connections.
+    pub fn is_draining(&self) -> bool {
+        self.connections.is_draining()
+    }
+
+    /// Number of connections being served.
+    pub fn connections(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// Accept connections from `listener`, serving each on its own thread.
+    pub fn listen(&self, listener: TcpListener) -> Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = self.clone();
+            thread::spawn(move || server.serve_tcp(stream));
+        }
+        Ok(())
+    }
+
+    /// Serve a mux session over a `TcpStream` until it is closed.
+    pub fn serve_tcp(&self, stream: TcpStream) -> Result<()> {
+        stream.set_nodelay(true)?;
+        let reader = stream.try_clone()?;
+        self.serve(reader, stream)
+    }
+
+    /// Serve a mux session over the two halves of a connection
+    ///
+    /// Blocks until the peer closes the connection, or acknowledges a drain,
+    /// and the requests in progress are answered. Frames that fail to
+    /// decode are answered with an `Rerr` and the session continues. A frame
+    /// that can't be read ends the session with an `Rerr` on tag 0.
+    pub fn serve<R, W>(&self, reader: R, writer: W) -> Result<()>
+        where R: Read,
+              W: Write + Send + 'static
+    {
+        let mut reader = BufReader::new(reader);
+        let conn = Conn {
+            writer: Arc::new(Mutex::new(Box::new(writer))),
+            in_flight: Arc::new(InFlight::default()),
+            pending: Arc::new(Mutex::new(Pending::default())),
+            leases: self.lease_policy.as_ref().map(|policy| Arc::new(Mutex::new(Leases::new(policy.clone())))),
+        };
+        let mut reassembler = Reassembler::new();
+
+        let drain = conn.clone();
+        let _registration = Connections::open(&self.connections, Arc::new(move || {
+            let _ = send(&drain.writer, Tag::new(true, DRAIN_TAG), MessageFrame::Tdrain);
+        }));
+
+        loop {
+            let fragment = match codec::read_fragment(&mut reader) {
+                Ok(fragment) => fragment,
+                Err(Error::Io(ref e)) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => {
+                    // the stream can't be read past the failure, tell the
+                    // client why the session ends
+                    let _ = send(&conn.writer, Tag::new(true, 0), rerr(e.to_string()));
+                    conn.in_flight.wait();
+                    return Err(e);
+                }
+            };
+
+            let tag = fragment.tag.clone();
+            match reassembler.push(fragment) {
+                // the client issues no more requests once it acknowledged a drain
+                Ok(Some(Message { frame: MessageFrame::Rdrain, .. })) => break,
+                Ok(Some(msg)) => self.handle(msg, &conn)?,
+                Ok(None) => (),
+                // the frame is already read so this is never a stream failure
+                Err(e) => send(&conn.writer, Tag::new(true, tag.id), rerr(e.to_string()))?,
+            }
+        }
+
+        conn.in_flight.wait();
+        Ok(())
+    }
+
+    fn handle(&self, msg: Message, conn: &Conn) -> Result<()> {
+        let Message { tag, frame } = msg;
+        let frame = match frame {
+            MessageFrame::Tinit(tinit) => {
+                let rinit = MessageFrame::Rinit(self.init_options.to_init(tinit.version.min(VERSION)));
+                // the initial lease follows the negotiation
+                send(&conn.writer, tag, rinit)?;
+                return conn.update_lease(0);
+            }
+            MessageFrame::Tping => MessageFrame::Rping,
+            MessageFrame::Tdrain => MessageFrame::Rdrain,
+            MessageFrame::Tdispatch(ref tdispatch) if is_expired(tdispatch) => deadline_exceeded(),
+            MessageFrame::Tdispatch(mut tdispatch) => {
+                let service = self.service.clone();
+                let span = self.tracer.as_ref().map(|tracer| Span::server(tracer, &mut tdispatch));
+                return self.spawn(tag, conn, move || {
+                    let rdispatch = service.dispatch(tdispatch);
+                    if let Some(span) = span {
+                        span.record(Annotation::ServerSend);
+                    }
+                    MessageFrame::Rdispatch(rdispatch)
+                });
+            }
+            MessageFrame::Treq(treq) => {
+                let service = self.service.clone();
+                return self.spawn(tag, conn, move || MessageFrame::Rreq(service.request(treq)));
+            }
+            MessageFrame::Tdiscarded(ref tdiscarded) => {
+                if !conn.pending.lock().unwrap().discard(tdiscarded.id) {
+                    return Ok(());
+                }
+                return send(&conn.writer, Tag::new(true, tdiscarded.id), MessageFrame::Rdiscarded);
+            }
+            ref other if other.frame_id() < 0 => return Ok(()),
+            ref other => rerr(format!("Unexpected frame type: {}", other.frame_id())),
+        };
+
+        send(&conn.writer, tag, frame)
+    }
+
+    // answer an application message on its own thread
+    fn spawn<F>(&self, tag: Tag, conn: &Conn, f: F) -> Result<()>
+        where F: FnOnce() -> MessageFrame + Send + 'static
+    {
+        conn.update_lease(1)?;
+        conn.in_flight.begin();
+        let token = conn.pending.lock().unwrap().insert(tag.id);
+
+        let id = tag.id;
+        let spawned = {
+            let conn = conn.clone();
+            thread::Builder::new()
+                .name("mux-server-dispatch".to_string())
+                .spawn(move || {
+                    let frame = f();
+                    // the response of a discarded request is dropped
+                    if conn.pending.lock().unwrap().remove(tag.id, token) {
+                        let _ = send(&conn.writer, tag, frame);
+                    }
+                    let _ = conn.update_lease(-1);
+                    conn.in_flight.end();
+                })
+        };
+
+        if let Err(e) = spawned {
+            // the request never started, undo its accounting
+            conn.pending.lock().unwrap().remove(id, token);
+            conn.in_flight.end();
+            let _ = conn.update_lease(-1);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+impl InFlight {
+    fn begin(&self) {
+        *self.count.lock().unwrap() += 1;
+    }
+
+    fn end(&self) {
+        let mut count = self.count.lock().unwrap();
+        *count -= 1;
+        if *count == 0 {
+            self.idle.notify_all();
+        }
+    }
+
+    // block until every request in progress is answered
+    fn wait(&self) {
+        let mut count = self.count.lock().unwrap();
+        while *count > 0 {
+            count = self.idle.wait(count).unwrap();
+        }
+    }
+}
+
+impl Pending {
+    // track a request of tag `id`, returning its token
+    fn insert(&mut self, id: u32) -> u64 {
+        let token = self.next_token;
+        self.next_token += 1;
+        self.tokens.insert(id, token);
+        token
+    }
+
+    // stop tracking the request `token`. Returns whether it was still in
+    // progress, i.e. neither discarded nor superseded by a reuse of its tag.
+    fn remove(&mut self, id: u32, token: u64) -> bool {
+        if self.tokens.get(&id) == Some(&token) {
+            self.tokens.remove(&id);
+            true
+        } else {
+            false
+        }
+    }
+
+    // discard the request in progress of tag `id`. Returns whether there
+    // was one.
+    fn discard(&mut self, id: u32) -> bool {
+        self.tokens.remove(&id).is_some()
+    }
+}
+
+impl Conn {
+    // account for `delta` more requests in progress, granting a new lease
+    // if the policy decides on one that differs from the last
+    fn update_lease(&self, delta: isize) -> Result<()> {
+        let leases = match self.leases {
+            Some(ref leases) => leases,
+            None => return Ok(()),
+        };
+
+        // the lock is held while sending so leases are granted in order
+        let mut leases = leases.lock().unwrap();
+        match leases.update(delta) {
+            Some(lease) => send(&self.writer, Tag::new(true, 0), MessageFrame::Tlease(lease)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Leases {
+    fn new(policy: Arc<dyn LeasePolicy>) -> Leases {
+        Leases { policy, outstanding: 0, granted: None }
+    }
+
+    // account for `delta` more requests in progress, returning the lease to
+    // grant if the policy decides on one that differs from the last
+    fn update(&mut self, delta: isize) -> Option<Tlease> {
+        self.outstanding = (self.outstanding as isize + delta) as usize;
+        match self.policy.lease(self.outstanding) {
+            Some(lease) if self.granted != Some(lease) => {
+                self.granted = Some(lease);
+                Some(lease)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[inline]
+fn rerr(msg: String) -> MessageFrame {
+    MessageFrame::Rerr(Rerr { msg })
+}
+
+// whether the caller stopped waiting for the response to `tdispatch`
+#[inline]
+fn is_expired(tdispatch: &Tdispatch) -> bool {
+    tdispatch.deadline().is_some_and(|deadline| deadline.is_expired())
+}
+
+// the response to a dispatch received past its deadline
+fn deadline_exceeded() -> MessageFrame {
+    MessageFrame::Rdispatch(Rdispatch {
+        contexts: Vec::new(),
+        msg: Rmsg::Nack("Deadline exceeded".to_string()),
+    })
+}
+
+fn send(writer: &SharedWriter, tag: Tag, frame: MessageFrame) -> Result<()> {
+    // encode up front so the frame is written with a single call
+    let msg = Message { tag, frame };
+    let mut buf = Vec::with_capacity(8 + codec::size::frame_size(&msg.frame));
+    codec::write_message(&mut buf, &msg)?;
+
+    let mut writer = writer.lock().unwrap();
+    writer.write_all(&buf)?;
+    writer.flush()?;
+    Ok(())
+}
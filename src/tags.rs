@@ -0,0 +1,144 @@
+//! Allocation of the tag ids of outstanding requests.
+
+use std::collections::HashSet;
+use std::error;
+use std::fmt;
+
+use super::*;
+
+/// Errors of `Tag` construction and allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagError {
+    /// The id doesn't fit the 23 bits of a tag.
+    OutOfRange(u32),
+    /// All ids are held by outstanding requests.
+    Exhausted,
+    /// The id isn't outstanding, for example a duplicate response.
+    NotOutstanding(u32),
+}
+
+impl fmt::Display for TagError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TagError::OutOfRange(id) => write!(f, "Tag id {} exceeds {}", id, MAX_TAG),
+            TagError::Exhausted => write!(f, "No tag ids available"),
+            TagError::NotOutstanding(id) => write!(f, "Tag id {} is not outstanding", id),
+        }
+    }
+}
+
+impl error::Error for TagError {}
+
+/// Allocator of the tag ids of outstanding requests
+///
+/// Ids are handed out from `1..=MAX_TAG`, 0 being reserved for messages
+/// that don't expect a response such as `Tlease`. Released ids are reused
+/// before fresh ones are handed out.
+///
+/// ```rust
+/// use mux::{Tag, TagAllocator, TagError};
+///
+/// let mut tags = TagAllocator::new();
+/// let id = tags.allocate().unwrap();
+/// assert_eq!(id, 1);
+///
+/// // a fragment not marked as the end keeps the id outstanding
+/// assert_eq!(tags.complete(&Tag::new(false, id)), Ok(false));
+/// assert_eq!(tags.complete(&Tag::new(true, id)), Ok(true));
+/// assert_eq!(tags.release(id), Err(TagError::NotOutstanding(id)));
+/// ```
+#[derive(Debug)]
+pub struct TagAllocator {
+    // the next never allocated id
+    next: u32,
+    max_id: u32,
+    released: Vec<u32>,
+    outstanding: HashSet<u32>,
+}
+
+impl Default for TagAllocator {
+    fn default() -> TagAllocator {
+        TagAllocator::with_max_id(MAX_TAG)
+    }
+}
+
+impl TagAllocator {
+    /// Construct a new `TagAllocator` handing out ids up to `MAX_TAG`.
+    pub fn new() -> TagAllocator {
+        TagAllocator::default()
+    }
+
+    /// Construct a new `TagAllocator` handing out ids up to `max_id`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_id` is 0 or exceeds `MAX_TAG`.
+    pub fn with_max_id(max_id: u32) -> TagAllocator {
+        assert!(max_id > 0 && max_id <= MAX_TAG);
+        TagAllocator {
+            next: 1,
+            max_id,
+            released: Vec::new(),
+            outstanding: HashSet::new(),
+        }
+    }
+
+    /// Allocate an id, failing with `TagError::Exhausted` if every id is
+    /// outstanding.
+    pub fn allocate(&mut self) -> Result<u32, TagError> {
+        let id = match self.released.pop() {
+            Some(id) => id,
+            None if self.next <= self.max_id => {
+                self.next += 1;
+                self.next - 1
+            }
+            None => return Err(TagError::Exhausted),
+        };
+
+        self.outstanding.insert(id);
+        Ok(id)
+    }
+
+    /// Release an outstanding id for reuse.
+    pub fn release(&mut self, id: u32) -> Result<(), TagError> {
+        if self.outstanding.remove(&id) {
+            self.released.push(id);
+            Ok(())
+        } else {
+            Err(TagError::NotOutstanding(id))
+        }
+    }
+
+    /// Account for a response with `tag`
+    ///
+    /// The id is released if the `tag` ends its stream, returning whether it
+    /// was. A response to an id that isn't outstanding is an error.
+    pub fn complete(&mut self, tag: &Tag) -> Result<bool, TagError> {
+        if !tag.end {
+            if self.outstanding.contains(&tag.id) {
+                Ok(false)
+            } else {
+                Err(TagError::NotOutstanding(tag.id))
+            }
+        } else {
+            self.release(tag.id).map(|_| true)
+        }
+    }
+
+    /// Whether `id` is allocated and not yet released.
+    #[inline]
+    pub fn is_outstanding(&self, id: u32) -> bool {
+        self.outstanding.contains(&id)
+    }
+
+    /// Number of outstanding ids.
+    #[inline]
+    pub fn outstanding(&self) -> usize {
+        self.outstanding.len()
+    }
+
+    /// Release every outstanding id.
+    pub fn clear(&mut self) {
+        self.released.extend(self.outstanding.drain());
+    }
+}
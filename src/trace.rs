@@ -0,0 +1,186 @@
+//! Zipkin compatible tracing of dispatches.
+//!
+//! The span of a dispatch is identified by the `TraceId` it carries in its
+//! `contexts::TraceId` context. Sessions given a `Tracer` propagate the
+//! trace: clients issue each dispatch in a child span of the one it
+//! carries, or in a new trace, and servers extract the span of the requests
+//! they receive, starting a new trace for requests without one. The
+//! `Tracer` is handed the annotations of the spans, such as when requests
+//! are sent and received, to export them to a collector.
+//!
+//! ```rust
+//! use std::sync::Arc;
+//! use mux::trace::{Annotation, BufferingTracer, TraceId, Tracer};
+//!
+//! let tracer = Arc::new(BufferingTracer::new());
+//! let trace_id = TraceId::root().child();
+//! tracer.record_now(trace_id, Annotation::ClientSend);
+//!
+//! let records = tracer.take();
+//! assert_eq!(records[0].trace_id, trace_id);
+//! assert_eq!(records[0].annotation, Annotation::ClientSend);
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use super::*;
+use super::contexts;
+
+pub use super::contexts::TraceId;
+
+/// An event in the life of a span.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Annotation {
+    /// The client sent the request.
+    ClientSend,
+    /// The client received the response.
+    ClientRecv,
+    /// The client failed to receive a response, with the reason.
+    ClientRecvError(String),
+    /// The server received the request.
+    ServerRecv,
+    /// The server sent the response.
+    ServerSend,
+    /// Name of the request: the destination of the `Tdispatch`.
+    Rpc(String),
+    /// A message of the application.
+    Message(String),
+}
+
+/// An `Annotation` of a span and when it happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// The span annotated.
+    pub trace_id: TraceId,
+    /// When the annotated event happened.
+    pub timestamp: SystemTime,
+    /// The event.
+    pub annotation: Annotation,
+}
+
+/// Receiver of the annotations of the spans of a session
+///
+/// Spans that aren't sampled, their `sampled` flag being `Some(false)`, are
+/// not recorded.
+pub trait Tracer: Send + Sync {
+    /// Record an annotation.
+    fn record(&self, record: Record);
+
+    /// Record `annotation` of the span of `trace_id` as happening now.
+    fn record_now(&self, trace_id: TraceId, annotation: Annotation) {
+        self.record(Record { trace_id, timestamp: SystemTime::now(), annotation });
+    }
+}
+
+impl<T: Tracer + ?Sized> Tracer for Arc<T> {
+    #[inline]
+    fn record(&self, record: Record) {
+        (**self).record(record)
+    }
+}
+
+/// A `Tracer` collecting the records in memory until they're taken.
+#[derive(Debug, Default)]
+pub struct BufferingTracer {
+    records: Mutex<Vec<Record>>,
+}
+
+impl BufferingTracer {
+    /// Construct a new, empty `BufferingTracer`.
+    pub fn new() -> BufferingTracer {
+        BufferingTracer::default()
+    }
+
+    /// Take the records collected so far, in the order they were recorded.
+    pub fn take(&self) -> Vec<Record> {
+        mem::take(&mut *self.records.lock().unwrap())
+    }
+}
+
+impl Tracer for BufferingTracer {
+    fn record(&self, record: Record) {
+        self.records.lock().unwrap().push(record);
+    }
+}
+
+impl TraceId {
+    /// The root span of a new trace, leaving the sampling decision to the
+    /// receiver.
+    pub fn root() -> TraceId {
+        let id = next_id();
+        TraceId {
+            span_id: id,
+            parent_id: id,
+            trace_id: id,
+            trace_id_high: None,
+            sampled: None,
+            debug: false,
+        }
+    }
+
+    /// A new span of the trace, child of this one.
+    pub fn child(&self) -> TraceId {
+        TraceId {
+            span_id: next_id(),
+            parent_id: self.span_id,
+            ..*self
+        }
+    }
+}
+
+// a traced dispatch and the tracer of its annotations
+pub(crate) struct Span {
+    tracer: Arc<dyn Tracer>,
+    trace_id: TraceId,
+}
+
+impl Span {
+    // start the client span of `tdispatch`, a child of the span it carries
+    pub(crate) fn client(tracer: &Arc<dyn Tracer>, tdispatch: &mut Tdispatch) -> Span {
+        let trace_id = tdispatch.trace_id().map_or_else(TraceId::root, |parent| parent.child());
+        contexts::set(&mut tdispatch.contexts, &trace_id);
+
+        let span = Span { tracer: tracer.clone(), trace_id };
+        span.record(Annotation::Rpc(tdispatch.dest.clone()));
+        span.record(Annotation::ClientSend);
+        span
+    }
+
+    // start the server span of `tdispatch`, the span it carries
+    pub(crate) fn server(tracer: &Arc<dyn Tracer>, tdispatch: &mut Tdispatch) -> Span {
+        let trace_id = tdispatch.trace_id().unwrap_or_else(|| {
+            let root = TraceId::root();
+            contexts::set(&mut tdispatch.contexts, &root);
+            root
+        });
+
+        let span = Span { tracer: tracer.clone(), trace_id };
+        span.record(Annotation::Rpc(tdispatch.dest.clone()));
+        span.record(Annotation::ServerRecv);
+        span
+    }
+
+    pub(crate) fn record(&self, annotation: Annotation) {
+        if self.trace_id.sampled != Some(false) {
+            self.tracer.record_now(self.trace_id, annotation);
+        }
+    }
+}
+
+// a random non zero id
+fn next_id() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    loop {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        match hasher.finish() {
+            0 => continue,
+            id => return id,
+        }
+    }
+}
@@ -22,6 +22,10 @@ pub const TPING: i8 = 65;
 pub const RPING: i8 = -65;
 
 pub const TDISCARDED: i8 = 66;
+pub const RDISCARDED: i8 = -66;
+/// Legacy `Tdiscarded` type id still emitted by older Finagle peers.
+pub const BAD_TDISCARDED: i8 = -62;
+
 pub const TLEASE: i8 = 67;
 
 pub const RERR: i8 = -128;
@@ -0,0 +1,427 @@
+#![cfg(feature = "tokio")]
+
+extern crate mux;
+
+use futures::{SinkExt, StreamExt};
+use mux::*;
+use mux::client::{AsyncClient, Error};
+use mux::codec::MuxCodec;
+use mux::contexts::{self, Deadline};
+use mux::init::{InitOptions, Negotiator};
+use mux::lease::LoadLeasePolicy;
+use mux::server::AsyncServer;
+use mux::trace::{Annotation, BufferingTracer};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio_util::codec::Framed;
+
+const LEASE: Duration = Duration::from_secs(30);
+
+// echoes bodies back, sleeping on the "/slow" and "/slower" destinations
+async fn echo(tdispatch: Tdispatch) -> Rdispatch {
+    if tdispatch.dest == "/slow" {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    } else if tdispatch.dest == "/slower" {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    Rdispatch {
+        contexts: tdispatch.contexts,
+        msg: Rmsg::Ok(tdispatch.body),
+    }
+}
+
+async fn client() -> AsyncClient {
+    let (a, b) = tokio::io::duplex(1024);
+    tokio::spawn(async move { AsyncServer::new(echo).serve(b).await });
+    AsyncClient::new(a).await.unwrap()
+}
+
+#[tokio::test]
+async fn dispatch_and_ping() {
+    let client = client().await;
+    assert_eq!(client.version(), VERSION);
+    client.ping().await.unwrap();
+
+    let mut tdispatch = Tdispatch::new("/foo".to_string(), b"hello".to_vec());
+    tdispatch.contexts.push((b"key".to_vec(), b"value".to_vec()));
+    let rdispatch = client.dispatch(tdispatch).await.unwrap();
+    assert_eq!(rdispatch.contexts, vec![(b"key".to_vec(), b"value".to_vec())]);
+    assert_eq!(rdispatch.msg, Rmsg::Ok(b"hello".to_vec()));
+
+    let rmsg = client.request(Treq { headers: Vec::new(), body: b"treq".to_vec() }).await.unwrap();
+    assert_eq!(rmsg, Rmsg::Error("Treq not supported".to_string()));
+}
+
+#[tokio::test]
+async fn concurrent_dispatches() {
+    let client = client().await;
+
+    let slow = {
+        let client = client.clone();
+        tokio::spawn(async move {
+            client.dispatch(Tdispatch::new("/slow".to_string(), b"slow".to_vec())).await.unwrap()
+        })
+    };
+
+    let requests = (0..20u8).map(|i| {
+        let client = client.clone();
+        async move {
+            let rdispatch = client.dispatch(Tdispatch::new("/foo".to_string(), vec![i])).await;
+            assert_eq!(rdispatch.unwrap().msg, Rmsg::Ok(vec![i]));
+        }
+    });
+    futures::future::join_all(requests).await;
+    assert!(!slow.is_finished());
+
+    assert_eq!(slow.await.unwrap().msg, Rmsg::Ok(b"slow".to_vec()));
+}
+
+#[tokio::test]
+async fn server_finishes_in_flight_dispatches() {
+    let (a, b) = tokio::io::duplex(1024);
+    let server = tokio::spawn(async move { AsyncServer::new(echo).serve(b).await });
+
+    let mut framed = Framed::new(a, MuxCodec::new());
+    let tdispatch = Tdispatch::new("/slow".to_string(), b"slow".to_vec());
+    framed.send(Message { tag: Tag::new(true, 7), frame: MessageFrame::Tdispatch(tdispatch) })
+        .await
+        .unwrap();
+
+    // closing our writing half ends the session once the dispatch is answered
+    let (mut sink, mut stream) = framed.split();
+    sink.close().await.unwrap();
+
+    let msg = stream.next().await.unwrap().unwrap();
+    assert_eq!(msg.tag, Tag::new(true, 7));
+    assert_eq!(msg.frame.frame_id(), types::RDISPATCH);
+    server.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn negotiates_init_options() {
+    let mut server_options = InitOptions::new();
+    server_options.max_frame_size = Some(4096);
+    let (a, b) = tokio::io::duplex(1024);
+    let server = AsyncServer::new(echo).with_init_options(server_options);
+    tokio::spawn(async move { server.serve(b).await });
+
+    let mut client_options = InitOptions::new();
+    client_options.max_frame_size = Some(1024);
+    let client = AsyncClient::with_init_options(a, &client_options).await.unwrap();
+
+    let params = client.negotiate(&Negotiator::new()).unwrap();
+    assert_eq!(params.version, VERSION);
+    assert_eq!(params.max_frame_size, Some(1024));
+}
+
+#[tokio::test]
+async fn client_acknowledges_drain() {
+    let (a, b) = tokio::io::duplex(1024);
+    let peer = tokio::spawn(async move {
+        let mut framed = Framed::new(b, MuxCodec::new());
+        let tinit = framed.next().await.unwrap().unwrap();
+        let rinit = MessageFrame::Rinit(Init { version: VERSION, headers: Vec::new() });
+        framed.send(Message { tag: tinit.tag, frame: rinit }).await.unwrap();
+
+        framed.send(Message { tag: Tag::new(true, 0), frame: MessageFrame::Tdrain }).await.unwrap();
+        // hand back the connection to keep it open
+        (framed.next().await.unwrap().unwrap(), framed)
+    });
+
+    let client = AsyncClient::new(a).await.unwrap();
+    let (rdrain, _framed) = peer.await.unwrap();
+    assert_eq!(rdrain, Message { tag: Tag::new(true, 0), frame: MessageFrame::Rdrain });
+    assert!(client.is_draining());
+
+    match client.ping().await {
+        Err(Error::Draining) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn reassembles_fragmented_responses() {
+    let (a, b) = tokio::io::duplex(1024);
+    tokio::spawn(async move {
+        let mut framed = Framed::new(b, MuxCodec::new());
+        let tinit = framed.next().await.unwrap().unwrap();
+        let rinit = MessageFrame::Rinit(Init { version: VERSION, headers: Vec::new() });
+        framed.send(Message { tag: tinit.tag, frame: rinit }).await.unwrap();
+
+        let tdispatch = framed.next().await.unwrap().unwrap();
+        let rdispatch = Rdispatch { contexts: Vec::new(), msg: Rmsg::Ok(vec![7; 100]) };
+        let msg = Message { tag: tdispatch.tag, frame: MessageFrame::Rdispatch(rdispatch) };
+        let mut buf = Vec::new();
+        for fragment in codec::Fragmenter::new(16).fragment(&msg).unwrap() {
+            codec::write_fragment(&mut buf, &fragment).unwrap();
+        }
+        framed.get_mut().write_all(&buf).await.unwrap();
+        // keep the connection open until the client is done
+        framed.next().await
+    });
+
+    let client = AsyncClient::new(a).await.unwrap();
+    let rdispatch = client.dispatch(Tdispatch::new("/foo".to_string(), b"hello".to_vec())).await.unwrap();
+    assert_eq!(rdispatch.msg, Rmsg::Ok(vec![7; 100]));
+}
+
+#[tokio::test]
+async fn closed_with_the_connection() {
+    let (a, b) = tokio::io::duplex(1024);
+    let peer = tokio::spawn(async move {
+        let mut framed = Framed::new(b, MuxCodec::new());
+        let tinit = framed.next().await.unwrap().unwrap();
+        framed.send(Message { tag: tinit.tag, frame: MessageFrame::Rerr(Rerr { msg: "no".to_string() }) })
+            .await
+            .unwrap();
+        // wait for the ping and hang up without answering
+        framed.next().await.unwrap().unwrap()
+    });
+
+    let client = AsyncClient::new(a).await.unwrap();
+    assert_eq!(client.version(), 0);
+
+    match client.ping().await {
+        Err(Error::Closed) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    assert!(client.is_closed());
+    assert_eq!(peer.await.unwrap().frame, MessageFrame::Tping);
+}
+
+#[tokio::test]
+async fn server_drains_sessions() {
+    let server = AsyncServer::new(echo);
+    let (a, b) = tokio::io::duplex(1024);
+    let serving = {
+        let server = server.clone();
+        tokio::spawn(async move { server.serve(b).await })
+    };
+
+    let client = AsyncClient::new(a).await.unwrap();
+    assert_eq!(server.connections(), 1);
+    let slow = {
+        let client = client.clone();
+        tokio::spawn(async move {
+            client.dispatch(Tdispatch::new("/slow".to_string(), b"slow".to_vec())).await
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    // the dispatch in progress is answered before the connection closes
+    server.drain().await;
+    assert!(server.is_draining());
+    assert_eq!(server.connections(), 0);
+    assert_eq!(slow.await.unwrap().unwrap().msg, Rmsg::Ok(b"slow".to_vec()));
+    serving.await.unwrap().unwrap();
+
+    client.drained().await;
+    assert!(client.is_draining());
+    assert!(client.ping().await.is_err());
+}
+
+#[tokio::test]
+async fn server_drain_waits_for_responses() {
+    let answered = Arc::new(AtomicBool::new(false));
+    let server = {
+        let answered = answered.clone();
+        AsyncServer::new(move |tdispatch: Tdispatch| {
+            let answered = answered.clone();
+            async move {
+                let rdispatch = echo(tdispatch).await;
+                answered.store(true, Ordering::SeqCst);
+                rdispatch
+            }
+        })
+    };
+    let (a, b) = tokio::io::duplex(1024);
+    let serving = {
+        let server = server.clone();
+        tokio::spawn(async move { server.serve(b).await })
+    };
+
+    let client = AsyncClient::new(a).await.unwrap();
+    let slow = {
+        let client = client.clone();
+        tokio::spawn(async move {
+            client.dispatch(Tdispatch::new("/slow".to_string(), b"slow".to_vec())).await
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    // the connection only closes once the dispatch in progress is answered
+    server.drain().await;
+    assert!(answered.load(Ordering::SeqCst));
+    assert_eq!(slow.await.unwrap().unwrap().msg, Rmsg::Ok(b"slow".to_vec()));
+    serving.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn client_drain_closes_session() {
+    let client = client().await;
+    client.ping().await.unwrap();
+    client.drain().await;
+    assert!(client.is_closed());
+
+    match client.ping().await {
+        Err(Error::Closed) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn deadlines() {
+    let client = client().await;
+
+    let rdispatch = client.dispatch_timeout(Tdispatch::new("/foo".to_string(), b"a".to_vec()), Duration::from_secs(60))
+        .await
+        .unwrap();
+    let deadline = contexts::get::<Deadline, _>(&rdispatch.contexts).unwrap().unwrap();
+    assert!(deadline.remaining() > Duration::from_secs(59));
+
+    match client.dispatch_timeout(Tdispatch::new("/slow".to_string(), b"b".to_vec()), Duration::from_millis(10)).await {
+        Err(Error::Timeout) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    let mut tdispatch = Tdispatch::new("/foo".to_string(), b"c".to_vec());
+    contexts::set(&mut tdispatch.contexts, &Deadline { timestamp: UNIX_EPOCH, deadline: SystemTime::now() });
+    let rdispatch = client.dispatch(tdispatch).await.unwrap();
+    assert_eq!(rdispatch.msg, Rmsg::Nack("Deadline exceeded".to_string()));
+
+    // the session outlives the timed out dispatch
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    client.ping().await.unwrap();
+}
+
+#[tokio::test]
+async fn discards_release_tags() {
+    let client = client().await;
+
+    match client.dispatch_timeout(Tdispatch::new("/slow".to_string(), b"a".to_vec()), Duration::from_millis(10)).await {
+        Err(Error::Timeout) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    // the server acknowledges the discard well before the dispatch completes
+    tokio::time::timeout(Duration::from_millis(50), client.drain()).await.unwrap();
+}
+
+#[tokio::test]
+async fn reused_discarded_tags() {
+    let (a, b) = tokio::io::duplex(1024);
+    tokio::spawn(async move { AsyncServer::new(echo).serve(b).await });
+    let mut framed = Framed::new(a, MuxCodec::new());
+
+    let tdispatch = Tdispatch::new("/slow".to_string(), b"a".to_vec());
+    framed.send(Message { tag: Tag::new(true, 2), frame: MessageFrame::Tdispatch(tdispatch) }).await.unwrap();
+    let tdiscarded = Tdiscarded { id: 2, msg: "timed out".to_string() };
+    framed.send(Message { tag: Tag::new(true, 0), frame: MessageFrame::Tdiscarded(tdiscarded) }).await.unwrap();
+    let msg = framed.next().await.unwrap().unwrap();
+    assert_eq!(msg, Message { tag: Tag::new(true, 2), frame: MessageFrame::Rdiscarded });
+
+    // the discarded dispatch completes first, its response is still dropped
+    let tdispatch = Tdispatch::new("/slower".to_string(), b"b".to_vec());
+    framed.send(Message { tag: Tag::new(true, 2), frame: MessageFrame::Tdispatch(tdispatch) }).await.unwrap();
+    let msg = framed.next().await.unwrap().unwrap();
+    let rdispatch = Rdispatch { contexts: Vec::new(), msg: Rmsg::Ok(b"b".to_vec()) };
+    assert_eq!(msg, Message { tag: Tag::new(true, 2), frame: MessageFrame::Rdispatch(rdispatch) });
+}
+
+#[tokio::test]
+async fn rerr_for_malformed_frames() {
+    let (a, b) = tokio::io::duplex(1024);
+    tokio::spawn(async move { AsyncServer::new(echo).serve(b).await });
+    let mut framed = Framed::new(a, MuxCodec::new());
+
+    // a Tdispatch with a truncated destination, then an unknown frame type
+    framed.get_mut().write_all(&[0, 0, 0, 7, 2, 0, 0, 4, 0, 0, 9]).await.unwrap();
+    framed.get_mut().write_all(&[0, 0, 0, 4, 80, 0, 0, 5]).await.unwrap();
+
+    for id in &[4, 5] {
+        let msg = framed.next().await.unwrap().unwrap();
+        assert_eq!(msg.tag, Tag::new(true, *id));
+        match msg.frame {
+            MessageFrame::Rerr(_) => (),
+            other => panic!("Unexpected frame: {:?}", other),
+        }
+    }
+
+    // the session survives
+    framed.send(Message { tag: Tag::new(true, 6), frame: MessageFrame::Tping }).await.unwrap();
+    assert_eq!(framed.next().await.unwrap().unwrap().frame, MessageFrame::Rping);
+}
+
+#[tokio::test]
+async fn rerr_for_unreadable_frames() {
+    let (a, b) = tokio::io::duplex(1024);
+    let server = tokio::spawn(async move { AsyncServer::new(echo).serve(b).await });
+    let mut framed = Framed::new(a, MuxCodec::new());
+
+    // a frame size too small for the type and tag
+    framed.get_mut().write_all(&[0, 0, 0, 2, 65, 0, 0, 1]).await.unwrap();
+
+    let msg = framed.next().await.unwrap().unwrap();
+    assert_eq!(msg.tag, Tag::new(true, 0));
+    match msg.frame {
+        MessageFrame::Rerr(_) => (),
+        other => panic!("Unexpected frame: {:?}", other),
+    }
+
+    match server.await.unwrap() {
+        Err(codec::Error::FrameTooSmall(2)) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn grants_leases_by_load() {
+    let (a, b) = tokio::io::duplex(1024);
+    let server = AsyncServer::new(echo).with_lease_policy(LoadLeasePolicy::new(1, LEASE));
+    tokio::spawn(async move { server.serve(b).await });
+    let mut framed = Framed::new(a, MuxCodec::new());
+
+    let tinit = Init { version: VERSION, headers: Vec::new() };
+    framed.send(Message { tag: Tag::new(true, 1), frame: MessageFrame::Tinit(tinit) }).await.unwrap();
+    assert_eq!(framed.next().await.unwrap().unwrap().frame.frame_id(), types::RINIT);
+    let granted = Message { tag: Tag::new(true, 0), frame: MessageFrame::Tlease(Tlease { duration: LEASE }) };
+    assert_eq!(framed.next().await.unwrap().unwrap(), granted);
+
+    let tdispatch = Tdispatch::new("/slow".to_string(), b"slow".to_vec());
+    framed.send(Message { tag: Tag::new(true, 2), frame: MessageFrame::Tdispatch(tdispatch) }).await.unwrap();
+
+    let revoked = Message {
+        tag: Tag::new(true, 0),
+        frame: MessageFrame::Tlease(Tlease { duration: Duration::ZERO }),
+    };
+    assert_eq!(framed.next().await.unwrap().unwrap(), revoked);
+    assert_eq!(framed.next().await.unwrap().unwrap().tag, Tag::new(true, 2));
+    assert_eq!(framed.next().await.unwrap().unwrap(), granted);
+
+    // an unchanged lease isn't granted again
+    framed.send(Message { tag: Tag::new(true, 3), frame: MessageFrame::Tping }).await.unwrap();
+    assert_eq!(framed.next().await.unwrap().unwrap().frame, MessageFrame::Rping);
+}
+
+#[tokio::test]
+async fn traces_dispatches() {
+    let server_tracer = Arc::new(BufferingTracer::new());
+    let client_tracer = Arc::new(BufferingTracer::new());
+    let (a, b) = tokio::io::duplex(1024);
+    let server = AsyncServer::new(echo).with_tracer(server_tracer.clone());
+    tokio::spawn(async move { server.serve(b).await });
+    let client = AsyncClient::new(a).await.unwrap().with_tracer(client_tracer.clone());
+
+    let rdispatch = client.dispatch(Tdispatch::new("/foo".to_string(), b"a".to_vec())).await.unwrap();
+    let span = contexts::get::<contexts::TraceId, _>(&rdispatch.contexts).unwrap().unwrap();
+
+    let client_records = client_tracer.take();
+    assert!(client_records.iter().all(|record| record.trace_id == span));
+    assert_eq!(client_records.last().unwrap().annotation, Annotation::ClientRecv);
+
+    let server_records = server_tracer.take();
+    assert!(server_records.iter().all(|record| record.trace_id == span));
+    assert_eq!(server_records.last().unwrap().annotation, Annotation::ServerSend);
+}
@@ -1,7 +1,6 @@
 extern crate mux;
 
 use std::fmt::Debug;
-use std::io;
 use std::io::Cursor;
 use std::time::Duration;
 
@@ -9,7 +8,7 @@ use mux::*;
 use mux::codec::*;
 use mux::codec::size::*;
 
-const BUFFER_STR: &'static str = "hello world";
+const BUFFER_STR: &str = "hello world";
 
 fn writer() -> Cursor<Vec<u8>> {
     Cursor::new(Vec::new())
@@ -19,9 +18,9 @@ fn body() -> Vec<u8> {
     BUFFER_STR.to_owned().into_bytes()
 }
 
-fn check<T, F1, F2, F3>(buf: Vec<u8>, decode: F1, expected: T, encode: F2, size: F3) -> ()
-    where F1: Fn(Cursor<Vec<u8>>) -> io::Result<T>,
-          F2: Fn(&mut Cursor<Vec<u8>>, &T) -> io::Result<()>,
+fn check<T, F1, F2, F3>(buf: Vec<u8>, decode: F1, expected: T, encode: F2, size: F3)
+    where F1: Fn(Cursor<Vec<u8>>) -> codec::Result<T>,
+          F2: Fn(&mut Cursor<Vec<u8>>, &T) -> codec::Result<()>,
           F3: FnOnce(T) -> MessageFrame,
           T: PartialEq + Debug
 {
@@ -42,7 +41,7 @@ fn test_treq() {
                0x6f, 0x72, 0x6c, 0x64, ];
 
     let expected = Treq { headers: Vec::new(), body: body() };
-    check(buf, &decode_treq, expected, &encode_treq, |t| MessageFrame::Treq(t));
+    check(buf, decode_treq, expected, encode_treq, MessageFrame::Treq);
 }
 
 #[test]
@@ -52,7 +51,7 @@ fn test_rreqok() {
                0x6f, 0x72, 0x6c, 0x64, ];
 
     let expected = Rmsg::Ok(body());
-    check(buf, &decode_rreq, expected, &encode_rreq, |t| MessageFrame::Rreq(t));
+    check(buf, decode_rreq, expected, encode_rreq, MessageFrame::Rreq);
 }
 
 #[test]
@@ -62,7 +61,7 @@ fn test_rreqerror() {
                0x6f, 0x72, 0x6c, 0x64, ];
 
     let expected = Rmsg::Error(BUFFER_STR.to_owned());
-    check(buf, &decode_rreq, expected, &encode_rreq, |t| MessageFrame::Rreq(t));
+    check(buf, decode_rreq, expected, encode_rreq, MessageFrame::Rreq);
 }
 
 #[test]
@@ -71,7 +70,7 @@ fn test_rreqnack() {
     let buf = vec![0x02, ];
 
     let expected = Rmsg::Nack("".to_owned());
-    check(buf, &decode_rreq, expected, &encode_rreq, |t| MessageFrame::Rreq(t));
+    check(buf, decode_rreq, expected, encode_rreq, MessageFrame::Rreq);
 }
 
 #[test]
@@ -88,7 +87,7 @@ fn test_tdispatch_1() {
         body: body(),
     };
 
-    check(buf, &decode_tdispatch, expected, &encode_tdispatch, |t| MessageFrame::Tdispatch(t));
+    check(buf, decode_tdispatch, expected, encode_tdispatch, MessageFrame::Tdispatch);
 }
 
 #[test]
@@ -116,7 +115,7 @@ fn test_tdispatch_2() {
     encode_tdispatch(&mut w, &expected).unwrap();
 
     assert_eq!(w.into_inner(), buf);
-    check(buf, &decode_tdispatch, expected, &encode_tdispatch, |t| MessageFrame::Tdispatch(t));
+    check(buf, decode_tdispatch, expected, encode_tdispatch, MessageFrame::Tdispatch);
 }
 
 #[test]
@@ -129,17 +128,17 @@ fn test_tdispatch_3() {
                0x72, 0x6c, 0x64, ];
 
     let dtab = Dtab::from_entries(
-        vec![Dentry::new("/f/foo".to_owned(), "/go".to_owned())]
+        vec![Dentry::read("/f/foo=>/go").unwrap()]
     );
 
     let expected = Tdispatch {
         contexts: vec![],
         dest: "/path".to_owned(),
-        dtab: dtab,
+        dtab,
         body: body(),
     };
 
-    check(buf, &decode_tdispatch, expected, &encode_tdispatch, |t| MessageFrame::Tdispatch(t));
+    check(buf, decode_tdispatch, expected, encode_tdispatch, MessageFrame::Tdispatch);
 }
 
 #[test]
@@ -153,7 +152,7 @@ fn test_rdispatchok_1() {
         msg: Rmsg::Ok(body()),
     };
 
-    check(buf, &decode_rdispatch, expected, &encode_rdispatch, |t| MessageFrame::Rdispatch(t));
+    check(buf, decode_rdispatch, expected, encode_rdispatch, MessageFrame::Rdispatch);
 }
 
 #[test]
@@ -171,7 +170,7 @@ fn test_rdispatchok_2() {
         msg: Rmsg::Ok(body()),
     };
 
-    check(buf, &decode_rdispatch, expected, &encode_rdispatch, |t| MessageFrame::Rdispatch(t));
+    check(buf, decode_rdispatch, expected, encode_rdispatch, MessageFrame::Rdispatch);
 }
 
 #[test]
@@ -185,7 +184,7 @@ fn test_rdispatcherror() {
         msg: Rmsg::Error(BUFFER_STR.to_owned()),
     };
 
-    check(buf, &decode_rdispatch, expected, &encode_rdispatch, |t| MessageFrame::Rdispatch(t));
+    check(buf, decode_rdispatch, expected, encode_rdispatch, MessageFrame::Rdispatch);
 }
 
 #[test]
@@ -198,7 +197,7 @@ fn test_rdispatchnack() {
         msg: Rmsg::Nack("".to_owned()),
     };
 
-    check(buf, &decode_rdispatch, expected, &encode_rdispatch, |t| MessageFrame::Rdispatch(t));
+    check(buf, decode_rdispatch, expected, encode_rdispatch, MessageFrame::Rdispatch);
 }
 
 #[test]
@@ -211,7 +210,7 @@ fn test_rerr() {
         msg: BUFFER_STR.to_owned(),
     };
 
-    check(buf, &decode_rerr, expected, &encode_rerr, |t| MessageFrame::Rerr(t));
+    check(buf, decode_rerr, expected, encode_rerr, MessageFrame::Rerr);
 }
 
 #[test]
@@ -225,7 +224,67 @@ fn test_tdiscarded() {
         msg: BUFFER_STR.to_owned(),
     };
 
-    check(buf, &decode_tdiscarded, expected, &encode_tdiscarded, |t| MessageFrame::Tdiscarded(t));
+    check(buf, decode_tdiscarded, expected, encode_tdiscarded, MessageFrame::Tdiscarded);
+}
+
+#[test]
+fn test_tdiscarded_message() {
+    // Message type: Tdiscarded(tag 0, discarding 1, hello world)
+    let buf = vec![0x42, 0x80, 0x00, 0x00, 0x00, 0x00, 0x01, 0x68,
+               0x65, 0x6c, 0x6c, 0x6f, 0x20, 0x77, 0x6f, 0x72,
+               0x6c, 0x64, ];
+
+    let expected = Message {
+        tag: Tag::new(false, 0),
+        frame: MessageFrame::Tdiscarded(Tdiscarded {
+            id: 1,
+            msg: BUFFER_STR.to_owned(),
+        }),
+    };
+
+    assert_eq!(decode_message(Cursor::new(buf.clone())).unwrap(), expected);
+
+    let mut w = writer();
+    encode_message(&mut w, &expected).unwrap();
+    assert_eq!(w.into_inner(), buf);
+}
+
+#[test]
+fn test_legacy_tdiscarded_message() {
+    // Message type: Tdiscarded(tag 0, discarding 1, hello world) with the legacy -62 type
+    let buf = vec![0x00, 0x00, 0x00, 0x12, 0xc2, 0x80, 0x00, 0x00,
+               0x00, 0x00, 0x01, 0x68, 0x65, 0x6c, 0x6c, 0x6f,
+               0x20, 0x77, 0x6f, 0x72, 0x6c, 0x64, ];
+
+    let expected = Message {
+        tag: Tag::new(false, 0),
+        frame: MessageFrame::Tdiscarded(Tdiscarded {
+            id: 1,
+            msg: BUFFER_STR.to_owned(),
+        }),
+    };
+
+    let msg = read_message(&mut Cursor::new(buf)).unwrap();
+    assert_eq!(msg, expected);
+    assert_eq!(msg.frame.frame_id(), types::TDISCARDED);
+}
+
+#[test]
+fn test_rdiscarded_message() {
+    // Message type: Rdiscarded(1)
+    let buf = vec![0x00, 0x00, 0x00, 0x04, 0xbe, 0x00, 0x00, 0x01, ];
+
+    let expected = Message {
+        tag: Tag::new(true, 1),
+        frame: MessageFrame::Rdiscarded,
+    };
+
+    assert_eq!(read_message(&mut Cursor::new(buf.clone())).unwrap(), expected);
+    assert_eq!(frame_size(&expected.frame), 0);
+
+    let mut w = writer();
+    write_message(&mut w, &expected).unwrap();
+    assert_eq!(w.into_inner(), buf);
 }
 
 #[test]
@@ -233,9 +292,28 @@ fn test_tlease() {
     // Request type: Tlease(0,1000)
     let buf = vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xe8, ];
 
-    let expected = Tlease {
-        duration: Duration::from_millis(1000),
-    };
+    let expected = Tlease { duration: Duration::from_millis(1000) };
+
+    check(buf, decode_tlease, expected, encode_tlease, MessageFrame::Tlease);
+}
 
-    check(buf, &decode_tlease, expected, &encode_tlease, |t| MessageFrame::Tlease(t));
+#[test]
+fn test_tlease_infinite() {
+    // Request type: Tlease(0,i64::MAX)
+    let buf = vec![0x00, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, ];
+
+    check(buf, decode_tlease, Tlease::infinite(), encode_tlease, MessageFrame::Tlease);
+}
+
+#[test]
+fn test_tlease_unknown_unit() {
+    // Request type: Tlease(1,1)
+    let buf = vec![0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, ];
+
+    let expected = MessageFrame::Unknown { tpe: types::TLEASE, body: buf.clone() };
+    assert_eq!(decode_frame(types::TLEASE, Cursor::new(buf.clone())).unwrap(), expected);
+
+    let mut w = writer();
+    encode_frame(&mut w, &expected).unwrap();
+    assert_eq!(w.into_inner(), buf);
 }
@@ -0,0 +1,110 @@
+extern crate mux;
+
+use mux::*;
+use std::io;
+use std::time::Duration;
+
+fn frames() -> Vec<MessageFrame> {
+    vec![
+        MessageFrame::Treq(Treq {
+            headers: vec![(1, vec![4, 5, 6])],
+            body: vec![1, 2, 3],
+        }),
+        MessageFrame::Rreq(Rmsg::Ok(vec![1, 2, 3])),
+        MessageFrame::Rreq(Rmsg::Nack("Boo".to_owned())),
+        MessageFrame::Tdispatch(Tdispatch {
+            contexts: vec![(vec![1, 2, 3], vec![4, 5, 6])],
+            dest: "/foo".to_string(),
+            dtab: Dtab::parse("/a=>/b").unwrap(),
+            body: vec![1, 2, 3],
+        }),
+        MessageFrame::Rdispatch(Rdispatch {
+            contexts: vec![(vec![1, 2, 3], vec![4, 5, 6])],
+            msg: Rmsg::Error("Boo".to_owned()),
+        }),
+        MessageFrame::Tinit(Init {
+            version: 12,
+            headers: vec![(vec![1, 2, 3], vec![4, 5, 6, 7]), (vec![43, 127], vec![])],
+        }),
+        MessageFrame::Rinit(Init {
+            version: 1,
+            headers: Vec::new(),
+        }),
+        MessageFrame::Tdrain,
+        MessageFrame::Rdrain,
+        MessageFrame::Tping,
+        MessageFrame::Rping,
+        MessageFrame::Tdiscarded(Tdiscarded { id: 5, msg: "Bye".to_owned() }),
+        MessageFrame::Rdiscarded,
+        MessageFrame::Tlease(Tlease { duration: Duration::from_millis(1500) }),
+        MessageFrame::Rerr(Rerr { msg: "Foo!".to_owned() }),
+    ]
+}
+
+fn encode(msg: &Message) -> Vec<u8> {
+    let mut w = io::Cursor::new(Vec::new());
+    codec::encode_message(&mut w, msg).unwrap();
+    w.into_inner()
+}
+
+#[test]
+fn decode_ref_matches_owned() {
+    for frame in frames() {
+        let msg = Message { tag: Tag::new(true, 2), frame };
+        let buf = encode(&msg);
+
+        let decoded = codec::decode_message_ref(&buf).unwrap();
+        assert_eq!(decoded.frame.frame_id(), msg.frame.frame_id());
+        assert_eq!(decoded.into_owned(), msg);
+    }
+}
+
+#[test]
+fn tdispatch_ref_borrows_frame() {
+    let msg = Message { tag: Tag::new(true, 2), frame: frames().remove(3) };
+    let buf = encode(&msg);
+    let range = buf.as_ptr() as usize..buf.as_ptr() as usize + buf.len();
+    let within = |s: &[u8]| range.contains(&(s.as_ptr() as usize));
+
+    match codec::decode_message_ref(&buf).unwrap().frame {
+        MessageFrameRef::Tdispatch(t) => {
+            assert!(within(t.body));
+            assert!(within(t.dest.as_bytes()));
+            assert!(within(t.contexts[0].0) && within(t.contexts[0].1));
+            assert_eq!(t.dtab, Dtab::parse("/a=>/b").unwrap());
+            assert_eq!(t.body, &[1, 2, 3]);
+        }
+        other => panic!("Unexpected frame: {:?}", other),
+    }
+}
+
+#[test]
+fn truncated_frame() {
+    let msg = Message { tag: Tag::new(true, 2), frame: frames().remove(3) };
+    let buf = encode(&msg);
+
+    assert!(codec::decode_message_ref(&buf[..10]).is_err());
+    assert!(codec::decode_message_ref(&buf[..2]).is_err());
+}
+
+#[test]
+fn invalid_utf8() {
+    // Rerr with an invalid UTF8 message
+    let buf = [0x80, 0x00, 0x00, 0x01, 0xff, 0xfe];
+    assert!(codec::decode_message_ref(&buf).is_err());
+}
+
+#[test]
+fn partial_init_header() {
+    // a Tinit ending partway through the length of a header key
+    let buf = [0x44, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00];
+    assert!(codec::decode_message_ref(&buf).is_err());
+    assert!(codec::decode_message(io::Cursor::new(&buf[..])).is_err());
+
+    // while the headers may end with the frame
+    let buf = [0x44, 0x00, 0x00, 0x01, 0x00, 0x01];
+    let init = Init { version: 1, headers: Vec::new() };
+    assert_eq!(codec::decode_message_ref(&buf).unwrap().into_owned().frame, MessageFrame::Tinit(init));
+    let init = Init { version: 1, headers: Vec::new() };
+    assert_eq!(codec::decode_message(io::Cursor::new(&buf[..])).unwrap().frame, MessageFrame::Tinit(init));
+}
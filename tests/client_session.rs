@@ -0,0 +1,238 @@
+extern crate mux;
+
+mod common;
+
+use common::write;
+use mux::*;
+use mux::client::{Error, Session};
+use mux::contexts::{self, Deadline};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// a minimal server echoing the bodies of requests. If `drained` is given,
+// the server sends a Tdrain after the first dispatch and reports the Rdrain.
+fn serve(rinit: bool, drained: Option<mpsc::Sender<Tag>>) -> TcpStream {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = stream.try_clone().unwrap();
+        while let Ok(msg) = codec::read_message(&mut reader) {
+            let frame = match msg.frame {
+                MessageFrame::Tinit(_) if rinit => {
+                    MessageFrame::Rinit(Init { version: 3, headers: Vec::new() })
+                }
+                MessageFrame::Tinit(_) => MessageFrame::Rerr(Rerr { msg: "unknown".to_string() }),
+                MessageFrame::Tping => MessageFrame::Rping,
+                MessageFrame::Treq(treq) => MessageFrame::Rreq(Rmsg::Ok(treq.body)),
+                MessageFrame::Tdispatch(tdispatch) => MessageFrame::Rdispatch(Rdispatch {
+                    contexts: tdispatch.contexts,
+                    msg: Rmsg::Ok(tdispatch.body),
+                }),
+                MessageFrame::Rdrain => {
+                    drained.as_ref().unwrap().send(msg.tag).unwrap();
+                    continue;
+                }
+                _ => MessageFrame::Rerr(Rerr { msg: "unexpected".to_string() }),
+            };
+            let is_dispatch = frame.frame_id() == types::RDISPATCH;
+            write(&mut stream, msg.tag, frame);
+
+            if is_dispatch && drained.is_some() {
+                write(&mut stream, Tag::new(true, 0), MessageFrame::Tdrain);
+            }
+        }
+    });
+
+    TcpStream::connect(addr).unwrap()
+}
+
+fn session(rinit: bool) -> Session {
+    let stream = serve(rinit, None);
+    Session::new(stream.try_clone().unwrap(), stream).unwrap()
+}
+
+#[test]
+fn negotiates_version() {
+    assert_eq!(session(true).version(), VERSION);
+    assert_eq!(session(false).version(), 0);
+}
+
+#[test]
+fn ping_request_and_dispatch() {
+    let session = session(true);
+    session.ping().unwrap();
+
+    let rmsg = session.request(Treq { headers: Vec::new(), body: b"treq".to_vec() }).unwrap();
+    assert_eq!(rmsg, Rmsg::Ok(b"treq".to_vec()));
+
+    let rdispatch = session.dispatch(Tdispatch::new("/foo".to_string(), b"hello".to_vec())).unwrap();
+    assert_eq!(rdispatch.msg, Rmsg::Ok(b"hello".to_vec()));
+}
+
+#[test]
+fn concurrent_dispatches() {
+    let session = Arc::new(session(true));
+    let threads: Vec<_> = (0..8u8).map(|i| {
+        let session = session.clone();
+        thread::spawn(move || {
+            for j in 0..50u8 {
+                let payload = vec![i, j];
+                let tdispatch = Tdispatch::new("/foo".to_string(), vec![i, j]);
+                assert_eq!(session.dispatch(tdispatch).unwrap().msg, Rmsg::Ok(payload));
+            }
+        })
+    }).collect();
+
+    for t in threads {
+        t.join().unwrap();
+    }
+}
+
+#[test]
+fn server_initiated_drain() {
+    let (tx, rx) = mpsc::channel();
+    let stream = serve(true, Some(tx));
+    let session = Session::new(stream.try_clone().unwrap(), stream).unwrap();
+
+    session.dispatch(Tdispatch::new("/foo".to_string(), b"a".to_vec())).unwrap();
+    assert_eq!(rx.recv().unwrap(), Tag::new(true, 0));
+    assert!(session.is_draining());
+
+    match session.ping() {
+        Err(Error::Draining) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn completes_outstanding_requests_before_closing() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let peer = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = stream.try_clone().unwrap();
+        let tinit = codec::read_message(&mut reader).unwrap();
+        write(&mut stream, tinit.tag, MessageFrame::Rinit(Init { version: VERSION, headers: Vec::new() }));
+
+        // drain with a dispatch outstanding
+        let tdispatch = codec::read_message(&mut reader).unwrap();
+        write(&mut stream, Tag::new(true, 1), MessageFrame::Tdrain);
+        assert_eq!(codec::read_message(&mut reader).unwrap().frame, MessageFrame::Rdrain);
+
+        let rdispatch = Rdispatch { contexts: Vec::new(), msg: Rmsg::Ok(b"late".to_vec()) };
+        write(&mut stream, tdispatch.tag, MessageFrame::Rdispatch(rdispatch));
+
+        // the session closes once the dispatch completed
+        assert!(codec::read_message(&mut reader).is_err());
+    });
+
+    let session = Arc::new(Session::connect(addr).unwrap());
+    let dispatch = {
+        let session = session.clone();
+        thread::spawn(move || session.dispatch(Tdispatch::new("/foo".to_string(), b"a".to_vec())))
+    };
+
+    assert_eq!(dispatch.join().unwrap().unwrap().msg, Rmsg::Ok(b"late".to_vec()));
+    session.wait_drained();
+    assert!(session.is_draining());
+    peer.join().unwrap();
+}
+
+#[test]
+fn drain_closes_session() {
+    let session = session(true);
+    session.ping().unwrap();
+    session.drain().unwrap();
+    assert!(session.is_closed());
+
+    match session.ping() {
+        Err(Error::Closed) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn stamps_deadlines() {
+    let session = session(true);
+    let rdispatch = session.dispatch_timeout(Tdispatch::new("/foo".to_string(), b"a".to_vec()), Duration::from_secs(60)).unwrap();
+    let deadline = contexts::get::<Deadline, _>(&rdispatch.contexts).unwrap().unwrap();
+    assert!(deadline.remaining() > Duration::from_secs(59));
+
+    // a sooner deadline set upstream is kept
+    let upstream = Deadline::from_timeout(Duration::from_secs(10));
+    let mut tdispatch = Tdispatch::new("/foo".to_string(), b"b".to_vec());
+    contexts::set(&mut tdispatch.contexts, &upstream);
+    let rdispatch = session.dispatch_timeout(tdispatch, Duration::from_secs(60)).unwrap();
+    let deadline = contexts::get::<Deadline, _>(&rdispatch.contexts).unwrap().unwrap();
+    assert_eq!(deadline.deadline, upstream.deadline);
+    assert_eq!(rdispatch.contexts.len(), 1);
+}
+
+#[test]
+fn discards_requests_past_their_deadline() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let peer = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = stream.try_clone().unwrap();
+        let tinit = codec::read_message(&mut reader).unwrap();
+        write(&mut stream, tinit.tag, MessageFrame::Rinit(Init { version: VERSION, headers: Vec::new() }));
+
+        let tdispatch = codec::read_message(&mut reader).unwrap();
+        let tdiscarded = codec::read_message(&mut reader).unwrap();
+        assert_eq!(tdiscarded.tag, Tag::new(true, 0));
+        match tdiscarded.frame {
+            MessageFrame::Tdiscarded(ref tdiscarded) => assert_eq!(tdiscarded.id, tdispatch.tag.id),
+            ref other => panic!("Unexpected frame: {:?}", other),
+        }
+
+        // the late response is dropped and the session carries on
+        let rdispatch = Rdispatch { contexts: Vec::new(), msg: Rmsg::Ok(b"late".to_vec()) };
+        write(&mut stream, tdispatch.tag, MessageFrame::Rdispatch(rdispatch));
+        let tping = codec::read_message(&mut reader).unwrap();
+        write(&mut stream, tping.tag, MessageFrame::Rping);
+    });
+
+    let session = Session::connect(addr).unwrap();
+    match session.dispatch_timeout(Tdispatch::new("/foo".to_string(), b"a".to_vec()), Duration::from_millis(50)) {
+        Err(Error::Timeout) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    session.ping().unwrap();
+    peer.join().unwrap();
+}
+
+#[test]
+fn releases_tags_of_discarded_requests() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let peer = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = stream.try_clone().unwrap();
+        let tinit = codec::read_message(&mut reader).unwrap();
+        write(&mut stream, tinit.tag, MessageFrame::Rinit(Init { version: VERSION, headers: Vec::new() }));
+
+        let tdispatch = codec::read_message(&mut reader).unwrap();
+        codec::read_message(&mut reader).unwrap();
+        write(&mut stream, tdispatch.tag, MessageFrame::Rdiscarded);
+
+        // the session closes once drained, without waiting for a response
+        assert!(codec::read_message(&mut reader).is_err());
+    });
+
+    let session = Session::connect(addr).unwrap();
+    match session.dispatch_timeout(Tdispatch::new("/foo".to_string(), b"a".to_vec()), Duration::from_millis(50)) {
+        Err(Error::Timeout) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    session.drain().unwrap();
+    peer.join().unwrap();
+}
@@ -0,0 +1,108 @@
+extern crate mux;
+
+use mux::*;
+use mux::codec::Error;
+use std::io;
+
+#[test]
+fn unknown_frame_type() {
+    match codec::decode_message(&[100, 0, 0, 1][..]) {
+        Err(Error::UnknownFrameType(100)) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    match codec::decode_message_ref(&[100, 0, 0, 1]) {
+        Err(Error::UnknownFrameType(100)) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn invalid_rmsg_status() {
+    match codec::decode_rreq(&[3, 1, 2][..]) {
+        Err(Error::InvalidRmsgStatus(3)) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    match codec::decode_rdispatch(&[7, 0, 0][..]) {
+        Err(Error::InvalidRmsgStatus(7)) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn invalid_utf8() {
+    match codec::decode_rerr(&[0xff, 0xfe][..]) {
+        Err(Error::InvalidUtf8 { field: "rerr message" }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    // Tdispatch with no contexts and a non-UTF8 destination
+    match codec::decode_tdispatch(&[0, 0, 0, 1, 0xff, 0, 0][..]) {
+        Err(Error::InvalidUtf8 { field: "dest" }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn length_overflow() {
+    let mut w = io::Cursor::new(Vec::new());
+    let tdispatch = Tdispatch::new(String::from_utf8(vec![b'a'; 70_000]).unwrap(), vec![]);
+
+    match codec::encode_tdispatch(&mut w, &tdispatch) {
+        Err(Error::LengthOverflow { field: "dest", len: 70_000, max: 65_535 }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn frame_too_small() {
+    match codec::read_message(&mut &[0, 0, 0, 3, 65, 0, 0][..]) {
+        Err(Error::FrameTooSmall(3)) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn unknown_lease_unit() {
+    match codec::decode_tlease(&[1, 0, 0, 0, 0, 0, 0, 0, 1][..]) {
+        Err(Error::UnknownLeaseUnit(1)) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn malformed_dentry() {
+    // a Tdispatch to /a with the dtab entry "a=>/b", whose prefix isn't a path
+    let buf = [2, 0, 0, 1, 0, 0, 0, 2, b'/', b'a', 0, 1, 0, 1, b'a', 0, 2, b'/', b'b'];
+    match codec::decode_message(&buf[..]) {
+        Err(Error::Dtab(e)) => assert_eq!(e.position, 0),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    match codec::decode_message_ref(&buf) {
+        Err(Error::Dtab(e)) => assert_eq!(e.position, 0),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn truncated_frame() {
+    match codec::read_message(&mut &[0, 0, 0, 8, 2, 0, 0, 1][..]) {
+        Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn convert_to_io_error() {
+    let err: io::Error = Error::InvalidRmsgStatus(3).into();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "Invalid status code: 3");
+
+    let err: io::Error = Error::UnknownFrameType(100).into();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    let err: io::Error = Error::Io(io::Error::new(io::ErrorKind::BrokenPipe, "boom")).into();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+}
@@ -0,0 +1,11 @@
+// Helpers shared by the integration tests.
+
+use mux::*;
+use std::io::Write;
+
+// write `frame` to `stream` in a single call so the peer reads it whole
+pub fn write<W: Write>(stream: &mut W, tag: Tag, frame: MessageFrame) {
+    let mut buf = Vec::new();
+    codec::write_message(&mut buf, &Message { tag, frame }).unwrap();
+    stream.write_all(&buf).unwrap();
+}
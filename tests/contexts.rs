@@ -0,0 +1,114 @@
+extern crate mux;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use mux::*;
+use mux::contexts::*;
+
+fn roundtrip<K: ContextKey + PartialEq + std::fmt::Debug>(value: K) {
+    let mut contexts = Contexts::new();
+    set(&mut contexts, &value);
+    assert_eq!(contexts, vec![(K::KEY.as_bytes().to_vec(), value.encode())]);
+    assert_eq!(get::<K, _>(&contexts).unwrap(), Some(value));
+}
+
+#[test]
+fn trace_context() {
+    let trace_id = TraceId {
+        span_id: 1,
+        parent_id: 2,
+        trace_id: 3,
+        trace_id_high: None,
+        sampled: Some(true),
+        debug: false,
+    };
+
+    let mut expected = vec![0; 32];
+    expected[7] = 1;
+    expected[15] = 2;
+    expected[23] = 3;
+    expected[31] = 6;
+    assert_eq!(trace_id.encode(), expected);
+    roundtrip(trace_id);
+
+    let wide = TraceId { trace_id_high: Some(4), sampled: None, debug: true, ..trace_id };
+    let encoded = wide.encode();
+    assert_eq!(encoded.len(), 40);
+    assert_eq!(encoded[31], 1);
+    assert_eq!(encoded[39], 4);
+    roundtrip(wide);
+
+    roundtrip(TraceId { sampled: Some(false), ..trace_id });
+    assert_eq!(TraceId::decode(&[0; 16]), Err(ContextError::InvalidValue(TRACE_CONTEXT_KEY)));
+}
+
+#[test]
+fn deadline() {
+    let deadline = Deadline {
+        timestamp: UNIX_EPOCH + Duration::from_nanos(1),
+        deadline: UNIX_EPOCH + Duration::from_secs(2),
+    };
+    assert_eq!(deadline.encode(), [0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0x77, 0x35, 0x94, 0x00]);
+    assert!(deadline.is_expired());
+    assert_eq!(deadline.remaining(), Duration::from_secs(0));
+    roundtrip(deadline);
+
+    let timeout = Deadline::from_timeout(Duration::from_secs(60));
+    assert!(!timeout.is_expired());
+    assert!(timeout.remaining() > Duration::from_secs(59));
+
+    // saturates like Finagle's `Time.Top`
+    let top = Deadline::from_timeout(Duration::from_secs(u64::MAX));
+    assert_eq!(&top.encode()[8..], &i64::MAX.to_be_bytes());
+
+    let before_epoch = Deadline { timestamp: UNIX_EPOCH - Duration::from_secs(1), deadline: SystemTime::now() };
+    assert_eq!(&before_epoch.encode()[..8], &(-1_000_000_000i64).to_be_bytes());
+    assert_eq!(Deadline::decode(&before_epoch.encode()).unwrap().timestamp, before_epoch.timestamp);
+
+    assert_eq!(Deadline::decode(&[0; 8]), Err(ContextError::InvalidValue(DEADLINE_KEY)));
+}
+
+#[test]
+fn retries_client_id_and_backup_request() {
+    assert_eq!(Retries(258).encode(), [0, 0, 1, 2]);
+    roundtrip(Retries(3));
+    assert!(Retries::decode(&[1]).is_err());
+
+    assert_eq!(ClientId("users".to_string()).encode(), b"users");
+    roundtrip(ClientId("users".to_string()));
+    assert!(ClientId::decode(&[0xff]).is_err());
+
+    assert!(BackupRequest.encode().is_empty());
+    roundtrip(BackupRequest);
+}
+
+#[test]
+fn preserves_other_contexts() {
+    let mut tdispatch = Tdispatch::new("/s".to_string(), b"ping".to_vec());
+    tdispatch.contexts.push((b"custom".to_vec(), b"value".to_vec()));
+    tdispatch.contexts.push((RETRIES_KEY.as_bytes().to_vec(), vec![0, 0, 0, 1]));
+
+    assert_eq!(get::<Retries, _>(&tdispatch.contexts).unwrap(), Some(Retries(1)));
+    assert_eq!(get::<ClientId, _>(&tdispatch.contexts).unwrap(), None);
+
+    set(&mut tdispatch.contexts, &Retries(2));
+    set(&mut tdispatch.contexts, &BackupRequest);
+    assert_eq!(tdispatch.contexts.len(), 3);
+    assert_eq!(tdispatch.contexts[0], (b"custom".to_vec(), b"value".to_vec()));
+    assert_eq!(get::<Retries, _>(&tdispatch.contexts).unwrap(), Some(Retries(2)));
+
+    assert!(remove::<BackupRequest>(&mut tdispatch.contexts));
+    assert!(!remove::<BackupRequest>(&mut tdispatch.contexts));
+    assert_eq!(get::<BackupRequest, _>(&tdispatch.contexts).unwrap(), None);
+
+    // invalid values are errors rather than missing
+    tdispatch.contexts.push((DEADLINE_KEY.as_bytes().to_vec(), b"short".to_vec()));
+    assert!(get::<Deadline, _>(&tdispatch.contexts).is_err());
+}
+
+#[test]
+fn reads_borrowed_contexts() {
+    let retries = Retries(7).encode();
+    let contexts: ContextsRef = vec![(b"other", b"x"), (RETRIES_KEY.as_bytes(), &retries)];
+    assert_eq!(get::<Retries, _>(&contexts).unwrap(), Some(Retries(7)));
+}
@@ -0,0 +1,160 @@
+extern crate mux;
+
+use mux::*;
+use mux::codec::{DecoderConfig, Error, FrameDecoder};
+use std::io;
+
+fn encode(frame: MessageFrame) -> Vec<u8> {
+    let mut w = io::Cursor::new(Vec::new());
+    codec::write_message(&mut w, &Message { tag: Tag::new(true, 1), frame }).unwrap();
+    w.into_inner()
+}
+
+fn tdispatch(contexts: usize, dentries: usize) -> MessageFrame {
+    let mut tdispatch = Tdispatch::new("/foo".to_string(), vec![1, 2, 3]);
+    for _ in 0..contexts {
+        tdispatch.contexts.push((vec![1], vec![2]));
+    }
+    for _ in 0..dentries {
+        tdispatch.dtab.add_entry(Prefix::read("/a").unwrap(), NameTree::read("/b").unwrap());
+    }
+    MessageFrame::Tdispatch(tdispatch)
+}
+
+#[test]
+fn default_frame_size_limit() {
+    // a frame announcing 1GB shouldn't be read at all
+    let buf = [0x40, 0, 0, 0, 65, 0, 0, 1];
+    match codec::read_message(&mut &buf[..]) {
+        Err(Error::FrameTooLarge { size: 0x4000_0000, .. }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn frame_size_limit() {
+    let config = DecoderConfig { max_frame_size: 20, ..DecoderConfig::default() };
+    let buf = encode(tdispatch(2, 0));
+
+    match codec::read_message_with(&mut &buf[..], &config) {
+        Err(Error::FrameTooLarge { max: 20, .. }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    let mut decoder = FrameDecoder::with_config(config);
+    match decoder.decode(&buf[..4]) {
+        Err(Error::FrameTooLarge { max: 20, .. }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    match codec::decode_message_with(&buf[4..], &config) {
+        Err(Error::FrameTooLarge { size: 21, max: 20 }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    let config = DecoderConfig { max_frame_size: buf.len() - 4, ..config };
+    assert!(codec::read_message_with(&mut &buf[..], &config).is_ok());
+    assert!(codec::decode_message_with(&buf[4..], &config).is_ok());
+}
+
+#[test]
+fn context_limit() {
+    let config = DecoderConfig { max_contexts: 2, ..DecoderConfig::default() };
+
+    let buf = encode(tdispatch(2, 0));
+    assert!(codec::read_message_with(&mut &buf[..], &config).is_ok());
+
+    let buf = encode(tdispatch(3, 0));
+    match codec::read_message_with(&mut &buf[..], &config) {
+        Err(Error::LimitExceeded { field: "context count", len: 3, max: 2 }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    let buf = encode(MessageFrame::Rdispatch(Rdispatch {
+        contexts: vec![(vec![], vec![]); 3],
+        msg: Rmsg::Nack("".to_owned()),
+    }));
+    match codec::decode_message_with(&buf[4..], &config) {
+        Err(Error::LimitExceeded { field: "context count", len: 3, max: 2 }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn dtab_limit() {
+    let config = DecoderConfig { max_dtab_entries: 1, ..DecoderConfig::default() };
+
+    let buf = encode(tdispatch(0, 1));
+    assert!(codec::read_message_with(&mut &buf[..], &config).is_ok());
+
+    let buf = encode(tdispatch(0, 2));
+    match codec::read_message_with(&mut &buf[..], &config) {
+        Err(Error::LimitExceeded { field: "dtab entry count", len: 2, max: 1 }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn init_header_limit() {
+    // a Tinit header key announcing 4GB
+    let buf = [0, 0, 0, 14, 68, 0, 0, 1, 0, 1, 0xff, 0xff, 0xff, 0xff, 1, 2];
+    match codec::read_message(&mut &buf[..]) {
+        Err(Error::LimitExceeded { field: "init header size", len: 0xffff_ffff, .. }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    let config = DecoderConfig { max_init_header_size: 6, ..DecoderConfig::default() };
+    let init = |v: usize| MessageFrame::Tinit(Init {
+        version: 1,
+        headers: vec![(vec![1, 2, 3], vec![0; v])],
+    });
+
+    let buf = encode(init(3));
+    assert!(codec::read_message_with(&mut &buf[..], &config).is_ok());
+
+    let buf = encode(init(4));
+    match codec::read_message_with(&mut &buf[..], &config) {
+        Err(Error::LimitExceeded { field: "init header size", len: 7, max: 6 }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn unlimited_config() {
+    let buf = encode(tdispatch(2000, 2000));
+    assert!(codec::read_message(&mut &buf[..]).is_err());
+    assert!(codec::read_message_with(&mut &buf[..], &DecoderConfig::unlimited()).is_ok());
+}
+
+#[test]
+fn borrowed_decoder_limits() {
+    let config = DecoderConfig { max_contexts: 2, max_dtab_entries: 1, ..DecoderConfig::default() };
+
+    let buf = encode(tdispatch(2, 1));
+    assert!(codec::decode_message_ref_with(&buf[4..], &config).is_ok());
+
+    let buf = encode(tdispatch(3, 0));
+    match codec::decode_message_ref_with(&buf[4..], &config) {
+        Err(Error::LimitExceeded { field: "context count", len: 3, max: 2 }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    let buf = encode(tdispatch(0, 2));
+    match codec::decode_message_ref_with(&buf[4..], &config) {
+        Err(Error::LimitExceeded { field: "dtab entry count", len: 2, max: 1 }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    let config = DecoderConfig { max_frame_size: 20, ..DecoderConfig::default() };
+    match codec::decode_message_ref_with(&buf[4..], &config) {
+        Err(Error::FrameTooLarge { max: 20, .. }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    // a few bytes announcing 0xffff contexts fail without allocating for them
+    let buf = [2, 0, 0, 1, 0xff, 0xff, 0, 0];
+    match codec::decode_message_ref_with(&buf, &DecoderConfig::unlimited()) {
+        Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
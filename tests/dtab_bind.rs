@@ -0,0 +1,128 @@
+extern crate mux;
+
+use mux::*;
+use std::time::{Duration, Instant};
+
+fn path(s: &str) -> Path {
+    Path::read(s).unwrap()
+}
+
+fn tree(s: &str) -> NameTree<Path> {
+    NameTree::read(s).unwrap()
+}
+
+fn bind(dtab: &str, name: &str) -> Result<NameTree<Path>, BindError> {
+    Dtab::parse(dtab).unwrap().bind(&tree(name))
+}
+
+#[test]
+fn lookup() {
+    let dtab = Dtab::parse("/s=>/a;/s/foo=>/b|/c;/x=>/y").unwrap();
+    assert_eq!(dtab.lookup(&path("/s/foo/bar")), tree("(/b/bar | /c/bar) | /a/foo/bar"));
+    assert_eq!(dtab.lookup(&path("/s/baz")), tree("/a/baz"));
+    assert_eq!(dtab.lookup(&path("/s")), tree("/a"));
+    assert_eq!(dtab.lookup(&path("/t")), NameTree::Neg);
+    assert_eq!(dtab.lookup(&path("/")), NameTree::Neg);
+
+    // prefixes match whole elements
+    assert_eq!(dtab.lookup(&path("/sx")), NameTree::Neg);
+
+    let wildcards = Dtab::parse("/s/*/http=>/h;/*=>/any").unwrap();
+    assert_eq!(wildcards.lookup(&path("/s/foo/http/bar")), tree("/any/foo/http/bar | /h/bar"));
+    assert_eq!(wildcards.lookup(&path("/s/foo")), tree("/any/foo"));
+}
+
+#[test]
+fn binds_recursively() {
+    let dtab = "/srv=>/$/inet/localhost;/s=>/srv/users;/s/admin=>/srv/admins";
+    assert_eq!(bind(dtab, "/s/8080").unwrap(), tree("/$/inet/localhost/users/8080"));
+    assert_eq!(bind(dtab, "/s/admin/8080").unwrap(), tree("/$/inet/localhost/admins/8080"));
+    assert_eq!(bind(dtab, "/$/inet/host/80").unwrap(), tree("/$/inet/host/80"));
+    assert_eq!(bind(dtab, "/other").unwrap(), NameTree::Neg);
+}
+
+#[test]
+fn later_entries_take_precedence() {
+    assert_eq!(bind("/s=>/$/a;/s=>/$/b", "/s").unwrap(), tree("/$/b"));
+
+    // falling back to earlier entries when the later are negative
+    assert_eq!(bind("/s=>/$/a;/s=>/t", "/s").unwrap(), tree("/$/a"));
+    assert_eq!(bind("/s=>/$/a;/s=>~", "/s").unwrap(), tree("/$/a"));
+
+    // but not when they fail
+    assert_eq!(bind("/s=>/$/a;/s=>!", "/s").unwrap(), NameTree::Fail);
+}
+
+#[test]
+fn binds_trees() {
+    let dtab = "/a=>/$/a;/b=>/$/b;/f=>!;/e=>$";
+    assert_eq!(bind(dtab, "/x | /b | /a").unwrap(), tree("/$/b"));
+    assert_eq!(bind(dtab, "/x | /y").unwrap(), NameTree::Neg);
+    assert_eq!(bind(dtab, "/e | /a").unwrap(), NameTree::Empty);
+    assert_eq!(bind(dtab, "/f | /a").unwrap(), NameTree::Fail);
+
+    assert_eq!(bind(dtab, "2*/a & /b").unwrap(), tree("2*/$/a & /$/b"));
+    assert_eq!(bind(dtab, "/a & /x").unwrap(), tree("/$/a"));
+    assert_eq!(bind(dtab, "/a & /e").unwrap(), tree("/$/a"));
+    assert_eq!(bind(dtab, "/e & /e").unwrap(), NameTree::Empty);
+    assert_eq!(bind(dtab, "/x & /y").unwrap(), NameTree::Neg);
+    assert_eq!(bind(dtab, "/a & /f").unwrap(), NameTree::Fail);
+}
+
+#[test]
+fn depth_limit() {
+    assert_eq!(bind("/a=>/b;/b=>/a", "/a").unwrap_err(), BindError::DepthExceeded);
+    assert_eq!(bind("/a=>/a/x", "/a").unwrap_err(), BindError::DepthExceeded);
+
+    // alternatives after a binding aren't bound
+    assert_eq!(bind("/a=>/$/a | /a", "/a").unwrap(), tree("/$/a"));
+
+    // up to MAX_BIND_DEPTH rewrites
+    let chain = |len: usize| {
+        let dentries: Vec<_> = (0..len).map(|i| format!("/{}=>/{}", i, i + 1)).collect();
+        dentries.join(";") + &format!(";/{}=>/$/end", len)
+    };
+    assert_eq!(bind(&chain(MAX_BIND_DEPTH - 1), "/0").unwrap(), tree("/$/end"));
+    assert_eq!(bind(&chain(MAX_BIND_DEPTH), "/0").unwrap_err(), BindError::DepthExceeded);
+}
+
+#[test]
+fn lookups_limit() {
+    // each name fans out to two alternatives of the next, none of which bind
+    let dentries: Vec<_> = (0..29).map(|i| format!("/p{}=>/p{} | /p{}", i, i + 1, i + 1)).collect();
+    let start = Instant::now();
+    assert_eq!(bind(&dentries.join(";"), "/p0").unwrap_err(), BindError::LookupsExceeded);
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    // short enough to stay within the limit
+    let dentries: Vec<_> = (0..8).map(|i| format!("/p{}=>/p{} | /p{}", i, i + 1, i + 1)).collect();
+    assert_eq!(bind(&dentries.join(";"), "/p0").unwrap(), NameTree::Neg);
+}
+
+#[test]
+fn syntax_errors() {
+    let tdispatch = Tdispatch::new("not a path".to_string(), Vec::new());
+    match tdispatch.bind(&Dtab::parse("/a=>/$/a").unwrap()) {
+        Err(BindError::Syntax(e)) => assert_eq!(e.position, 0),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn binds_dispatch_destination() {
+    let base = Dtab::parse("/s=>/$/inet/prod;/t=>/$/inet/test").unwrap();
+    let mut tdispatch = Tdispatch {
+        contexts: Vec::new(),
+        dest: "/s/users".to_string(),
+        dtab: Dtab::new(),
+        body: Vec::new(),
+    };
+    assert_eq!(tdispatch.bind(&base).unwrap(), tree("/$/inet/prod/users"));
+
+    // the dtab of the request overrides the base
+    tdispatch.dtab = Dtab::parse("/s=>/t").unwrap();
+    assert_eq!(tdispatch.bind(&base).unwrap(), tree("/$/inet/test/users"));
+
+    tdispatch.dest = "users".to_string();
+    assert!(tdispatch.bind(&base).is_err());
+}
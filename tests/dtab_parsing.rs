@@ -0,0 +1,111 @@
+extern crate mux;
+
+use mux::*;
+
+fn path(s: &str) -> Path {
+    Path::read(s).unwrap()
+}
+
+fn leaf(s: &str) -> NameTree<Path> {
+    NameTree::Leaf(path(s))
+}
+
+#[test]
+fn paths() {
+    assert_eq!(path("/"), Path::empty());
+    assert_eq!(path("/foo/bar").elems(), &[b"foo".to_vec(), b"bar".to_vec()]);
+    assert_eq!(path("/a\\x2fb").elems(), &[b"a/b".to_vec()]);
+    assert_eq!(path("/a\\x2Fb").to_string(), "/a\\x2fb");
+    assert_eq!(Path::new(vec![b"\xff ok".to_vec()]).to_string(), "/\\xff\\x20ok");
+    assert_eq!("/s/$_:.#%-".parse::<Path>().unwrap().len(), 2);
+
+    for &(input, position) in &[("foo", 0), ("/a//b", 3), ("/a/", 3), ("/a\\x4", 4), ("/a b", 3)] {
+        assert_eq!(Path::read(input).unwrap_err().position, position, "{}", input);
+    }
+}
+
+#[test]
+fn name_trees() {
+    assert_eq!(NameTree::read("~").unwrap(), NameTree::Neg);
+    assert_eq!(NameTree::read("!").unwrap(), NameTree::Fail);
+    assert_eq!(NameTree::read("$").unwrap(), NameTree::Empty);
+    assert_eq!(NameTree::read(" /a ").unwrap(), leaf("/a"));
+
+    assert_eq!(NameTree::read("/a | /b | ~").unwrap(), NameTree::Alt(vec![leaf("/a"), leaf("/b"), NameTree::Neg]));
+    assert_eq!(NameTree::read("(/a | /b) & 0.5*/c").unwrap(), NameTree::Union(vec![
+        Weighted { weight: 1.0, tree: NameTree::Alt(vec![leaf("/a"), leaf("/b")]) },
+        Weighted { weight: 0.5, tree: leaf("/c") },
+    ]));
+
+    for &(input, position) in &[("", 0), ("/a |", 4), ("(/a", 3), ("2/a", 1), ("/a /b", 3), ("1..2*/a", 0)] {
+        assert_eq!(NameTree::read(input).unwrap_err().position, position, "{}", input);
+    }
+}
+
+#[test]
+fn nesting_limit() {
+    let nested = |depth| format!("{}/a{}", "(".repeat(depth), ")".repeat(depth));
+    assert_eq!(NameTree::read(&nested(100)).unwrap(), leaf("/a"));
+
+    let err = NameTree::read(&nested(101)).unwrap_err();
+    assert_eq!(err.position, 100);
+    assert_eq!(err.message, "name tree nested too deeply");
+
+    // deep enough to overflow the stack without the limit
+    let dtab = format!("/s=>{}", "(".repeat(60000));
+    assert_eq!(Dtab::parse(&dtab).unwrap_err().position, 104);
+}
+
+#[test]
+fn canonical_name_trees() {
+    let cases = [
+        ("/a|/b", "/a | /b"),
+        ("(/a|/b)|/c", "(/a | /b) | /c"),
+        ("/a&/b|/c", "/a & /b | /c"),
+        ("/a&(/b|/c)", "/a & (/b | /c)"),
+        ("3*/a & 1*/b", "3.0*/a & /b"),
+        ("0.25*(/a & /b)", "0.25*(/a & /b)"),
+        ("((/a))", "/a"),
+        ("~|!|$", "~ | ! | $"),
+    ];
+
+    for &(input, shown) in &cases {
+        let tree = NameTree::read(input).unwrap();
+        assert_eq!(tree.to_string(), shown);
+        assert_eq!(NameTree::read(shown).unwrap(), tree);
+    }
+}
+
+#[test]
+fn dtabs() {
+    let dtab = Dtab::parse("/s=>/a|/b;/x=>/y").unwrap();
+    assert_eq!(dtab.entries.len(), 2);
+    assert_eq!(dtab.entries[0].prefix, Prefix::from(path("/s")));
+    assert_eq!(dtab.entries[0].dst, NameTree::Alt(vec![leaf("/a"), leaf("/b")]));
+    assert_eq!(dtab.entries[1].prefix, Prefix::from(path("/x")));
+    assert_eq!(dtab.entries[1].dst, leaf("/y"));
+    assert_eq!(dtab.to_string(), "/s=>/a | /b;/x=>/y");
+    assert_eq!(dtab.to_string().parse::<Dtab>().unwrap(), dtab);
+
+    assert_eq!(Dtab::parse("").unwrap(), Dtab::new());
+    assert_eq!(Dtab::parse(" /a => /b ; ").unwrap().to_string(), "/a=>/b");
+
+    assert_eq!(Dtab::parse("/a=>/b;/c").unwrap_err().position, 9);
+    assert_eq!(Dtab::parse("/a=>/b /c").unwrap_err().position, 7);
+}
+
+#[test]
+fn dentries() {
+    let dentry: Dentry = "/s/foo => 2*/a & /b".parse().unwrap();
+    let dst = NameTree::Union(vec![
+        Weighted { weight: 2.0, tree: leaf("/a") },
+        Weighted { weight: 1.0, tree: leaf("/b") },
+    ]);
+    assert_eq!(dentry, Dentry::new(Prefix::from(path("/s/foo")), dst));
+    assert_eq!(dentry.to_string(), "/s/foo=>2.0*/a & /b");
+
+    let wildcard = Dentry::read("/s/*/http=>/t").unwrap();
+    assert_eq!(wildcard.prefix.elems()[1], PrefixElem::Any);
+    assert_eq!(wildcard.to_string(), "/s/*/http=>/t");
+    assert!(Path::read("/s/*").is_err());
+}
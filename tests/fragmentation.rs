@@ -0,0 +1,211 @@
+extern crate mux;
+
+use mux::*;
+use mux::codec::{DecoderConfig, Error, Fragment, Fragmenter, Reassembler};
+use std::io;
+
+fn tdispatch(id: u32, len: usize) -> Message {
+    let mut tdispatch = Tdispatch::new("/foo".to_string(), vec![id as u8; len]);
+    tdispatch.contexts.push((b"key".to_vec(), b"value".to_vec()));
+    tdispatch.dtab.add_entry(Prefix::read("/a").unwrap(), NameTree::read("/b").unwrap());
+    Message {
+        tag: Tag::new(true, id),
+        frame: MessageFrame::Tdispatch(tdispatch),
+    }
+}
+
+fn rdispatch(id: u32, len: usize) -> Message {
+    Message {
+        tag: Tag::new(true, id),
+        frame: MessageFrame::Rdispatch(Rdispatch {
+            contexts: Vec::new(),
+            msg: Rmsg::Ok(vec![id as u8; len]),
+        }),
+    }
+}
+
+#[test]
+fn fragment_sizes_and_flags() {
+    let msg = tdispatch(5, 1000);
+    let size = codec::size::frame_size(&msg.frame);
+    let fragments = Fragmenter::new(100).fragment(&msg).unwrap();
+
+    assert_eq!(fragments.len(), size.div_ceil(100));
+    assert_eq!(fragments.iter().map(|f| f.body.len()).sum::<usize>(), size);
+    for (i, f) in fragments.iter().enumerate() {
+        assert_eq!(f.tpe, types::TDISPATCH);
+        assert_eq!(f.tag.id, 5);
+        assert_eq!(f.tag.end, i + 1 == fragments.len());
+        assert!(f.body.len() <= 100);
+    }
+}
+
+#[test]
+fn small_and_control_messages_are_not_fragmented() {
+    let fragmenter = Fragmenter::new(8);
+    let ping = Message { tag: Tag::new(true, 1), frame: MessageFrame::Tping };
+    assert_eq!(fragmenter.fragment(&ping).unwrap().len(), 1);
+
+    let rerr = Message {
+        tag: Tag::new(true, 1),
+        frame: MessageFrame::Rerr(Rerr { msg: "a long error message".to_string() }),
+    };
+    assert_eq!(fragmenter.fragment(&rerr).unwrap().len(), 1);
+
+    let small = rdispatch(1, 2);
+    assert_eq!(Fragmenter::new(100).fragment(&small).unwrap().len(), 1);
+}
+
+#[test]
+fn reassemble_interleaved() {
+    let fragmenter = Fragmenter::new(64);
+    let a = fragmenter.fragment(&tdispatch(1, 500)).unwrap();
+    let b = fragmenter.fragment(&rdispatch(2, 300)).unwrap();
+
+    let mut reassembler = Reassembler::new();
+    let mut done = Vec::new();
+    let mut a = a.into_iter();
+    let mut b = b.into_iter();
+    loop {
+        let (x, y) = (a.next(), b.next());
+        if x.is_none() && y.is_none() {
+            break;
+        }
+        for f in x.into_iter().chain(y) {
+            if let Some(msg) = reassembler.push(f).unwrap() {
+                done.push(msg);
+            }
+        }
+    }
+
+    assert_eq!(done, vec![rdispatch(2, 300), tdispatch(1, 500)]);
+    assert_eq!(reassembler.pending(), 0);
+}
+
+#[test]
+fn fragment_wire_roundtrip() {
+    let msg = tdispatch(7, 200);
+    let mut w = io::Cursor::new(Vec::new());
+    for f in Fragmenter::new(50).fragment(&msg).unwrap() {
+        codec::write_fragment(&mut w, &f).unwrap();
+    }
+
+    let buf = w.into_inner();
+    let mut r = &buf[..];
+    let mut reassembler = Reassembler::new();
+    let mut result = None;
+    while !r.is_empty() {
+        result = reassembler.push(codec::read_fragment(&mut r).unwrap()).unwrap();
+    }
+    assert_eq!(result, Some(msg));
+}
+
+#[test]
+fn rerr_terminates_rdispatch() {
+    let mut fragments = Fragmenter::new(16).fragment(&rdispatch(3, 100)).unwrap();
+    fragments.truncate(2);
+
+    let mut reassembler = Reassembler::new();
+    for f in fragments {
+        assert!(reassembler.push(f).unwrap().is_none());
+    }
+    assert_eq!(reassembler.pending(), 1);
+
+    let rerr = Fragment {
+        tpe: types::RERR,
+        tag: Tag::new(true, 3),
+        body: b"failed".to_vec(),
+    };
+    let msg = reassembler.push(rerr).unwrap().unwrap();
+    assert_eq!(msg.frame, MessageFrame::Rerr(Rerr { msg: "failed".to_string() }));
+    assert_eq!(reassembler.pending(), 0);
+}
+
+#[test]
+fn tdiscarded_terminates_tdispatch() {
+    let mut fragments = Fragmenter::new(16).fragment(&tdispatch(9, 100)).unwrap();
+    fragments.truncate(3);
+
+    let mut reassembler = Reassembler::new();
+    for f in fragments {
+        assert!(reassembler.push(f).unwrap().is_none());
+    }
+
+    let discard = Message {
+        tag: Tag::new(true, 0),
+        frame: MessageFrame::Tdiscarded(Tdiscarded { id: 9, msg: "timeout".to_string() }),
+    };
+    let fragment = Fragmenter::new(16).fragment(&discard).unwrap().pop().unwrap();
+    assert_eq!(reassembler.push(fragment).unwrap(), Some(discard));
+    assert_eq!(reassembler.pending(), 0);
+}
+
+#[test]
+fn invalid_fragments() {
+    let mut reassembler = Reassembler::new();
+    let ping = Fragment { tpe: types::TPING, tag: Tag::new(false, 1), body: Vec::new() };
+    match reassembler.push(ping) {
+        Err(Error::InvalidFragment { tpe: types::TPING, id: 1 }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    let first = Fragment { tpe: types::TDISPATCH, tag: Tag::new(false, 2), body: vec![0] };
+    let second = Fragment { tpe: types::RDISPATCH, tag: Tag::new(true, 2), body: vec![0] };
+    assert!(reassembler.push(first).unwrap().is_none());
+    match reassembler.push(second) {
+        Err(Error::InvalidFragment { tpe: types::RDISPATCH, id: 2 }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    assert_eq!(reassembler.pending(), 0);
+}
+
+#[test]
+fn reassembled_size_limit() {
+    let config = DecoderConfig { max_frame_size: 100, ..DecoderConfig::default() };
+    let mut reassembler = Reassembler::with_config(config);
+    let mut result = Ok(None);
+    for f in Fragmenter::new(40).fragment(&tdispatch(1, 200)).unwrap() {
+        result = reassembler.push(f);
+        if result.is_err() {
+            break;
+        }
+    }
+
+    match result {
+        Err(Error::FrameTooLarge { max: 100, .. }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn pending_fragments_limit() {
+    let config = DecoderConfig { max_pending_fragments_size: 100, ..DecoderConfig::default() };
+    let mut reassembler = Reassembler::with_config(config);
+    for id in 1..3 {
+        let first = Fragment { tpe: types::TDISPATCH, tag: Tag::new(false, id), body: vec![0; 40] };
+        assert!(reassembler.push(first).unwrap().is_none());
+    }
+
+    let third = Fragment { tpe: types::TDISPATCH, tag: Tag::new(false, 3), body: vec![0; 40] };
+    match reassembler.push(third) {
+        Err(Error::LimitExceeded { field: "pending fragment size", len: 120, max: 100 }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    assert_eq!(reassembler.pending(), 2);
+
+    // abandoned sequences no longer count against the limit
+    let rerr = Fragment { tpe: types::RERR, tag: Tag::new(true, 1), body: b"gone".to_vec() };
+    assert!(reassembler.push(rerr).unwrap().is_some());
+    let third = Fragment { tpe: types::TDISPATCH, tag: Tag::new(false, 3), body: vec![0; 40] };
+    assert!(reassembler.push(third).unwrap().is_none());
+}
+
+#[test]
+fn fragmenting_out_of_range_tags() {
+    let mut msg = tdispatch(1, 100);
+    msg.tag.id = MAX_TAG + 1;
+    match Fragmenter::new(40).fragment(&msg) {
+        Err(Error::Tag(TagError::OutOfRange(id))) => assert_eq!(id, MAX_TAG + 1),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
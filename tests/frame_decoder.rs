@@ -0,0 +1,108 @@
+extern crate mux;
+
+use mux::*;
+use mux::codec::FrameDecoder;
+use std::io;
+
+fn messages() -> Vec<Message> {
+    vec![
+        Message {
+            tag: Tag::new(true, 1),
+            frame: MessageFrame::Tdispatch(Tdispatch {
+                contexts: vec![(vec![1, 2, 3], vec![4, 5, 6])],
+                dest: "/foo".to_string(),
+                dtab: Dtab::parse("/a=>/b").unwrap(),
+                body: vec![1, 2, 3],
+            }),
+        },
+        Message {
+            tag: Tag::new(true, 2),
+            frame: MessageFrame::Tping,
+        },
+        Message {
+            tag: Tag::new(false, 0x7fffff),
+            frame: MessageFrame::Rreq(Rmsg::Ok(vec![7; 300])),
+        },
+    ]
+}
+
+fn encode(msgs: &[Message]) -> Vec<u8> {
+    let mut w = io::Cursor::new(Vec::new());
+    for msg in msgs {
+        codec::write_message(&mut w, msg).unwrap();
+    }
+    w.into_inner()
+}
+
+// feed the chunks to the decoder, collecting every message it yields
+fn decode_chunks<'a, I: Iterator<Item = &'a [u8]>>(chunks: I) -> (Vec<Message>, FrameDecoder) {
+    let mut decoder = FrameDecoder::new();
+    let mut acc = Vec::new();
+
+    for chunk in chunks {
+        let mut chunk = chunk;
+        while let Some(msg) = decoder.decode(chunk).unwrap() {
+            acc.push(msg);
+            chunk = &[];
+        }
+    }
+
+    (acc, decoder)
+}
+
+#[test]
+fn decode_whole_buffer() {
+    let buf = encode(&messages());
+    let (decoded, decoder) = decode_chunks(Some(&buf[..]).into_iter());
+
+    assert_eq!(decoded, messages());
+    assert_eq!(decoder.buffered(), 0);
+}
+
+#[test]
+fn decode_byte_at_a_time() {
+    let buf = encode(&messages());
+    let (decoded, decoder) = decode_chunks(buf.chunks(1));
+
+    assert_eq!(decoded, messages());
+    assert_eq!(decoder.buffered(), 0);
+}
+
+#[test]
+fn decode_split_at_every_boundary() {
+    let buf = encode(&messages());
+
+    for i in 0..buf.len() + 1 {
+        let (first, second) = buf.split_at(i);
+        let (decoded, decoder) = decode_chunks(vec![first, second].into_iter());
+
+        assert_eq!(decoded, messages(), "split at {}", i);
+        assert_eq!(decoder.buffered(), 0);
+    }
+}
+
+#[test]
+fn retain_partial_frames() {
+    let buf = encode(&messages()[..2]);
+    let (decoded, decoder) = decode_chunks(Some(&buf[..buf.len() - 2]).into_iter());
+
+    assert_eq!(decoded, &messages()[..1]);
+    assert_eq!(decoder.buffered(), 6);
+}
+
+#[test]
+fn invalid_frame_is_consumed() {
+    let mut buf = vec![0, 0, 0, 4, 100, 0, 0, 1]; // unknown frame type
+    buf.extend(encode(&messages()[1..2]));
+
+    let mut decoder = FrameDecoder::new();
+    assert!(decoder.decode(&buf).is_err());
+    assert_eq!(decoder.decode(&[]).unwrap(), Some(messages().remove(1)));
+    assert_eq!(decoder.decode(&[]).unwrap(), None);
+}
+
+#[test]
+fn invalid_frame_size() {
+    let mut decoder = FrameDecoder::new();
+    assert!(decoder.decode(&[0, 0, 0, 3, 65, 0, 0]).is_err());
+}
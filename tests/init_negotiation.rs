@@ -0,0 +1,173 @@
+extern crate mux;
+
+use mux::*;
+use mux::client::Session;
+use mux::init::*;
+use mux::server::{Server, Service};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+// a service class, agreed on only if both sides advertise the same
+#[derive(Debug, PartialEq)]
+struct ServiceClass(String);
+
+impl InitKey for ServiceClass {
+    const KEY: &'static str = "service-class";
+
+    fn encode(&self) -> Vec<u8> {
+        self.0.as_bytes().to_vec()
+    }
+
+    fn decode(value: &[u8]) -> init::Result<ServiceClass> {
+        String::from_utf8(value.to_vec())
+            .map(ServiceClass)
+            .map_err(|_| InitError::InvalidValue(Self::KEY.to_string()))
+    }
+
+    fn negotiate(client: ServiceClass, server: ServiceClass) -> Option<ServiceClass> {
+        if client == server { Some(client) } else { None }
+    }
+}
+
+fn options(tls: Tls, max_frame_size: Option<u32>) -> InitOptions {
+    let mut options = InitOptions::new();
+    options.tls = tls;
+    options.max_frame_size = max_frame_size;
+    options
+}
+
+#[test]
+fn standard_headers() {
+    let mut options = options(Tls::Required, Some(0x10000));
+    options.compression = Compression {
+        level: CompressionLevel::Desired,
+        formats: vec!["lz4".to_string(), "gzip".to_string()],
+    };
+
+    let init = options.to_init(1);
+    assert_eq!(init.headers, vec![
+        (b"tls".to_vec(), b"required".to_vec()),
+        (b"mux-framer".to_vec(), vec![0, 1, 0, 0]),
+        (b"compression".to_vec(), b"desired:lz4,gzip".to_vec()),
+    ]);
+    assert_eq!(InitOptions::from_init(&init).unwrap(), options);
+}
+
+#[test]
+fn empty_options_advertise_nothing() {
+    let init = InitOptions::new().to_init(VERSION);
+    assert!(init.headers.is_empty());
+
+    let params = Negotiator::new().negotiate(&init, &Init { version: 0, headers: Vec::new() }).unwrap();
+    assert_eq!(params.version, 0);
+    assert!(!params.tls);
+    assert_eq!(params.max_frame_size, None);
+    assert_eq!(params.compression, None);
+}
+
+#[test]
+fn invalid_values() {
+    for &(key, value) in &[("tls", &b"maybe"[..]), ("mux-framer", &[0, 1][..]), ("compression", &b"lots"[..])] {
+        let init = Init { version: 1, headers: vec![(key.as_bytes().to_vec(), value.to_vec())] };
+        assert_eq!(InitOptions::from_init(&init), Err(InitError::InvalidValue(key.to_string())));
+    }
+}
+
+#[test]
+fn negotiates_tls() {
+    let negotiate = |client, server| {
+        Negotiator::new()
+            .negotiate(&options(client, None).to_init(1), &options(server, None).to_init(1))
+            .map(|params| params.tls)
+    };
+
+    assert_eq!(negotiate(Tls::Off, Tls::Desired), Ok(false));
+    assert_eq!(negotiate(Tls::Desired, Tls::Desired), Ok(true));
+    assert_eq!(negotiate(Tls::Desired, Tls::Required), Ok(true));
+    assert_eq!(negotiate(Tls::Required, Tls::Off), Err(InitError::TlsRequired));
+    assert_eq!(negotiate(Tls::Off, Tls::Required), Err(InitError::TlsRequired));
+}
+
+#[test]
+fn negotiates_frame_size_and_compression() {
+    let mut client = options(Tls::Off, Some(4096));
+    client.compression = Compression {
+        level: CompressionLevel::Desired,
+        formats: vec!["zstd".to_string(), "gzip".to_string()],
+    };
+    let mut server = options(Tls::Off, Some(1024));
+    server.compression = Compression {
+        level: CompressionLevel::Accepted,
+        formats: vec!["gzip".to_string(), "zstd".to_string()],
+    };
+
+    let negotiator = Negotiator::new();
+    let params = negotiator.negotiate(&client.to_init(1), &server.to_init(1)).unwrap();
+    assert_eq!(params.max_frame_size, Some(1024));
+    assert_eq!(params.compression, Some("zstd".to_string()));
+
+    // fragmentation and compression take both sides
+    client.compression.level = CompressionLevel::Accepted;
+    let params = negotiator.negotiate(&client.to_init(1), &options(Tls::Off, None).to_init(1)).unwrap();
+    assert_eq!(params.max_frame_size, None);
+    let params = negotiator.negotiate(&client.to_init(1), &server.to_init(1)).unwrap();
+    assert_eq!(params.compression, None);
+}
+
+#[test]
+fn custom_keys() {
+    let mut client = InitOptions::new();
+    client.set(&ServiceClass("gold".to_string()));
+    client.set(&ServiceClass("silver".to_string()));
+    assert_eq!(client.get::<ServiceClass>(), Ok(Some(ServiceClass("silver".to_string()))));
+    assert_eq!(client.custom.len(), 1);
+
+    let mut server = InitOptions::new();
+    server.set(&ServiceClass("silver".to_string()));
+
+    // unregistered keys are left alone
+    let tinit = client.to_init(1);
+    let rinit = server.to_init(1);
+    let params = Negotiator::new().negotiate(&tinit, &rinit).unwrap();
+    assert_eq!(params.get::<ServiceClass>(), None);
+
+    let mut negotiator = Negotiator::new();
+    negotiator.register::<ServiceClass>();
+    let params = negotiator.negotiate(&tinit, &rinit).unwrap();
+    assert_eq!(params.get::<ServiceClass>(), Some(&ServiceClass("silver".to_string())));
+
+    server.set(&ServiceClass("gold".to_string()));
+    let params = negotiator.negotiate(&tinit, &server.to_init(1)).unwrap();
+    assert_eq!(params.get::<ServiceClass>(), None);
+}
+
+#[test]
+fn negotiates_sessions() {
+    struct Echo;
+
+    impl Service for Echo {
+        fn dispatch(&self, tdispatch: Tdispatch) -> Rdispatch {
+            Rdispatch { contexts: Vec::new(), msg: Rmsg::Ok(tdispatch.body) }
+        }
+    }
+
+    let mut server_options = options(Tls::Off, Some(4096));
+    server_options.set(&ServiceClass("gold".to_string()));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || Server::new(Echo).with_init_options(server_options).listen(listener));
+
+    let mut client_options = options(Tls::Desired, Some(1024));
+    client_options.set(&ServiceClass("gold".to_string()));
+    let stream = TcpStream::connect(addr).unwrap();
+    let session = Session::with_init_options(stream.try_clone().unwrap(), stream, &client_options)
+        .unwrap();
+
+    let mut negotiator = Negotiator::new();
+    negotiator.register::<ServiceClass>();
+    let params = session.negotiate(&negotiator).unwrap();
+    assert_eq!(params.version, VERSION);
+    assert!(!params.tls);
+    assert_eq!(params.max_frame_size, Some(1024));
+    assert_eq!(params.get::<ServiceClass>(), Some(&ServiceClass("gold".to_string())));
+}
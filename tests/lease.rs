@@ -0,0 +1,142 @@
+extern crate mux;
+
+mod common;
+
+use common::write;
+use mux::*;
+use mux::client::Session;
+use mux::lease::{LeaseTracker, LoadLeasePolicy};
+use mux::server::{Server, Service};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const LEASE: Duration = Duration::from_secs(30);
+
+// echoes bodies back, sleeping on a "/slow" destination
+struct Echo;
+
+impl Service for Echo {
+    fn dispatch(&self, tdispatch: Tdispatch) -> Rdispatch {
+        if tdispatch.dest == "/slow" {
+            thread::sleep(Duration::from_millis(100));
+        }
+        Rdispatch {
+            contexts: tdispatch.contexts,
+            msg: Rmsg::Ok(tdispatch.body),
+        }
+    }
+}
+
+// a server granting leases to connections with no request in progress
+fn server() -> TcpStream {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Server::new(Echo).with_lease_policy(LoadLeasePolicy::new(1, LEASE));
+    thread::spawn(move || server.listen(listener));
+    TcpStream::connect(addr).unwrap()
+}
+
+#[test]
+fn tracker_expiry() {
+    let start = Instant::now();
+    let mut tracker = LeaseTracker::new();
+    assert!(tracker.latest().is_none());
+    assert!(!tracker.is_expired_at(start));
+
+    tracker.observe_at(&Tlease { duration: Duration::from_secs(1) }, start);
+    assert_eq!(tracker.remaining_at(start), Some(Duration::from_secs(1)));
+    assert!(!tracker.is_expired_at(start + Duration::from_millis(999)));
+    assert!(tracker.is_expired_at(start + Duration::from_secs(2)));
+    assert_eq!(tracker.remaining_at(start + Duration::from_secs(2)), Some(Duration::ZERO));
+
+    tracker.observe_at(&Tlease::infinite(), start + Duration::from_secs(2));
+    assert_eq!(tracker.remaining_at(start + Duration::from_secs(3)), None);
+    assert!(!tracker.is_expired_at(start + Duration::from_secs(3)));
+}
+
+#[test]
+fn infinite_leases() {
+    assert_eq!(Tlease::infinite().expires_in(), None);
+    assert_eq!(Tlease { duration: LEASE }.expires_in(), Some(LEASE));
+
+    let mut buf = Vec::new();
+    codec::encode_tlease(&mut buf, &Tlease::infinite()).unwrap();
+    assert_eq!(buf, [0, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+    assert_eq!(codec::decode_tlease(&buf[..]).unwrap(), Tlease::infinite());
+}
+
+#[test]
+fn unknown_units_decode_as_unknown_frames() {
+    // a Tlease of 10 ticks in unit 1
+    let buf = [0, 0, 0, 13, 67, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 10];
+    let unknown = MessageFrame::Unknown { tpe: types::TLEASE, body: buf[8..].to_vec() };
+    assert_eq!(codec::read_message(&mut &buf[..]).unwrap().frame, unknown);
+    assert_eq!(codec::decode_message_ref(&buf[4..]).unwrap().into_owned().frame, unknown);
+
+    match codec::decode_tlease(&buf[8..]) {
+        Err(codec::Error::UnknownLeaseUnit(1)) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    // and are written back as they were read
+    let mut written = Vec::new();
+    codec::write_message(&mut written, &Message { tag: Tag::new(true, 0), frame: unknown }).unwrap();
+    assert_eq!(written, buf);
+}
+
+#[test]
+fn encodes_long_leases_as_infinite() {
+    for lease in &[Duration::from_secs(u64::MAX), Duration::MAX] {
+        let mut buf = Vec::new();
+        codec::encode_tlease(&mut buf, &Tlease { duration: *lease }).unwrap();
+        assert_eq!(codec::decode_tlease(&buf[..]).unwrap(), Tlease::infinite());
+    }
+}
+
+#[test]
+fn server_grants_leases_by_load() {
+    let mut stream = server();
+    let mut reader = stream.try_clone().unwrap();
+
+    write(&mut stream, Tag::new(true, 1), MessageFrame::Tinit(Init {
+        version: VERSION,
+        headers: Vec::new(),
+    }));
+    assert_eq!(codec::read_message(&mut reader).unwrap().frame.frame_id(), types::RINIT);
+    let granted = Message { tag: Tag::new(true, 0), frame: MessageFrame::Tlease(Tlease { duration: LEASE }) };
+    assert_eq!(codec::read_message(&mut reader).unwrap(), granted);
+
+    let tdispatch = Tdispatch::new("/slow".to_string(), b"slow".to_vec());
+    write(&mut stream, Tag::new(true, 2), MessageFrame::Tdispatch(tdispatch));
+
+    let revoked = Message {
+        tag: Tag::new(true, 0),
+        frame: MessageFrame::Tlease(Tlease { duration: Duration::ZERO }),
+    };
+    assert_eq!(codec::read_message(&mut reader).unwrap(), revoked);
+    let rdispatch = codec::read_message(&mut reader).unwrap();
+    assert_eq!(rdispatch.tag, Tag::new(true, 2));
+    assert_eq!(codec::read_message(&mut reader).unwrap(), granted);
+
+    // an unchanged lease isn't granted again
+    write(&mut stream, Tag::new(true, 3), MessageFrame::Tping);
+    assert_eq!(codec::read_message(&mut reader).unwrap().frame, MessageFrame::Rping);
+}
+
+#[test]
+fn session_tracks_lease() {
+    let stream = server();
+    let session = Session::new(stream.try_clone().unwrap(), stream).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while session.lease().latest().is_none() {
+        assert!(Instant::now() < deadline, "no lease received");
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let lease = session.lease();
+    assert_eq!(lease.latest(), Some(&Tlease { duration: LEASE }));
+    assert!(!lease.is_expired());
+    assert!(lease.remaining().unwrap() <= LEASE);
+}
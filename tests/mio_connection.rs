@@ -0,0 +1,142 @@
+#![cfg(feature = "mio")]
+
+extern crate mux;
+
+use mio::net::{TcpListener, TcpStream};
+use mio::{Events, Interest, Poll, Token};
+use mux::*;
+use mux::connection::Connection;
+use std::io::Write;
+use std::time::Duration;
+
+const LISTENER: Token = Token(0);
+const CLIENT: Token = Token(1);
+const SERVER: Token = Token(2);
+
+fn respond(frame: MessageFrame) -> MessageFrame {
+    match frame {
+        MessageFrame::Tping => MessageFrame::Rping,
+        MessageFrame::Tdispatch(tdispatch) => MessageFrame::Rdispatch(Rdispatch {
+            contexts: Vec::new(),
+            msg: Rmsg::Ok(tdispatch.body),
+        }),
+        other => panic!("Unexpected frame: {:?}", other),
+    }
+}
+
+#[test]
+fn multiplexed_connections() {
+    let mut poll = Poll::new().unwrap();
+    let mut listener = TcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    poll.registry().register(&mut listener, LISTENER, Interest::READABLE).unwrap();
+
+    let mut client = Connection::new(TcpStream::connect(listener.local_addr().unwrap()).unwrap());
+    let mut server = None;
+
+    // large enough to need several writable events
+    let large = vec![7; 4 * 1024 * 1024];
+    for id in 1..=20 {
+        client.send(&Message { tag: Tag::new(true, id), frame: MessageFrame::Tping }).unwrap();
+    }
+    let tdispatch = Tdispatch::new("/foo".to_string(), large.clone());
+    client.send(&Message { tag: Tag::new(true, 21), frame: MessageFrame::Tdispatch(tdispatch) })
+        .unwrap();
+    client.register(poll.registry(), CLIENT).unwrap();
+
+    let mut responses = Vec::new();
+    let mut events = Events::with_capacity(128);
+    while responses.len() < 21 {
+        poll.poll(&mut events, Some(Duration::from_secs(5))).unwrap();
+        assert!(!events.is_empty(), "event loop stalled");
+
+        for event in &events {
+            match event.token() {
+                LISTENER => {
+                    let (stream, _) = listener.accept().unwrap();
+                    let mut conn = Connection::new(stream);
+                    conn.register(poll.registry(), SERVER).unwrap();
+                    server = Some(conn);
+                }
+                CLIENT => {
+                    if event.is_writable() {
+                        client.on_writable().unwrap();
+                    }
+                    if event.is_readable() {
+                        responses.extend(client.on_readable().into_iter().map(Result::unwrap));
+                    }
+                    client.reregister(poll.registry(), CLIENT).unwrap();
+                }
+                SERVER => {
+                    let conn = server.as_mut().unwrap();
+                    if event.is_readable() {
+                        for msg in conn.on_readable() {
+                            let msg = msg.unwrap();
+                            let frame = respond(msg.frame);
+                            conn.send(&Message { tag: msg.tag, frame }).unwrap();
+                        }
+                    }
+                    if conn.wants_write() {
+                        conn.on_writable().unwrap();
+                    }
+                    conn.reregister(poll.registry(), SERVER).unwrap();
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    for (i, msg) in responses.iter().take(20).enumerate() {
+        assert_eq!(msg, &Message { tag: Tag::new(true, i as u32 + 1), frame: MessageFrame::Rping });
+    }
+    match responses[20].frame {
+        MessageFrame::Rdispatch(ref r) => assert_eq!(r.msg, Rmsg::Ok(large)),
+        ref other => panic!("Unexpected frame: {:?}", other),
+    }
+    assert!(!client.wants_write());
+}
+
+#[test]
+fn detects_closed_peer() {
+    let mut poll = Poll::new().unwrap();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut conn = Connection::new(TcpStream::connect(listener.local_addr().unwrap()).unwrap());
+    conn.register(poll.registry(), CLIENT).unwrap();
+
+    let (peer, _) = listener.accept().unwrap();
+    drop(peer);
+
+    let mut events = Events::with_capacity(8);
+    while !conn.is_closed() {
+        poll.poll(&mut events, Some(Duration::from_secs(5))).unwrap();
+        assert!(!events.is_empty(), "event loop stalled");
+        assert!(conn.on_readable().is_empty());
+    }
+}
+
+#[test]
+fn reads_past_invalid_frames() {
+    let mut poll = Poll::new().unwrap();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut conn = Connection::new(TcpStream::connect(listener.local_addr().unwrap()).unwrap());
+    conn.register(poll.registry(), CLIENT).unwrap();
+
+    // a ping, a frame of unknown type and another ping
+    let (mut peer, _) = listener.accept().unwrap();
+    peer.write_all(&[0, 0, 0, 4, 65, 0, 0, 1, 0, 0, 0, 4, 80, 0, 0, 2, 0, 0, 0, 4, 65, 0, 0, 3])
+        .unwrap();
+
+    let mut results = Vec::new();
+    let mut events = Events::with_capacity(8);
+    while results.len() < 3 {
+        poll.poll(&mut events, Some(Duration::from_secs(5))).unwrap();
+        assert!(!events.is_empty(), "event loop stalled");
+        results.extend(conn.on_readable());
+    }
+
+    assert_eq!(results[0].as_ref().unwrap().tag, Tag::new(true, 1));
+    match results[1] {
+        Err(codec::Error::UnknownFrameType(80)) => (),
+        ref other => panic!("Unexpected result: {:?}", other),
+    }
+    assert_eq!(results[2].as_ref().unwrap().tag, Tag::new(true, 3));
+}
@@ -0,0 +1,159 @@
+extern crate mux;
+extern crate serde_json;
+
+mod common;
+
+use common::write;
+use mux::*;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn mux_dump(args: &[&str], capture: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mux-dump"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(capture).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn capture() -> Vec<u8> {
+    let mut tdispatch = Tdispatch::new("/foo".to_string(), b"hello".to_vec());
+    tdispatch.contexts.push((b"key".to_vec(), vec![0, 1]));
+    tdispatch.dtab = Dtab::parse("/s=>/a").unwrap();
+
+    let mut capture = Vec::new();
+    write(&mut capture, Tag::new(true, 1), MessageFrame::Tping);
+    write(&mut capture, Tag::new(true, 2), MessageFrame::Tdispatch(tdispatch));
+    capture
+}
+
+#[test]
+fn dumps_messages() {
+    let output = mux_dump(&[], &capture());
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "\
+@0 Tping tag=1 end=true size=0
+@8 Tdispatch tag=2 end=true size=32
+  contexts:
+    key => 2 bytes 0001 |..|
+  dest: /foo
+  dtab: /s=>/a
+  body: 5 bytes 68656c6c6f |hello|
+");
+}
+
+#[test]
+fn dumps_json() {
+    let output = mux_dump(&["--json"], &capture());
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<serde_json::Value> = stdout.lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["type"], "Tping");
+    assert_eq!(lines[1]["offset"], 8);
+    assert_eq!(lines[1]["type"], "Tdispatch");
+    assert_eq!(lines[1]["tag"], 2);
+    assert_eq!(lines[1]["end"], true);
+    assert_eq!(lines[1]["contexts"][0][0], "key");
+    assert_eq!(lines[1]["contexts"][0][1]["hex"], "0001");
+    assert_eq!(lines[1]["dest"], "/foo");
+    assert_eq!(lines[1]["dtab"], "/s=>/a");
+    assert_eq!(lines[1]["body"]["len"], 5);
+    assert_eq!(lines[1]["body"]["utf8"], "hello");
+}
+
+#[test]
+fn dumps_unknown_frames() {
+    let mut capture = vec![0, 0, 0, 6, 100, 0, 0, 3, 0xab, 0xcd];
+    capture.extend(self::capture());
+
+    let output = mux_dump(&[], &capture);
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("\
+@0 Unknown tag=3 end=true size=2
+  type_id: 100
+  body: 2 bytes abcd |\u{fffd}\u{fffd}|
+@10 Tping tag=1"));
+}
+
+#[test]
+fn dumps_fragments() {
+    let msg = Message {
+        tag: Tag::new(true, 2),
+        frame: MessageFrame::Tdispatch(Tdispatch::new("/foo".to_string(), vec![0; 40])),
+    };
+    let mut capture = Vec::new();
+    for fragment in codec::Fragmenter::new(32).fragment(&msg).unwrap() {
+        codec::write_fragment(&mut capture, &fragment).unwrap();
+    }
+
+    let output = mux_dump(&["--json"], &capture);
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<serde_json::Value> = stdout.lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["type"], "Tdispatch");
+    assert_eq!(lines[0]["end"], false);
+    assert_eq!(lines[0]["fragment"]["len"], 32);
+    assert_eq!(lines[1]["offset"], 40);
+    assert_eq!(lines[1]["end"], true);
+    assert_eq!(lines[1]["dest"], "/foo");
+    assert_eq!(lines[1]["body"]["len"], 40);
+}
+
+// a Tdispatch with a truncated destination
+const MALFORMED: [u8; 11] = [0, 0, 0, 7, 2, 0, 0, 4, 0, 0, 9];
+
+#[test]
+fn stops_at_decode_errors() {
+    let mut capture = MALFORMED.to_vec();
+    capture.extend(self::capture());
+
+    let output = mux_dump(&[], &capture);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("decode error at offset 0"));
+}
+
+#[test]
+fn resyncs_on_decode_errors() {
+    let mut capture = MALFORMED.to_vec();
+    capture.extend(self::capture());
+
+    let output = mux_dump(&["--resync"], &capture);
+    assert_eq!(output.status.code(), Some(1));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("@11 Tping tag=1"));
+    assert!(stdout.contains("@19 Tdispatch tag=2"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("skipped 11 bytes to offset 11"));
+}
+
+#[test]
+fn resyncs_past_garbage_quickly() {
+    // bytes that never form a plausible length prefix
+    let mut capture = vec![0xff; 1 << 20];
+    capture.extend(self::capture());
+
+    let output = mux_dump(&["--resync"], &capture);
+    assert_eq!(output.status.code(), Some(1));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with(&format!("@{} Tping tag=1", 1 << 20)));
+}
@@ -3,7 +3,7 @@ extern crate mux;
 use mux::*;
 use std::io;
 
-static TDISPATCH_BUF: &'static [u8] = &[
+static TDISPATCH_BUF: &[u8] = &[
     0, 0, 0, 65, // frame size
 
     2, // TDISPATCH
@@ -30,14 +30,14 @@ static TDISPATCH_BUF: &'static [u8] = &[
 
     // dst
     0, 4, // length
-    '/' as u8, 66, 65, 68, // "/BAD"
+    b'/', 66, 65, 68, // "/BAD"
 
     // dtab: /BAD => /DAD
     0, 1, // length
     0, 4, // source length
-    '/' as u8, 66, 65, 68, // "/BAD"
+    b'/', 66, 65, 68, // "/BAD"
     0, 4, // tree length
-    '/' as u8, 68, 65, 68, // "/DAD"
+    b'/', 68, 65, 68, // "/DAD"
 
     // data: [0 .. 20)
     0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19];
@@ -53,7 +53,7 @@ fn roundtrip_frame(msg: MessageFrame) {
     };
 
     let mut w = new_write();
-    let _ = codec::write_message(&mut w, &msg).unwrap();
+    codec::write_message(&mut w, &msg).unwrap();
     let w = w.into_inner();
 
     let mut two = Vec::new();
@@ -87,7 +87,7 @@ fn encode_rdispatch() {
         msg: Rmsg::Ok(b"nope".to_vec()),
     });
     let tag = Tag::new(true, (1 << 16) | (2 << 8) | 3);
-    let msg = Message { tag: tag, frame: msg_frame };
+    let msg = Message { tag, frame: msg_frame };
 
     let expected = vec![
         0x00, 0x00, 0x00, 0x0b, // frame
@@ -145,7 +145,7 @@ fn roundtrip_tdispatch() {
     roundtrip_frame(MessageFrame::Tdispatch(Tdispatch {
         contexts: vec![(vec![1, 2, 3], vec![4, 5, 6])],
         dest: "foo".to_string(),
-        dtab: Dtab::from_entries(vec![Dentry::new("foo".to_string(), "bar".to_string())]),
+        dtab: Dtab::from_entries(vec![Dentry::read("/foo=>/bar").unwrap()]),
         body: vec![1, 2, 3],
     }));
 
@@ -222,6 +222,16 @@ fn roundtrip_rping() {
     roundtrip_frame(MessageFrame::Rping);
 }
 
+#[test]
+fn roundtrip_tdiscarded() {
+    roundtrip_frame(MessageFrame::Tdiscarded(Tdiscarded { id: 5, msg: "Bye".to_owned() }));
+}
+
+#[test]
+fn roundtrip_rdiscarded() {
+    roundtrip_frame(MessageFrame::Rdiscarded);
+}
+
 #[test]
 fn roundtrip_rerr() {
     roundtrip_frame(MessageFrame::Rerr(Rerr{ msg: "Foo!".to_owned() }));
@@ -239,7 +249,7 @@ fn roundtrip_tlease() {
 fn roundtrip_dtab() {
     fn roundtrip_frame(table: &Dtab) {
         let mut w = new_write();
-        let _ = codec::encode_dtab(&mut w, table).unwrap();
+        codec::encode_dtab(&mut w, table).unwrap();
         let mut w = io::Cursor::new(w.into_inner());
         let decoded = codec::decode_dtab(&mut w).unwrap();
 
@@ -249,9 +259,9 @@ fn roundtrip_dtab() {
     let mut tab = Dtab::new();
 
     roundtrip_frame(&tab);
-    tab.add_entry("a".to_string(), "b".to_string());
+    tab.add_entry(Prefix::read("/a").unwrap(), NameTree::read("/b").unwrap());
     roundtrip_frame(&tab);
-    tab.add_entry("c".to_string(), "d".to_string());
+    tab.add_entry(Prefix::read("/c").unwrap(), NameTree::read("/d | /e").unwrap());
     roundtrip_frame(&tab);
 }
 
@@ -260,7 +270,7 @@ fn roundtrip_dtab() {
 fn roundtrip_context() {
     fn roundtrip_frame(ctx: &Contexts) {
         let mut w = new_write();
-        let _ = codec::encode_contexts(&mut w, ctx).unwrap();
+        codec::encode_contexts(&mut w, ctx).unwrap();
         let mut w = io::Cursor::new(w.into_inner());
         let decoded = codec::decode_contexts(&mut w).unwrap();
 
@@ -278,7 +288,7 @@ fn roundtrip_context() {
 fn roundtrip_tag() {
     fn roundtrip_frame(tag: &Tag) {
         let mut w = new_write();
-        let _ = codec::encode_tag(&mut w, &tag).unwrap();
+        codec::encode_tag(&mut w, tag).unwrap();
         let mut w = io::Cursor::new(w.into_inner());
         let decoded = codec::decode_tag(&mut w).unwrap();
 
@@ -0,0 +1,82 @@
+#![cfg(feature = "serde")]
+
+extern crate mux;
+
+use mux::*;
+use serde_json::json;
+use std::time::Duration;
+
+fn roundtrip(frame: MessageFrame) {
+    let msg = Message { tag: Tag::new(true, 2), frame };
+    let json = serde_json::to_string(&msg).unwrap();
+    assert_eq!(serde_json::from_str::<Message>(&json).unwrap(), msg, "{}", json);
+}
+
+#[test]
+fn roundtrips_messages() {
+    let mut tdispatch = Tdispatch::new("/s/users".to_string(), b"tdispatch".to_vec());
+    tdispatch.contexts.push((b"key".to_vec(), vec![0, 255]));
+    tdispatch.dtab = Dtab::parse("/s=>/$/inet/localhost/8080").unwrap();
+
+    let frames = vec![
+        MessageFrame::Treq(Treq { headers: vec![(1, vec![2, 3])], body: b"treq".to_vec() }),
+        MessageFrame::Rreq(Rmsg::Ok(b"rreq".to_vec())),
+        MessageFrame::Rreq(Rmsg::Error("error".to_string())),
+        MessageFrame::Rreq(Rmsg::Nack("nack".to_string())),
+        MessageFrame::Tdispatch(tdispatch),
+        MessageFrame::Rdispatch(Rdispatch { contexts: vec![(b"k".to_vec(), Vec::new())], msg: Rmsg::Ok(Vec::new()) }),
+        MessageFrame::Tinit(Init { version: 1, headers: vec![(b"tls".to_vec(), b"off".to_vec())] }),
+        MessageFrame::Rinit(Init { version: 1, headers: Vec::new() }),
+        MessageFrame::Tdrain,
+        MessageFrame::Rdrain,
+        MessageFrame::Tping,
+        MessageFrame::Rping,
+        MessageFrame::Tdiscarded(Tdiscarded { id: 5, msg: "bye".to_string() }),
+        MessageFrame::Rdiscarded,
+        MessageFrame::Tlease(Tlease { duration: Duration::from_secs(10) }),
+        MessageFrame::Rerr(Rerr { msg: "rerr".to_string() }),
+        MessageFrame::Unknown { tpe: 100, body: b"unknown".to_vec() },
+    ];
+
+    for frame in frames {
+        roundtrip(frame);
+    }
+}
+
+#[test]
+fn representations() {
+    let mut tdispatch = Tdispatch::new("/s".to_string(), b"hello".to_vec());
+    tdispatch.contexts.push((b"key".to_vec(), b"value".to_vec()));
+    tdispatch.dtab = Dtab::parse("/s=>/t").unwrap();
+    let msg = Message { tag: Tag::new(false, 7), frame: MessageFrame::Tdispatch(tdispatch) };
+
+    assert_eq!(serde_json::to_value(&msg).unwrap(), json!({
+        "tag": { "end": false, "id": 7 },
+        "frame": {
+            "Tdispatch": {
+                "contexts": [["a2V5", "dmFsdWU="]],
+                "dest": "/s",
+                "dtab": { "entries": [{ "prefix": "/s", "dst": "/t" }] },
+                "body": "aGVsbG8=",
+            }
+        }
+    }));
+
+    let tlease = MessageFrame::Tlease(Tlease { duration: Duration::from_millis(1500) });
+    assert_eq!(serde_json::to_value(&tlease).unwrap(), json!({ "Tlease": { "duration": 1500 } }));
+
+    let treq = Treq { headers: vec![(1, vec![0xff])], body: Vec::new() };
+    assert_eq!(serde_json::to_value(&treq).unwrap(), json!({ "headers": [[1, "/w=="]], "body": "" }));
+}
+
+#[test]
+fn accepts_arrays_of_bytes() {
+    let rmsg: Rmsg = serde_json::from_value(json!({ "Ok": [104, 105] })).unwrap();
+    assert_eq!(rmsg, Rmsg::Ok(b"hi".to_vec()));
+
+    let init: Init = serde_json::from_value(json!({ "version": 1, "headers": [[[116], "b2Zm"]] })).unwrap();
+    assert_eq!(init.headers, vec![(b"t".to_vec(), b"off".to_vec())]);
+
+    assert!(serde_json::from_value::<Rmsg>(json!({ "Ok": "not base64!" })).is_err());
+    assert!(serde_json::from_value::<Rmsg>(json!({ "Ok": [256] })).is_err());
+}
@@ -0,0 +1,305 @@
+extern crate mux;
+
+use mux::*;
+use mux::client::Session;
+use mux::contexts::{self, Deadline};
+use mux::server::{Server, Service};
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// echoes bodies back, sleeping on the "/slow" and "/slower" destinations
+// and answering the seconds left until the deadline on "/remaining"
+struct Echo;
+
+impl Service for Echo {
+    fn dispatch(&self, tdispatch: Tdispatch) -> Rdispatch {
+        if tdispatch.dest == "/slow" {
+            thread::sleep(Duration::from_millis(200));
+        } else if tdispatch.dest == "/slower" {
+            thread::sleep(Duration::from_millis(400));
+        } else if tdispatch.dest == "/remaining" {
+            let remaining = tdispatch.deadline().unwrap().remaining();
+            let msg = Rmsg::Ok(remaining.as_secs().to_string().into_bytes());
+            return Rdispatch { contexts: Vec::new(), msg };
+        }
+        Rdispatch {
+            contexts: tdispatch.contexts,
+            msg: Rmsg::Ok(tdispatch.body),
+        }
+    }
+
+    fn request(&self, treq: Treq) -> Rmsg {
+        Rmsg::Ok(treq.body)
+    }
+}
+
+fn server() -> TcpStream {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || Server::new(Echo).listen(listener));
+    TcpStream::connect(addr).unwrap()
+}
+
+fn session() -> Session {
+    let stream = server();
+    Session::new(stream.try_clone().unwrap(), stream).unwrap()
+}
+
+fn roundtrip(stream: &mut TcpStream, msg: Message) -> Message {
+    let mut buf = Vec::new();
+    codec::write_message(&mut buf, &msg).unwrap();
+    stream.write_all(&buf).unwrap();
+    codec::read_message(stream).unwrap()
+}
+
+#[test]
+fn answers_control_messages() {
+    let mut stream = server();
+
+    let tinit = Init { version: 5, headers: Vec::new() };
+    let msg = roundtrip(&mut stream, Message { tag: Tag::new(true, 1), frame: MessageFrame::Tinit(tinit) });
+    assert_eq!(msg.tag, Tag::new(true, 1));
+    assert_eq!(msg.frame, MessageFrame::Rinit(Init { version: VERSION, headers: Vec::new() }));
+
+    let msg = roundtrip(&mut stream, Message { tag: Tag::new(true, 2), frame: MessageFrame::Tping });
+    assert_eq!(msg, Message { tag: Tag::new(true, 2), frame: MessageFrame::Rping });
+
+    let msg = roundtrip(&mut stream, Message { tag: Tag::new(true, 3), frame: MessageFrame::Tdrain });
+    assert_eq!(msg, Message { tag: Tag::new(true, 3), frame: MessageFrame::Rdrain });
+}
+
+#[test]
+fn acknowledges_discards() {
+    let mut stream = server();
+
+    let tdispatch = Tdispatch::new("/slow".to_string(), b"a".to_vec());
+    let mut buf = Vec::new();
+    codec::write_message(&mut buf, &Message { tag: Tag::new(true, 2), frame: MessageFrame::Tdispatch(tdispatch) }).unwrap();
+    stream.write_all(&buf).unwrap();
+
+    let tdiscarded = Tdiscarded { id: 2, msg: "timed out".to_string() };
+    let msg = roundtrip(&mut stream, Message { tag: Tag::new(true, 0), frame: MessageFrame::Tdiscarded(tdiscarded) });
+    assert_eq!(msg, Message { tag: Tag::new(true, 2), frame: MessageFrame::Rdiscarded });
+
+    // the response of the discarded dispatch is dropped
+    thread::sleep(Duration::from_millis(300));
+    let msg = roundtrip(&mut stream, Message { tag: Tag::new(true, 3), frame: MessageFrame::Tping });
+    assert_eq!(msg, Message { tag: Tag::new(true, 3), frame: MessageFrame::Rping });
+
+    // discards of requests already answered are ignored
+    let tdiscarded = Tdiscarded { id: 3, msg: "timed out".to_string() };
+    let mut buf = Vec::new();
+    codec::write_message(&mut buf, &Message { tag: Tag::new(true, 0), frame: MessageFrame::Tdiscarded(tdiscarded) }).unwrap();
+    stream.write_all(&buf).unwrap();
+    let msg = roundtrip(&mut stream, Message { tag: Tag::new(true, 4), frame: MessageFrame::Tping });
+    assert_eq!(msg, Message { tag: Tag::new(true, 4), frame: MessageFrame::Rping });
+}
+
+#[test]
+fn reused_discarded_tags() {
+    let mut stream = server();
+
+    let tdispatch = Tdispatch::new("/slow".to_string(), b"a".to_vec());
+    let mut buf = Vec::new();
+    codec::write_message(&mut buf, &Message { tag: Tag::new(true, 2), frame: MessageFrame::Tdispatch(tdispatch) }).unwrap();
+    stream.write_all(&buf).unwrap();
+
+    let tdiscarded = Tdiscarded { id: 2, msg: "timed out".to_string() };
+    let msg = roundtrip(&mut stream, Message { tag: Tag::new(true, 0), frame: MessageFrame::Tdiscarded(tdiscarded) });
+    assert_eq!(msg, Message { tag: Tag::new(true, 2), frame: MessageFrame::Rdiscarded });
+
+    // the discarded dispatch completes first, its response is still dropped
+    let tdispatch = Tdispatch::new("/slower".to_string(), b"b".to_vec());
+    let msg = roundtrip(&mut stream, Message { tag: Tag::new(true, 2), frame: MessageFrame::Tdispatch(tdispatch) });
+    let rdispatch = Rdispatch { contexts: Vec::new(), msg: Rmsg::Ok(b"b".to_vec()) };
+    assert_eq!(msg, Message { tag: Tag::new(true, 2), frame: MessageFrame::Rdispatch(rdispatch) });
+}
+
+#[test]
+fn rerr_for_malformed_frames() {
+    let mut stream = server();
+
+    // a Tdispatch with a truncated destination, then an unknown frame type
+    stream.write_all(&[0, 0, 0, 7, 2, 0, 0, 4, 0, 0, 9]).unwrap();
+    stream.write_all(&[0, 0, 0, 4, 80, 0, 0, 5]).unwrap();
+
+    for id in &[4, 5] {
+        let msg = codec::read_message(&mut stream).unwrap();
+        assert_eq!(msg.tag, Tag::new(true, *id));
+        match msg.frame {
+            MessageFrame::Rerr(_) => (),
+            other => panic!("Unexpected frame: {:?}", other),
+        }
+    }
+
+    // the session survives
+    let msg = roundtrip(&mut stream, Message { tag: Tag::new(true, 6), frame: MessageFrame::Tping });
+    assert_eq!(msg.frame, MessageFrame::Rping);
+}
+
+#[test]
+fn rerr_for_unreadable_frames() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        Server::new(Echo).serve_tcp(stream)
+    });
+
+    // a frame size too small for the type and tag
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(&[0, 0, 0, 2, 65, 0, 0, 1]).unwrap();
+
+    let msg = codec::read_message(&mut stream).unwrap();
+    assert_eq!(msg.tag, Tag::new(true, 0));
+    match msg.frame {
+        MessageFrame::Rerr(_) => (),
+        other => panic!("Unexpected frame: {:?}", other),
+    }
+
+    match handle.join().unwrap() {
+        Err(codec::Error::FrameTooSmall(2)) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn serves_client_session() {
+    let session = session();
+    assert_eq!(session.version(), VERSION);
+    session.ping().unwrap();
+
+    let rmsg = session.request(Treq { headers: Vec::new(), body: b"treq".to_vec() }).unwrap();
+    assert_eq!(rmsg, Rmsg::Ok(b"treq".to_vec()));
+
+    let mut tdispatch = Tdispatch::new("/foo".to_string(), b"hello".to_vec());
+    tdispatch.contexts.push((b"key".to_vec(), b"value".to_vec()));
+    let rdispatch = session.dispatch(tdispatch).unwrap();
+    assert_eq!(rdispatch.contexts, vec![(b"key".to_vec(), b"value".to_vec())]);
+    assert_eq!(rdispatch.msg, Rmsg::Ok(b"hello".to_vec()));
+}
+
+#[test]
+fn dispatches_concurrently() {
+    let session = Arc::new(session());
+
+    let slow = {
+        let session = session.clone();
+        thread::spawn(move || {
+            session.dispatch(Tdispatch::new("/slow".to_string(), b"slow".to_vec())).unwrap()
+        })
+    };
+
+    // answered while the slow dispatch is still in progress
+    thread::sleep(Duration::from_millis(20));
+    let fast = session.dispatch(Tdispatch::new("/fast".to_string(), b"fast".to_vec())).unwrap();
+    assert_eq!(fast.msg, Rmsg::Ok(b"fast".to_vec()));
+    assert!(!slow.is_finished());
+
+    assert_eq!(slow.join().unwrap().msg, Rmsg::Ok(b"slow".to_vec()));
+}
+
+#[test]
+fn closes_with_the_connection() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        Server::new(Echo).serve_tcp(stream)
+    });
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let msg = roundtrip(&mut stream, Message { tag: Tag::new(true, 1), frame: MessageFrame::Tping });
+    assert_eq!(msg.frame, MessageFrame::Rping);
+    drop(stream);
+
+    handle.join().unwrap().unwrap();
+}
+
+#[test]
+fn drains_client_sessions() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Server::new(Echo);
+    {
+        let server = server.clone();
+        thread::spawn(move || server.listen(listener));
+    }
+
+    let session = Arc::new(Session::connect(addr).unwrap());
+    session.ping().unwrap();
+    assert_eq!(server.connections(), 1);
+
+    let slow = {
+        let session = session.clone();
+        thread::spawn(move || {
+            session.dispatch(Tdispatch::new("/slow".to_string(), b"slow".to_vec())).unwrap()
+        })
+    };
+    thread::sleep(Duration::from_millis(20));
+
+    // the dispatch in progress is answered before the connection closes
+    assert!(server.drain_timeout(Duration::from_secs(5)));
+    assert!(server.is_draining());
+    assert_eq!(server.connections(), 0);
+    assert_eq!(slow.join().unwrap().msg, Rmsg::Ok(b"slow".to_vec()));
+
+    session.wait_drained();
+    assert!(session.is_draining());
+    assert!(session.ping().is_err());
+}
+
+#[test]
+fn drain_blocked_on_a_connection() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Server::new(Echo);
+    {
+        let server = server.clone();
+        thread::spawn(move || server.listen(listener));
+    }
+
+    // a client that never reads its responses, until the connection can't
+    // take another one
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let body = vec![0; 1 << 20];
+    for id in 1..33 {
+        let tdispatch = Tdispatch::new("/foo".to_string(), body.clone());
+        let mut buf = Vec::new();
+        codec::write_message(&mut buf, &Message { tag: Tag::new(true, id), frame: MessageFrame::Tdispatch(tdispatch) }).unwrap();
+        stream.write_all(&buf).unwrap();
+    }
+    thread::sleep(Duration::from_millis(200));
+
+    // the Tdrain is stuck behind the responses
+    {
+        let server = server.clone();
+        thread::spawn(move || server.drain_timeout(Duration::from_secs(5)));
+    }
+    thread::sleep(Duration::from_millis(50));
+
+    // the server still answers while the drain is stuck
+    let (tx, rx) = std::sync::mpsc::channel();
+    {
+        let server = server.clone();
+        thread::spawn(move || tx.send((server.is_draining(), server.connections())).unwrap());
+    }
+    assert_eq!(rx.recv_timeout(Duration::from_secs(1)).unwrap(), (true, 1));
+}
+
+#[test]
+fn enforces_deadlines() {
+    let session = session();
+
+    let mut tdispatch = Tdispatch::new("/remaining".to_string(), b"a".to_vec());
+    contexts::set(&mut tdispatch.contexts, &Deadline { timestamp: UNIX_EPOCH, deadline: SystemTime::now() });
+    let rdispatch = session.dispatch(tdispatch).unwrap();
+    assert_eq!(rdispatch.msg, Rmsg::Nack("Deadline exceeded".to_string()));
+
+    let tdispatch = Tdispatch::new("/remaining".to_string(), b"b".to_vec());
+    let rdispatch = session.dispatch_timeout(tdispatch, Duration::from_secs(60)).unwrap();
+    assert_eq!(rdispatch.msg, Rmsg::Ok(b"59".to_vec()));
+}
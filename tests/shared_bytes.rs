@@ -0,0 +1,132 @@
+#![cfg(feature = "bytes")]
+
+extern crate bytes;
+extern crate mux;
+
+use bytes::BytesMut;
+use mux::*;
+use std::time::Duration;
+
+fn frames() -> Vec<MessageFrame> {
+    vec![
+        MessageFrame::Treq(Treq {
+            headers: vec![(1, vec![4, 5, 6])],
+            body: vec![1, 2, 3],
+        }),
+        MessageFrame::Rreq(Rmsg::Ok(vec![1, 2, 3])),
+        MessageFrame::Tdispatch(Tdispatch {
+            contexts: vec![(vec![1, 2, 3], vec![4, 5, 6])],
+            dest: "/foo".to_string(),
+            dtab: Dtab::parse("/a=>/b").unwrap(),
+            body: vec![1, 2, 3],
+        }),
+        MessageFrame::Rdispatch(Rdispatch {
+            contexts: vec![(vec![1, 2, 3], Vec::new())],
+            msg: Rmsg::Ok(Vec::new()),
+        }),
+        MessageFrame::Tinit(Init {
+            version: 12,
+            headers: vec![(vec![1, 2, 3], vec![4, 5, 6, 7])],
+        }),
+        MessageFrame::Tping,
+        MessageFrame::Tdiscarded(Tdiscarded { id: 5, msg: "Bye".to_owned() }),
+        MessageFrame::Tlease(Tlease { duration: Duration::from_millis(1500) }),
+        MessageFrame::Rerr(Rerr { msg: "Foo!".to_owned() }),
+    ]
+}
+
+fn messages() -> Vec<Message> {
+    frames().into_iter().map(|frame| Message { tag: Tag::new(true, 2), frame }).collect()
+}
+
+#[test]
+fn roundtrip_split() {
+    let mut buf = BytesMut::new();
+    for msg in &messages() {
+        codec::put_message(&mut buf, msg).unwrap();
+    }
+
+    let mut decoded = Vec::new();
+    while let Some(msg) = codec::split_message(&mut buf).unwrap() {
+        decoded.push(msg);
+    }
+
+    assert_eq!(decoded, messages());
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn split_partial_frames() {
+    let mut encoded = BytesMut::new();
+    for msg in &messages() {
+        codec::put_message(&mut encoded, msg).unwrap();
+    }
+
+    let mut buf = BytesMut::new();
+    let mut decoded = Vec::new();
+    for b in encoded.iter() {
+        buf.extend_from_slice(&[*b]);
+        while let Some(msg) = codec::split_message(&mut buf).unwrap() {
+            decoded.push(msg);
+        }
+    }
+
+    assert_eq!(decoded, messages());
+}
+
+#[test]
+fn split_frames_share_buffer() {
+    let mut buf = BytesMut::new();
+    codec::put_message(&mut buf, &messages().remove(2)).unwrap();
+    codec::put_message(&mut buf, &messages().remove(5)).unwrap();
+    let range = buf.as_ptr() as usize..buf.as_ptr() as usize + buf.len();
+
+    let frame = codec::split_frame(&mut buf).unwrap().unwrap();
+    assert!(range.contains(&(frame.as_ptr() as usize)));
+    match codec::decode_message_ref(&frame).unwrap().frame {
+        MessageFrameRef::Tdispatch(t) => {
+            let body = frame.slice_ref(t.body);
+            assert!(range.contains(&(body.as_ptr() as usize)));
+            assert_eq!(&body[..], &[1, 2, 3]);
+        }
+        other => panic!("Unexpected frame: {:?}", other),
+    }
+
+    // the frame forwarded as is decodes to the same message
+    let mut forwarded = (frame.len() as i32).to_be_bytes().to_vec();
+    forwarded.extend_from_slice(&frame);
+    assert_eq!(codec::read_message(&mut &forwarded[..]).unwrap(), messages().remove(2));
+
+    let frame = codec::split_frame(&mut buf).unwrap().unwrap();
+    assert_eq!(codec::decode_message_ref(&frame).unwrap().into_owned(), messages().remove(5));
+    assert!(codec::split_frame(&mut buf).unwrap().is_none());
+}
+
+#[test]
+fn split_frame_limits() {
+    let config = codec::DecoderConfig { max_frame_size: 4, ..codec::DecoderConfig::default() };
+    let mut buf = BytesMut::from(&[0, 0, 0, 5][..]);
+    match codec::split_frame_with(&mut buf, &config) {
+        Err(codec::Error::FrameTooLarge { size: 5, max: 4 }) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    let mut buf = BytesMut::from(&[0, 0, 0, 3, 0, 0, 0][..]);
+    match codec::split_frame(&mut buf) {
+        Err(codec::Error::FrameTooSmall(3)) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn shared_matches_read_message() {
+    let mut buf = BytesMut::new();
+    for msg in &messages() {
+        codec::put_message(&mut buf, msg).unwrap();
+    }
+
+    let mut r = std::io::Cursor::new(buf.to_vec());
+    for msg in messages() {
+        assert_eq!(codec::read_message(&mut r).unwrap(), msg);
+    }
+}
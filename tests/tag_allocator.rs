@@ -0,0 +1,72 @@
+extern crate mux;
+
+use mux::*;
+
+#[test]
+fn try_new() {
+    assert_eq!(Tag::try_new(true, 0), Ok(Tag::new(true, 0)));
+    assert_eq!(Tag::try_new(false, MAX_TAG), Ok(Tag::new(false, MAX_TAG)));
+    assert_eq!(Tag::try_new(true, MAX_TAG + 1), Err(TagError::OutOfRange(MAX_TAG + 1)));
+}
+
+#[test]
+fn allocates_from_one() {
+    let mut tags = TagAllocator::new();
+    let ids: Vec<u32> = (0..5).map(|_| tags.allocate().unwrap()).collect();
+    assert_eq!(ids, vec![1, 2, 3, 4, 5]);
+    assert_eq!(tags.outstanding(), 5);
+    assert!(tags.is_outstanding(3));
+    assert!(!tags.is_outstanding(0));
+}
+
+#[test]
+fn reuses_released_ids() {
+    let mut tags = TagAllocator::new();
+    for _ in 0..3 {
+        tags.allocate().unwrap();
+    }
+
+    tags.release(2).unwrap();
+    assert!(!tags.is_outstanding(2));
+    assert_eq!(tags.allocate(), Ok(2));
+    assert_eq!(tags.allocate(), Ok(4));
+}
+
+#[test]
+fn exhaustion() {
+    let mut tags = TagAllocator::with_max_id(3);
+    for id in 1..=3 {
+        assert_eq!(tags.allocate(), Ok(id));
+    }
+    assert_eq!(tags.allocate(), Err(TagError::Exhausted));
+
+    tags.release(1).unwrap();
+    assert_eq!(tags.allocate(), Ok(1));
+    assert_eq!(tags.allocate(), Err(TagError::Exhausted));
+
+    tags.clear();
+    assert_eq!(tags.outstanding(), 0);
+    assert!(tags.allocate().is_ok());
+}
+
+#[test]
+fn duplicate_and_unexpected_responses() {
+    let mut tags = TagAllocator::new();
+    let id = tags.allocate().unwrap();
+
+    assert_eq!(tags.complete(&Tag::new(true, id)), Ok(true));
+    assert_eq!(tags.complete(&Tag::new(true, id)), Err(TagError::NotOutstanding(id)));
+    assert_eq!(tags.release(42), Err(TagError::NotOutstanding(42)));
+}
+
+#[test]
+fn end_bit_keeps_fragmented_streams_outstanding() {
+    let mut tags = TagAllocator::new();
+    let id = tags.allocate().unwrap();
+
+    assert_eq!(tags.complete(&Tag::new(false, id)), Ok(false));
+    assert_eq!(tags.complete(&Tag::new(false, id)), Ok(false));
+    assert!(tags.is_outstanding(id));
+    assert_eq!(tags.complete(&Tag::new(true, id)), Ok(true));
+    assert_eq!(tags.complete(&Tag::new(false, id)), Err(TagError::NotOutstanding(id)));
+}
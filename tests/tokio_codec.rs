@@ -0,0 +1,127 @@
+#![cfg(feature = "tokio")]
+
+extern crate mux;
+
+use futures::{SinkExt, StreamExt};
+use mux::*;
+use mux::codec::{DecoderConfig, MuxCodec};
+use std::io;
+use tokio::io::AsyncWriteExt;
+use tokio_util::codec::{Framed, FramedRead};
+
+fn messages() -> Vec<Message> {
+    let mut tdispatch = Tdispatch::new("/foo".to_string(), vec![1, 2, 3]);
+    tdispatch.contexts.push((b"key".to_vec(), b"value".to_vec()));
+    vec![
+        Message { tag: Tag::new(true, 1), frame: MessageFrame::Tdispatch(tdispatch) },
+        Message { tag: Tag::new(true, 2), frame: MessageFrame::Tping },
+        Message { tag: Tag::new(false, 3), frame: MessageFrame::Rreq(Rmsg::Ok(vec![7; 300])) },
+    ]
+}
+
+#[tokio::test]
+async fn framed_roundtrip() {
+    let (a, b) = tokio::io::duplex(64);
+    let mut client = Framed::new(a, MuxCodec::new());
+    let mut server = Framed::new(b, MuxCodec::new());
+
+    let send = async {
+        for msg in messages() {
+            client.send(msg).await.unwrap();
+        }
+    };
+    let recv = async {
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            received.push(server.next().await.unwrap().unwrap());
+        }
+        received
+    };
+
+    let (_, received) = tokio::join!(send, recv);
+    assert_eq!(received, messages());
+}
+
+#[tokio::test]
+async fn byte_at_a_time() {
+    let mut buf = Vec::new();
+    for msg in messages() {
+        codec::write_message(&mut buf, &msg).unwrap();
+    }
+
+    let (mut a, b) = tokio::io::duplex(1);
+    let write = async move {
+        for byte in buf {
+            a.write_all(&[byte]).await.unwrap();
+        }
+    };
+    let read = FramedRead::new(b, MuxCodec::new()).map(Result::unwrap).collect::<Vec<_>>();
+
+    let (_, received) = tokio::join!(write, read);
+    assert_eq!(received, messages());
+}
+
+#[tokio::test]
+async fn frame_size_limit() {
+    let (mut a, b) = tokio::io::duplex(64);
+    a.write_all(&[0, 0, 1, 0, 65, 0, 0, 1]).await.unwrap();
+
+    let config = DecoderConfig { max_frame_size: 16, ..DecoderConfig::default() };
+    let mut framed = FramedRead::new(b, MuxCodec::with_config(config));
+    let err = framed.next().await.unwrap().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+// decode `msg` with a `MuxCodec` enforcing `config`
+async fn decode_with(msg: Message, config: DecoderConfig) -> io::Result<Message> {
+    let (a, b) = tokio::io::duplex(256);
+    Framed::new(a, MuxCodec::new()).send(msg).await.unwrap();
+    FramedRead::new(b, MuxCodec::with_config(config)).next().await.unwrap()
+}
+
+// a Tdispatch with two contexts and dtab entries
+fn tdispatch() -> Message {
+    let mut tdispatch = Tdispatch::new("/foo".to_string(), b"hello".to_vec());
+    tdispatch.contexts.push((b"a".to_vec(), b"1".to_vec()));
+    tdispatch.contexts.push((b"b".to_vec(), b"2".to_vec()));
+    tdispatch.dtab = Dtab::parse("/s=>/a;/t=>/b").unwrap();
+    Message { tag: Tag::new(true, 1), frame: MessageFrame::Tdispatch(tdispatch) }
+}
+
+#[tokio::test]
+async fn decoder_limits() {
+    let config = DecoderConfig { max_contexts: 1, ..DecoderConfig::default() };
+    let err = decode_with(tdispatch(), config).await.unwrap_err();
+    match err.get_ref().and_then(|e| e.downcast_ref::<codec::Error>()) {
+        Some(codec::Error::LimitExceeded { field: "context count", len: 2, max: 1 }) => (),
+        other => panic!("Unexpected error: {:?}", other),
+    }
+
+    let config = DecoderConfig { max_dtab_entries: 1, ..DecoderConfig::default() };
+    let err = decode_with(tdispatch(), config).await.unwrap_err();
+    match err.get_ref().and_then(|e| e.downcast_ref::<codec::Error>()) {
+        Some(codec::Error::LimitExceeded { field: "dtab entry count", len: 2, max: 1 }) => (),
+        other => panic!("Unexpected error: {:?}", other),
+    }
+
+    assert_eq!(decode_with(tdispatch(), DecoderConfig::default()).await.unwrap(), tdispatch());
+}
+
+#[tokio::test]
+async fn reassembles_fragments() {
+    let mut buf = Vec::new();
+    for msg in messages().into_iter().take(2) {
+        for fragment in codec::Fragmenter::new(8).fragment(&msg).unwrap() {
+            codec::write_fragment(&mut buf, &fragment).unwrap();
+        }
+    }
+
+    let (mut a, b) = tokio::io::duplex(64);
+    let write = async move { a.write_all(&buf).await.unwrap() };
+    let read = FramedRead::new(b, MuxCodec::new().reassembling())
+        .map(Result::unwrap)
+        .collect::<Vec<_>>();
+
+    let (_, received) = tokio::join!(write, read);
+    assert_eq!(received, messages().into_iter().take(2).collect::<Vec<_>>());
+}
@@ -0,0 +1,118 @@
+extern crate mux;
+
+use mux::*;
+use mux::client::Session;
+use mux::contexts::{self, TraceId};
+use mux::server::{Server, Service};
+use mux::trace::*;
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+// answers with the contexts the dispatch is handed
+struct Contexts;
+
+impl Service for Contexts {
+    fn dispatch(&self, tdispatch: Tdispatch) -> Rdispatch {
+        Rdispatch { contexts: tdispatch.contexts, msg: Rmsg::Ok(Vec::new()) }
+    }
+}
+
+fn server(tracer: &Arc<BufferingTracer>) -> TcpStream {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Server::new(Contexts).with_tracer(tracer.clone());
+    thread::spawn(move || server.listen(listener));
+    TcpStream::connect(addr).unwrap()
+}
+
+fn annotations(records: &[Record], trace_id: TraceId) -> Vec<Annotation> {
+    records.iter()
+        .filter(|record| record.trace_id == trace_id)
+        .map(|record| record.annotation.clone())
+        .collect()
+}
+
+#[test]
+fn trace_ids() {
+    let root = TraceId::root();
+    assert_eq!(root.parent_id, root.span_id);
+    assert_eq!(root.trace_id, root.span_id);
+    assert_eq!(root.sampled, None);
+
+    let child = TraceId { sampled: Some(true), trace_id_high: Some(7), ..root }.child();
+    assert_eq!(child.parent_id, root.span_id);
+    assert_eq!(child.trace_id, root.trace_id);
+    assert_eq!(child.trace_id_high, Some(7));
+    assert_eq!(child.sampled, Some(true));
+    assert_ne!(child.span_id, root.span_id);
+    assert_ne!(TraceId::root().trace_id, root.trace_id);
+}
+
+#[test]
+fn propagates_spans() {
+    let server_tracer = Arc::new(BufferingTracer::new());
+    let client_tracer = Arc::new(BufferingTracer::new());
+    let stream = server(&server_tracer);
+    let session = Session::new(stream.try_clone().unwrap(), stream).unwrap()
+        .with_tracer(client_tracer.clone());
+
+    let parent = TraceId::root();
+    let mut tdispatch = Tdispatch::new("/users".to_string(), b"a".to_vec());
+    contexts::set(&mut tdispatch.contexts, &parent);
+    let rdispatch = session.dispatch(tdispatch).unwrap();
+
+    // the server hands the client span to the service
+    let span = contexts::get::<TraceId, _>(&rdispatch.contexts).unwrap().unwrap();
+    assert_eq!(span.parent_id, parent.span_id);
+    assert_eq!(span.trace_id, parent.trace_id);
+
+    let records = client_tracer.take();
+    assert_eq!(annotations(&records, span), vec![
+        Annotation::Rpc("/users".to_string()),
+        Annotation::ClientSend,
+        Annotation::ClientRecv,
+    ]);
+    assert!(records[0].timestamp <= records[2].timestamp);
+
+    // the response is written once the span is recorded
+    assert_eq!(annotations(&server_tracer.take(), span), vec![
+        Annotation::Rpc("/users".to_string()),
+        Annotation::ServerRecv,
+        Annotation::ServerSend,
+    ]);
+
+    // dispatches without a span start a trace
+    let rdispatch = session.dispatch(Tdispatch::new("/users".to_string(), b"b".to_vec())).unwrap();
+    let root = contexts::get::<TraceId, _>(&rdispatch.contexts).unwrap().unwrap();
+    assert_eq!(root.parent_id, root.span_id);
+    assert_eq!(annotations(&client_tracer.take(), root).len(), 3);
+}
+
+#[test]
+fn starts_traces_of_untraced_requests() {
+    let server_tracer = Arc::new(BufferingTracer::new());
+    let stream = server(&server_tracer);
+    let session = Session::new(stream.try_clone().unwrap(), stream).unwrap();
+
+    let rdispatch = session.dispatch(Tdispatch::new("/users".to_string(), b"a".to_vec())).unwrap();
+    let root = contexts::get::<TraceId, _>(&rdispatch.contexts).unwrap().unwrap();
+    assert_eq!(annotations(&server_tracer.take(), root).len(), 3);
+}
+
+#[test]
+fn ignores_unsampled_spans() {
+    let server_tracer = Arc::new(BufferingTracer::new());
+    let client_tracer = Arc::new(BufferingTracer::new());
+    let stream = server(&server_tracer);
+    let session = Session::new(stream.try_clone().unwrap(), stream).unwrap()
+        .with_tracer(client_tracer.clone());
+
+    let mut tdispatch = Tdispatch::new("/users".to_string(), b"a".to_vec());
+    contexts::set(&mut tdispatch.contexts, &TraceId { sampled: Some(false), ..TraceId::root() });
+    let rdispatch = session.dispatch(tdispatch).unwrap();
+
+    assert_eq!(contexts::get::<TraceId, _>(&rdispatch.contexts).unwrap().unwrap().sampled, Some(false));
+    assert!(client_tracer.take().is_empty());
+    assert!(server_tracer.take().is_empty());
+}
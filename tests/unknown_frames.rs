@@ -0,0 +1,59 @@
+extern crate mux;
+
+use mux::*;
+use mux::codec::{DecoderConfig, Error, FrameDecoder};
+use std::io;
+
+// a frame of type 80 with tag 3 and a 5 byte body
+const FRAME: [u8; 13] = [0, 0, 0, 9, 80, 0, 0, 3, 1, 2, 3, 4, 5];
+
+fn lenient() -> DecoderConfig {
+    DecoderConfig { allow_unknown_frames: true, ..DecoderConfig::default() }
+}
+
+#[test]
+fn unknown_frame_rejected_by_default() {
+    match codec::read_message(&mut &FRAME[..]) {
+        Err(Error::UnknownFrameType(80)) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    match codec::decode_message_ref(&FRAME[4..]) {
+        Err(Error::UnknownFrameType(80)) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn unknown_frame_roundtrip() {
+    let msg = codec::read_message_with(&mut &FRAME[..], &lenient()).unwrap();
+    assert_eq!(msg.tag, Tag::new(true, 3));
+    match msg.frame {
+        MessageFrame::Unknown { tpe: 80, ref body } => assert_eq!(&body[..], &[1, 2, 3, 4, 5]),
+        ref other => panic!("Unexpected frame: {:?}", other),
+    }
+    assert_eq!(msg.frame.frame_id(), 80);
+    assert_eq!(codec::size::frame_size(&msg.frame), 5);
+
+    let mut w = io::Cursor::new(Vec::new());
+    codec::write_message(&mut w, &msg).unwrap();
+    assert_eq!(w.into_inner(), &FRAME[..]);
+}
+
+#[test]
+fn unknown_frame_ref() {
+    let msg = codec::decode_message_ref_with(&FRAME[4..], &lenient()).unwrap();
+    assert_eq!(msg.frame, MessageFrameRef::Unknown { tpe: 80, body: &FRAME[8..] });
+    assert_eq!(msg.frame.frame_id(), 80);
+
+    let owned = msg.into_owned();
+    assert_eq!(codec::size::frame_size(&owned.frame), 5);
+}
+
+#[test]
+fn unknown_frame_decoder() {
+    let mut decoder = FrameDecoder::with_config(lenient());
+    let msg = decoder.decode(&FRAME).unwrap().unwrap();
+    assert_eq!(msg.frame.frame_id(), 80);
+    assert_eq!(decoder.buffered(), 0);
+}